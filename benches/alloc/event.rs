@@ -60,6 +60,25 @@ pub fn benchmarks<M: Measurement>(c: &mut Criterion<M>, prefix: &str) {
         });
     });
 
+    group.bench_function("event_in_existing_spans", |b| {
+        let dispatch = tracing::Dispatch::new(
+            tracing_subscriber::registry().with(
+                JsonLayer::new(std::io::sink)
+                    .without_time()
+                    .with_target(false),
+            ),
+        );
+        tracing::dispatcher::with_default(&dispatch, || {
+            let outer = tracing::info_span!("outer", req = "r1");
+            let _og = outer.enter();
+            let inner = tracing::info_span!("inner", step = 2u64);
+            let _ig = inner.enter();
+            b.iter(|| {
+                tracing::info!("processing");
+            });
+        });
+    });
+
     group.bench_function("event_escape", |b| {
         let dispatch = tracing::Dispatch::new(
             tracing_subscriber::registry().with(
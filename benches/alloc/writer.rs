@@ -32,6 +32,39 @@ pub fn benchmarks<M: Measurement>(c: &mut Criterion<M>, prefix: &str) {
         });
     });
 
+    // A typical line, written into a buffer that grows from empty and into
+    // one sized up front as the layer does
+    let line = |mut jw: JsonWriter| {
+        jw.obj_start();
+        jw.key("level");
+        jw.val_str("INFO");
+        jw.comma();
+        jw.key("fields");
+        jw.obj_start();
+        jw.key("message");
+        jw.val_str(black_box("request handled"));
+        jw.comma();
+        jw.key("user");
+        jw.val_str(black_box("alice"));
+        jw.comma();
+        jw.key("status");
+        jw.val_u64(black_box(200));
+        jw.obj_end();
+        jw.comma();
+        jw.key("target");
+        jw.val_str("my_app::handlers::requests");
+        jw.obj_end();
+        black_box(jw.into_vec())
+    };
+
+    group.bench_function("line_grow", |b| {
+        b.iter(|| line(JsonWriter::new()));
+    });
+
+    group.bench_function("line_with_capacity", |b| {
+        b.iter(|| line(JsonWriter::with_capacity(256)));
+    });
+
     group.bench_function("timestamp", |b| {
         b.iter(|| {
             let mut buf = String::new();
@@ -0,0 +1,158 @@
+use std::cell::Cell;
+use std::fmt;
+use std::time::Duration;
+
+thread_local! {
+    /// Set while a wrapper type may hand its value to the visitor formatting
+    /// it, rather than printing it.
+    static CAPTURING: Cell<bool> = const { Cell::new(false) };
+    /// The value handed over by the last captured wrapper.
+    static CAPTURED: Cell<Option<Value>> = const { Cell::new(None) };
+    /// The contents of the last captured [`Bytes`] value, kept apart from
+    /// [`CAPTURED`] so the allocation is reused.
+    static CAPTURED_BYTES: Cell<Vec<u8>> = const { Cell::new(Vec::new()) };
+}
+
+/// How [`Bytes`] field values are written.
+///
+/// Set with [`JsonLayer::with_bytes_encoding`](crate::JsonLayer::with_bytes_encoding).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BytesEncoding {
+    /// A standard, padded base64 string: `"aGk="`.
+    #[default]
+    Base64,
+    /// A lowercase hex string: `"6869"`.
+    Hex,
+    /// An array of numbers: `[104,105]`.
+    Array,
+}
+
+/// Marks a byte slice field for [`JsonLayer`](crate::JsonLayer) to write
+/// in the configured [`BytesEncoding`].
+///
+/// `tracing` has no byte-slice field type, so a `&[u8]` recorded with `?`
+/// is written as the string `"[104, 105]"`. Recording it wrapped in `Bytes`
+/// instead writes it as, by default, base64:
+///
+/// ```rust
+/// use tracing_microjson::Bytes;
+///
+/// let payload = b"hi";
+/// tracing::info!(payload = ?Bytes(payload), "received");
+/// // ..."fields":{"payload":"aGk=","message":"received"}...
+/// ```
+///
+/// Only a `Bytes` recorded directly as a field's value is encoded; one
+/// nested inside another `Debug` value, and the output of other layers,
+/// uses its plain `Debug` form, the same as the slice's.
+#[derive(Clone, Copy)]
+pub struct Bytes<'a>(pub &'a [u8]);
+
+impl fmt::Debug for Bytes<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if CAPTURING.replace(false) {
+            let mut captured = CAPTURED_BYTES.take();
+            captured.clear();
+            captured.extend_from_slice(self.0);
+            CAPTURED_BYTES.set(captured);
+            CAPTURED.set(Some(Value::Bytes));
+            return Ok(());
+        }
+        fmt::Debug::fmt(self.0, f)
+    }
+}
+
+/// How [`DurationField`] values are written.
+///
+/// Set with [`JsonLayer::with_duration_format`](crate::JsonLayer::with_duration_format).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DurationFormat {
+    /// Whole nanoseconds as a number: `1500000000`. Durations too long for
+    /// a `u64` (over 584 years) are written as a decimal string instead.
+    #[default]
+    Nanos,
+    /// An object of whole seconds and the remaining nanoseconds, as
+    /// [`Duration`] stores them: `{"secs":1,"nanos":500000000}`.
+    SecsNanos,
+}
+
+/// Marks a [`Duration`] field for [`JsonLayer`](crate::JsonLayer) to write
+/// as a number in the configured [`DurationFormat`].
+///
+/// A `Duration` recorded with `?` is written as its `Debug` string, e.g.
+/// `"1.5s"`, which is hard to aggregate. Wrapped in `DurationField` it is
+/// written, by default, as whole nanoseconds:
+///
+/// ```rust
+/// use std::time::Duration;
+/// use tracing_microjson::DurationField;
+///
+/// let elapsed = Duration::from_millis(1500);
+/// tracing::info!(elapsed = ?DurationField(elapsed), "done");
+/// // ..."fields":{"elapsed":1500000000,"message":"done"}...
+/// ```
+///
+/// As with [`Bytes`], only a wrapper recorded directly as a field's value
+/// is converted. IP and socket addresses need no wrapper: recorded with `%`
+/// or `?`, they are already written as canonical strings such as
+/// `"192.0.2.1"`, `"2001:db8::1"` and `"[::1]:8080"`.
+#[derive(Clone, Copy)]
+pub struct DurationField(pub Duration);
+
+impl fmt::Debug for DurationField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if CAPTURING.replace(false) {
+            CAPTURED.set(Some(Value::Duration(self.0)));
+            return Ok(());
+        }
+        fmt::Debug::fmt(&self.0, f)
+    }
+}
+
+/// Which wrapper was captured; a byte slice's contents are in
+/// [`CAPTURED_BYTES`].
+#[derive(Clone, Copy)]
+enum Value {
+    Bytes,
+    Duration(Duration),
+}
+
+/// Run `format`, letting the first wrapper type it formats capture its
+/// value instead of printing it.
+///
+/// Returns the captured value, if any; the caller decides from what
+/// `format` wrote whether the wrapper was the whole value.
+pub(crate) fn capture(format: impl FnOnce()) -> Option<Captured> {
+    CAPTURED.set(None);
+    CAPTURING.set(true);
+    format();
+    CAPTURING.set(false);
+    Some(match CAPTURED.take()? {
+        Value::Bytes => Captured::Bytes(CapturedBytes(CAPTURED_BYTES.take())),
+        Value::Duration(duration) => Captured::Duration(duration),
+    })
+}
+
+/// A value taken by [`capture`].
+pub(crate) enum Captured {
+    Bytes(CapturedBytes),
+    Duration(Duration),
+}
+
+/// Bytes taken by [`capture`], returned to the thread-local buffer for reuse
+/// when dropped.
+pub(crate) struct CapturedBytes(Vec<u8>);
+
+impl std::ops::Deref for CapturedBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Drop for CapturedBytes {
+    fn drop(&mut self) {
+        CAPTURED_BYTES.set(std::mem::take(&mut self.0));
+    }
+}
@@ -0,0 +1,61 @@
+use crate::writer::{JsonWriter, Key};
+use std::cell::RefCell;
+use std::fmt;
+use std::marker::PhantomData;
+
+thread_local! {
+    static CONTEXT: RefCell<Vec<(Key, String)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Add `key: value` to the current thread's context until the returned guard
+/// is dropped.
+///
+/// Layers built with
+/// [`JsonLayer::with_context_stack`](crate::JsonLayer::with_context_stack)
+/// write every active pair as a top-level string entry on each event from
+/// this thread, in the order they were pushed. Unlike span fields, this
+/// needs no span and is not seen by other layers.
+///
+/// `value` is formatted once, when pushed. Dropping a guard also removes
+/// any pairs pushed after it, so guards should be dropped in reverse order,
+/// as scoped `let` bindings are.
+///
+/// ```rust
+/// let _request = tracing_microjson::push_context("request_id", 42);
+/// tracing::info!("handled"); // ..."request_id":"42"...
+/// ```
+pub fn push_context(key: &'static str, value: impl fmt::Display) -> ContextGuard {
+    CONTEXT.with_borrow_mut(|stack| {
+        stack.push((Key::new(key), value.to_string()));
+        ContextGuard {
+            depth: stack.len() - 1,
+            _not_send: PhantomData,
+        }
+    })
+}
+
+/// Removes a [`push_context`] pair when dropped.
+#[must_use = "the context is removed as soon as the guard is dropped"]
+pub struct ContextGuard {
+    depth: usize,
+    // Context is per-thread, so the guard must be dropped where it was made
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Drop for ContextGuard {
+    fn drop(&mut self) {
+        CONTEXT.with_borrow_mut(|stack| stack.truncate(self.depth));
+    }
+}
+
+/// Write the current thread's context pairs as object entries, each
+/// preceded by a comma.
+pub(crate) fn write_entries(jw: &mut JsonWriter) {
+    CONTEXT.with_borrow(|stack| {
+        for (key, value) in stack {
+            jw.comma();
+            jw.key_from(key);
+            jw.val_str(value);
+        }
+    });
+}
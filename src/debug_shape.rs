@@ -0,0 +1,235 @@
+use crate::writer::JsonWriter;
+use std::cell::Cell;
+use std::fmt::{self, Write as _};
+
+thread_local! {
+    static DEBUG_TEXT: Cell<String> = const { Cell::new(String::new()) };
+    static DECODED: Cell<String> = const { Cell::new(String::new()) };
+}
+
+/// Nesting deeper than this is written as a string, bounding the recursion.
+const MAX_DEPTH: usize = 32;
+
+/// Write `value`'s `Debug` output as JSON if it is made up entirely of
+/// shapes that map onto JSON unambiguously, and as a string otherwise.
+///
+/// Recognized, as `{:?}` writes them:
+///
+/// - `None` as `null`, and `Some(x)` as `x`
+/// - `true`, `false`, and numbers that are valid JSON numbers (not `NaN`
+///   or `inf`)
+/// - string and char literals, unescaped and written as JSON strings
+/// - `[a, b]` as an array
+/// - `{"k": v}` as an object, if every key is a string literal
+///
+/// Anything else anywhere in the output, such as a struct name, a tuple,
+/// or a map with non-string keys, leaves the whole value a string.
+pub(crate) fn write(jw: &mut JsonWriter, value: &dyn fmt::Debug) {
+    DEBUG_TEXT.with(|text_cell| {
+        DECODED.with(|decoded_cell| {
+            let mut text = text_cell.take();
+            text.clear();
+            let _ = write!(text, "{value:?}");
+
+            let start = jw.len();
+            let mut parser = Parser {
+                rest: &text,
+                decoded: decoded_cell.take(),
+            };
+            if !(parser.value(jw, 0) && parser.rest.is_empty()) {
+                jw.truncate(start);
+                jw.val_str(&text);
+            }
+
+            decoded_cell.set(parser.decoded);
+            text_cell.set(text);
+        });
+    });
+}
+
+/// Translates `Debug` output to JSON as it goes. Each method returns
+/// `false` on text it does not recognize, leaving partial output for the
+/// caller to discard.
+struct Parser<'a> {
+    rest: &'a str,
+    /// Scratch space for unescaped string literals.
+    decoded: String,
+}
+
+impl Parser<'_> {
+    fn eat(&mut self, prefix: &str) -> bool {
+        match self.rest.strip_prefix(prefix) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn value(&mut self, jw: &mut JsonWriter, depth: usize) -> bool {
+        if depth > MAX_DEPTH {
+            return false;
+        }
+        if self.eat("None") {
+            jw.val_null();
+            true
+        } else if self.eat("Some(") {
+            self.value(jw, depth + 1) && self.eat(")")
+        } else if self.eat("true") {
+            jw.val_bool(true);
+            true
+        } else if self.eat("false") {
+            jw.val_bool(false);
+            true
+        } else if self.eat("[") {
+            jw.arr_start();
+            if !self.eat("]") {
+                loop {
+                    if !self.value(jw, depth + 1) {
+                        return false;
+                    }
+                    if self.eat("]") {
+                        break;
+                    }
+                    if !self.eat(", ") {
+                        return false;
+                    }
+                    jw.comma();
+                }
+            }
+            jw.arr_end();
+            true
+        } else if self.eat("{") {
+            jw.obj_start();
+            if !self.eat("}") {
+                loop {
+                    if !(self.string(jw) && self.eat(": ")) {
+                        return false;
+                    }
+                    jw.raw(b":");
+                    if !self.value(jw, depth + 1) {
+                        return false;
+                    }
+                    if self.eat("}") {
+                        break;
+                    }
+                    if !self.eat(", ") {
+                        return false;
+                    }
+                    jw.comma();
+                }
+            }
+            jw.obj_end();
+            true
+        } else if self.rest.starts_with('"') {
+            self.string(jw)
+        } else if self.rest.starts_with('\'') {
+            self.char(jw)
+        } else {
+            self.number(jw)
+        }
+    }
+
+    /// A string literal with the escapes `Debug` uses for `str`.
+    fn string(&mut self, jw: &mut JsonWriter) -> bool {
+        if !self.literal('"') {
+            return false;
+        }
+        jw.val_str(&self.decoded);
+        true
+    }
+
+    /// A char literal, written as a one-character string.
+    fn char(&mut self, jw: &mut JsonWriter) -> bool {
+        if !(self.literal('\'') && self.decoded.chars().count() == 1) {
+            return false;
+        }
+        jw.val_str(&self.decoded);
+        true
+    }
+
+    /// Unescape a literal delimited by `quote` into `decoded`.
+    fn literal(&mut self, quote: char) -> bool {
+        let Some(rest) = self.rest.strip_prefix(quote) else {
+            return false;
+        };
+        self.rest = rest;
+        self.decoded.clear();
+        let mut chars = self.rest.char_indices();
+        while let Some((i, c)) = chars.next() {
+            let c = match c {
+                c if c == quote => {
+                    self.rest = &self.rest[i + 1..];
+                    return true;
+                }
+                '\\' => match chars.next() {
+                    Some((_, 'n')) => '\n',
+                    Some((_, 'r')) => '\r',
+                    Some((_, 't')) => '\t',
+                    Some((_, '0')) => '\0',
+                    Some((_, c @ ('\\' | '"' | '\''))) => c,
+                    Some((j, 'u')) => {
+                        // \u{hex}
+                        let Some(braced) = self.rest[j + 1..].strip_prefix('{') else {
+                            return false;
+                        };
+                        let Some(end) = braced.find('}') else {
+                            return false;
+                        };
+                        let Some(c) = u32::from_str_radix(&braced[..end], 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                        else {
+                            return false;
+                        };
+                        // Skip `{`, the digits and `}`
+                        for _ in 0..end + 2 {
+                            chars.next();
+                        }
+                        c
+                    }
+                    _ => return false,
+                },
+                c => c,
+            };
+            self.decoded.push(c);
+        }
+        false
+    }
+
+    /// A number, if it is also valid as a JSON number.
+    fn number(&mut self, jw: &mut JsonWriter) -> bool {
+        let bytes = self.rest.as_bytes();
+        let mut i = usize::from(bytes.first() == Some(&b'-'));
+        let digits = |i: &mut usize| {
+            let start = *i;
+            while bytes.get(*i).is_some_and(u8::is_ascii_digit) {
+                *i += 1;
+            }
+            *i > start
+        };
+        let int_start = i;
+        if !digits(&mut i) || (bytes[int_start] == b'0' && i - int_start > 1) {
+            return false;
+        }
+        if bytes.get(i) == Some(&b'.') {
+            i += 1;
+            if !digits(&mut i) {
+                return false;
+            }
+        }
+        if matches!(bytes.get(i), Some(b'e' | b'E')) {
+            i += 1;
+            if matches!(bytes.get(i), Some(b'+' | b'-')) {
+                i += 1;
+            }
+            if !digits(&mut i) {
+                return false;
+            }
+        }
+        jw.raw(&bytes[..i]);
+        self.rest = &self.rest[i..];
+        true
+    }
+}
@@ -0,0 +1,3142 @@
+//! The [`JsonLayer`] itself, and everything else that needs `std`.
+
+use std::cell::Cell;
+use std::fmt;
+use std::io::Write;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime};
+use tracing_core::span::Id;
+use tracing_core::{Event, Level, LevelFilter, Subscriber};
+use tracing_subscriber::Layer;
+use tracing_subscriber::fmt::format::Writer as FmtWriter;
+use tracing_subscriber::fmt::time::FormatTime;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::{LookupSpan, SpanRef};
+
+use crate::capture::{BytesEncoding, DurationFormat};
+use crate::level::{LevelHandle, LevelNames, LevelStyle};
+use crate::make_writer::{SharedWriter, SingleThreadWriter};
+use crate::metrics::LayerMetrics;
+#[cfg(feature = "opentelemetry")]
+use crate::otel;
+use crate::span::{SpanFields, SpanOpenedAt, SpanShape};
+use crate::visitor::{Entry, FieldConfig, JsonVisitor, MessageVisitor, Select};
+use crate::writer::{JsonWriter, Key, LineEnding, WriterConfig};
+use crate::{context, level};
+
+/// A timestamp formatter that produces RFC 3339 timestamps with microsecond
+/// precision in UTC (e.g. `2026-02-20T12:00:00.000000Z`).
+///
+/// This is the default timer used by [`JsonLayer`]. It uses a hand-written
+/// formatter for minimal overhead — no chrono or time crate required.
+#[derive(Clone, Copy)]
+pub struct SystemTimestamp;
+
+impl FormatTime for SystemTimestamp {
+    fn format_time(&self, w: &mut FmtWriter<'_>) -> std::fmt::Result {
+        write_timestamp(SystemTime::now(), w)
+    }
+}
+
+/// The [`SystemTimestamp`] format, reading the time from a custom clock
+/// instead of [`SystemTime::now`].
+///
+/// Created by [`JsonLayer::with_clock`]; mainly useful for deterministic
+/// output in tests.
+#[derive(Clone)]
+pub struct ClockTimestamp<F> {
+    clock: F,
+}
+
+impl<F> FormatTime for ClockTimestamp<F>
+where
+    F: Fn() -> SystemTime,
+{
+    fn format_time(&self, w: &mut FmtWriter<'_>) -> std::fmt::Result {
+        write_timestamp((self.clock)(), w)
+    }
+}
+
+/// How the `target` field is written.
+///
+/// Set with [`JsonLayer::with_target_transform`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TargetTransform {
+    /// Unchanged: `my_app::Http::server`.
+    #[default]
+    AsIs,
+    /// Lowercased: `my_app::http::server`.
+    Lowercase,
+    /// Only the last `::`-separated segment, as a short logger name:
+    /// `server`.
+    LastSegment,
+}
+
+impl TargetTransform {
+    fn write(self, target: &str, jw: &mut JsonWriter) {
+        match self {
+            Self::AsIs => jw.val_str(target),
+            Self::Lowercase => jw.val_display(&Lowercase(target)),
+            Self::LastSegment => jw.val_str(target.rsplit("::").next().unwrap_or(target)),
+        }
+    }
+}
+
+/// Displays a string lowercased, without allocating a copy.
+struct Lowercase<'a>(&'a str);
+
+impl fmt::Display for Lowercase<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use fmt::Write as _;
+        for c in self.0.chars().flat_map(char::to_lowercase) {
+            f.write_char(c)?;
+        }
+        Ok(())
+    }
+}
+
+thread_local! {
+    static EVENT_BUF: Cell<Vec<u8>> = const { Cell::new(Vec::new()) };
+    static SCOPE_IDS: Cell<Vec<Id>> = const { Cell::new(Vec::new()) };
+    static FIELD_ENTRIES: Cell<Vec<Entry>> = const { Cell::new(Vec::new()) };
+    static FIELD_SCRATCH: Cell<Vec<u8>> = const { Cell::new(Vec::new()) };
+    static LAST_EVENT: Cell<Option<Instant>> = const { Cell::new(None) };
+    static MESSAGE_BUF: Cell<String> = const { Cell::new(String::new()) };
+}
+
+/// A [`tracing_subscriber::Layer`] that formats events as JSON lines.
+///
+/// See the [crate-level docs](crate) for configuration options and output
+/// format details.
+///
+/// A layer is `Clone` when its writer and timer are, so one configured layer
+/// can serve as the base for several subscribers. The default
+/// [`SystemTimestamp`], [`ClockTimestamp`] with a `Clone` clock, and
+/// tracing-subscriber's timers are all `Clone`; a custom timer passed to
+/// [`with_timer`](Self::with_timer) must implement `Clone` too for the layer
+/// to be. Clones share any [`LevelHandle`], and clones of a layer in
+/// [`array_mode`](Self::array_mode) write into the same array.
+///
+/// Writing a line never panics: a line the writer fails to write is dropped,
+/// counted in [`dropped_lines`](Self::dropped_lines), and passed to any
+/// [`with_write_error_handler`](Self::with_write_error_handler) closure.
+/// The only panic is the opt-in one of
+/// [`with_output_validation`](Self::with_output_validation).
+#[derive(Clone)]
+pub struct JsonLayer<W, T = SystemTimestamp> {
+    make_writer: W,
+    timer: T,
+    config: Config,
+}
+
+/// Output options, kept separate from the writer and timer so that builders
+/// which change those type parameters can carry the configuration across.
+#[derive(Clone)]
+struct Config {
+    /// Cleared by [`JsonLayer::from_env`] to omit the timestamp without
+    /// changing the timer type.
+    display_timestamp: bool,
+    timestamp_key: Key,
+    level_key: Key,
+    span_key: Key,
+    spans_key: Key,
+    fields_key: Key,
+    level_names: LevelNames,
+    /// Set by [`LevelStyle::Numeric`]: the level is written as its ordinal
+    /// instead of a name from `level_names`.
+    level_numeric: bool,
+    display_level_ordinal: bool,
+    level_number_key: Option<Key>,
+    /// The number written under `level_number_key` for each level, indexed
+    /// by ordinal.
+    level_numbers: [u64; 5],
+    static_fields: Vec<(Key, &'static str)>,
+    context_stack: bool,
+    field_hook: Option<Arc<FieldHook>>,
+    display_target: bool,
+    target_prefix: String,
+    target_transform: TargetTransform,
+    display_module_path: bool,
+    display_filename: bool,
+    display_line_number: bool,
+    code_location: bool,
+    display_thread_id: bool,
+    thread_id_numeric: bool,
+    display_thread_name: bool,
+    delta_time: bool,
+    pid: Option<u32>,
+    ppid: Option<u32>,
+    host_ip: Option<IpAddr>,
+    #[cfg(feature = "opentelemetry")]
+    display_otel_ids: bool,
+    #[cfg(feature = "opentelemetry")]
+    display_datadog_ids: bool,
+    display_current_span: bool,
+    display_span_list: bool,
+    max_span_depth: Option<usize>,
+    display_span_ids: bool,
+    span_target: bool,
+    span_open_time: bool,
+    span_tree_ids: bool,
+    parent_span: bool,
+    span_shape: SpanShape,
+    span_path: bool,
+    span_path_separator: String,
+    flatten_event: bool,
+    require_message: bool,
+    default_message: Option<String>,
+    message_kv: bool,
+    timestamp_last: bool,
+    line_hash: bool,
+    validate_output: bool,
+    newline_safety: bool,
+    pretty: bool,
+    flush: bool,
+    write_error_handler: Option<Arc<WriteErrorHandler>>,
+    /// Shared by clones, like the array framing.
+    dropped_lines: Arc<AtomicU64>,
+    metrics: Option<Arc<LayerMetrics>>,
+    line_ending: LineEnding,
+    max_line_len: Option<usize>,
+    array: Option<Arc<ArrayFraming>>,
+    buf_hint: usize,
+    buf_cap_limit: usize,
+    level_handle: Option<LevelHandle>,
+    fields: FieldConfig,
+    writer: WriterConfig,
+}
+
+/// The state of [`JsonLayer::array_mode`]: whether the opening `[` has been
+/// written, and how to write the closing `]` when the layer (and every clone
+/// of it) is dropped.
+struct ArrayFraming {
+    /// Held while each event is written, so that the separators are in the
+    /// same order as the lines.
+    started: Mutex<bool>,
+    close: Box<WriteBytes>,
+}
+
+type WriteBytes = dyn Fn(&[u8]) + Send + Sync;
+
+/// The closure set with [`JsonLayer::with_field_hook`].
+type FieldHook = dyn Fn(&mut JsonWriter) + Send + Sync;
+
+/// The closure set with [`JsonLayer::with_write_error_handler`].
+type WriteErrorHandler = dyn Fn(std::io::Error) + Send + Sync;
+
+impl ArrayFraming {
+    /// Start an array whose closing `]` is written through a copy of
+    /// `make_writer`.
+    fn new<W>(make_writer: &W) -> Arc<Self>
+    where
+        W: for<'w> tracing_subscriber::fmt::MakeWriter<'w> + Clone + Send + Sync + 'static,
+    {
+        let make_writer = make_writer.clone();
+        Arc::new(ArrayFraming {
+            started: Mutex::new(false),
+            close: Box::new(move |bytes| {
+                let _ = make_writer.make_writer().write_all(bytes);
+            }),
+        })
+    }
+}
+
+impl Drop for ArrayFraming {
+    fn drop(&mut self) {
+        let started = self.started.get_mut().unwrap_or_else(|e| e.into_inner());
+        (self.close)(if *started { b"]\n" } else { b"[]\n" });
+    }
+}
+
+/// Prints the output options; the writer and timer are left out so that
+/// neither needs to implement `Debug`.
+impl<W, T> fmt::Debug for JsonLayer<W, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let c = &self.config;
+        let mut s = f.debug_struct("JsonLayer");
+        s.field("display_timestamp", &c.display_timestamp)
+            .field("timestamp_key", &c.timestamp_key)
+            .field("level_key", &c.level_key)
+            .field("span_key", &c.span_key)
+            .field("spans_key", &c.spans_key)
+            .field("fields_key", &c.fields_key)
+            .field("level_numeric", &c.level_numeric)
+            .field("display_level_ordinal", &c.display_level_ordinal)
+            .field("level_number_key", &c.level_number_key)
+            .field("level_numbers", &c.level_numbers)
+            .field("context_stack", &c.context_stack)
+            .field("field_hook", &c.field_hook.is_some())
+            .field("display_target", &c.display_target)
+            .field("target_prefix", &c.target_prefix)
+            .field("target_transform", &c.target_transform)
+            .field("display_module_path", &c.display_module_path)
+            .field("display_filename", &c.display_filename)
+            .field("display_line_number", &c.display_line_number)
+            .field("code_location", &c.code_location)
+            .field("display_thread_id", &c.display_thread_id)
+            .field("thread_id_numeric", &c.thread_id_numeric)
+            .field("display_thread_name", &c.display_thread_name)
+            .field("delta_time", &c.delta_time)
+            .field("pid", &c.pid)
+            .field("ppid", &c.ppid)
+            .field("host_ip", &c.host_ip);
+        #[cfg(feature = "opentelemetry")]
+        s.field("display_otel_ids", &c.display_otel_ids)
+            .field("display_datadog_ids", &c.display_datadog_ids);
+        s.field("display_current_span", &c.display_current_span)
+            .field("display_span_list", &c.display_span_list)
+            .field("max_span_depth", &c.max_span_depth)
+            .field("display_span_ids", &c.display_span_ids)
+            .field("span_target", &c.span_target)
+            .field("span_open_time", &c.span_open_time)
+            .field("span_tree_ids", &c.span_tree_ids)
+            .field("parent_span", &c.parent_span)
+            .field("span_shape", &c.span_shape)
+            .field("span_path", &c.span_path)
+            .field("span_path_separator", &c.span_path_separator)
+            .field("flatten_event", &c.flatten_event)
+            .field("message_top_level", &c.fields.message_top_level)
+            .field("hoisted_fields", &c.fields.hoisted)
+            .field("require_message", &c.require_message)
+            .field("default_message", &c.default_message)
+            .field("message_kv", &c.message_kv)
+            .field("timestamp_last", &c.timestamp_last)
+            .field("line_hash", &c.line_hash)
+            .field("validate_output", &c.validate_output)
+            .field("newline_safety", &c.newline_safety)
+            .field("pretty", &c.pretty)
+            .field("flush", &c.flush)
+            .field("write_error_handler", &c.write_error_handler.is_some())
+            .field("dropped_lines", &c.dropped_lines.load(Ordering::Relaxed))
+            .field("metrics", &c.metrics)
+            .field("line_ending", &c.line_ending)
+            .field("max_line_len", &c.max_line_len)
+            .field("array_mode", &c.array.is_some())
+            .field("level_handle", &c.level_handle)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<W, T> JsonLayer<W, T> {
+    const DEFAULT_BUF_CAPACITY: usize = 256;
+    const DEFAULT_BUF_CAP_LIMIT: usize = 4096;
+    /// The ECS version written by [`JsonLayer::ecs`], matching the other
+    /// ecs-logging libraries.
+    const ECS_VERSION: &'static str = "1.6.0";
+}
+
+impl<W> JsonLayer<W>
+where
+    W: for<'w> tracing_subscriber::fmt::MakeWriter<'w> + 'static,
+{
+    /// Create a new `JsonLayer` that writes JSON lines to `make_writer`.
+    ///
+    /// Accepts anything implementing [`tracing_subscriber::fmt::MakeWriter`],
+    /// e.g. `std::io::stderr` or `std::io::stdout`. Writers are obtained with
+    /// [`make_writer_for`](tracing_subscriber::fmt::MakeWriter::make_writer_for),
+    /// so writers that route by level or target (such as those built with
+    /// [`MakeWriterExt`](tracing_subscriber::fmt::writer::MakeWriterExt))
+    /// work as expected.
+    pub fn new(make_writer: W) -> Self {
+        Self {
+            make_writer,
+            timer: SystemTimestamp,
+            config: Config {
+                display_timestamp: true,
+                timestamp_key: Key::new("timestamp"),
+                level_key: Key::new("level"),
+                span_key: Key::new("span"),
+                spans_key: Key::new("spans"),
+                fields_key: Key::new("fields"),
+                level_names: LevelNames::DEFAULT,
+                level_numeric: false,
+                display_level_ordinal: false,
+                level_number_key: None,
+                level_numbers: level::SYSLOG_SEVERITIES,
+                static_fields: Vec::new(),
+                context_stack: false,
+                field_hook: None,
+                display_target: true,
+                target_prefix: String::new(),
+                target_transform: TargetTransform::AsIs,
+                display_module_path: false,
+                display_filename: false,
+                display_line_number: false,
+                code_location: false,
+                display_thread_id: false,
+                thread_id_numeric: false,
+                display_thread_name: false,
+                delta_time: false,
+                pid: None,
+                ppid: None,
+                host_ip: None,
+                #[cfg(feature = "opentelemetry")]
+                display_otel_ids: false,
+                #[cfg(feature = "opentelemetry")]
+                display_datadog_ids: false,
+                display_current_span: true,
+                display_span_list: true,
+                max_span_depth: None,
+                display_span_ids: false,
+                span_target: false,
+                span_open_time: false,
+                span_tree_ids: false,
+                parent_span: false,
+                span_shape: SpanShape::Object,
+                span_path: false,
+                span_path_separator: String::from(">"),
+                flatten_event: false,
+                require_message: false,
+                default_message: None,
+                message_kv: false,
+                timestamp_last: false,
+                line_hash: false,
+                validate_output: false,
+                newline_safety: false,
+                pretty: false,
+                flush: false,
+                write_error_handler: None,
+                dropped_lines: Arc::new(AtomicU64::new(0)),
+                metrics: None,
+                line_ending: LineEnding::Lf,
+                max_line_len: None,
+                array: None,
+                buf_hint: Self::DEFAULT_BUF_CAPACITY,
+                buf_cap_limit: Self::DEFAULT_BUF_CAP_LIMIT,
+                level_handle: None,
+                fields: FieldConfig::default(),
+                writer: WriterConfig::default(),
+            },
+        }
+    }
+
+    /// Create a `JsonLayer` preconfigured for [Google Cloud Logging]'s
+    /// structured logging format.
+    ///
+    /// This is [`new`](Self::new) with:
+    ///
+    /// - the timestamp written under `time`
+    ///   ([`with_timestamp_field_name`](Self::with_timestamp_field_name)),
+    /// - the level written under `severity`
+    ///   ([`with_level_field_name`](Self::with_level_field_name)) using
+    ///   Cloud Logging severity names: `DEBUG` (for both `TRACE` and
+    ///   `DEBUG`), `INFO`, `WARNING` and `ERROR`,
+    /// - event fields, including `message`, written at the top level
+    ///   ([`flatten_event`](Self::flatten_event)).
+    ///
+    /// Each line has the shape:
+    ///
+    /// ```text
+    /// {"time":"2026-02-20T12:00:00.000000Z","severity":"INFO","message":"hello","user":"alice","target":"my_app","span":{"name":"req"},"spans":[{"name":"req"}]}
+    /// ```
+    ///
+    /// All other options keep their defaults and can still be changed.
+    ///
+    /// [Google Cloud Logging]: https://cloud.google.com/logging/docs/structured-logging
+    pub fn gcp(make_writer: W) -> Self {
+        let mut layer = Self::new(make_writer)
+            .with_timestamp_field_name("time")
+            .with_level_field_name("severity")
+            .flatten_event(true);
+        layer.config.level_names = LevelNames::GCP;
+        layer
+    }
+
+    /// Create a `JsonLayer` preconfigured for [Datadog] log ingestion.
+    ///
+    /// This is [`new`](Self::new) with:
+    ///
+    /// - the level written under `status`
+    ///   ([`with_level_field_name`](Self::with_level_field_name)) using
+    ///   Datadog status names: `debug` (for both `TRACE` and `DEBUG`),
+    ///   `info`, `warning` and `error`,
+    /// - event fields, including `message`, written at the top level
+    ///   ([`flatten_event`](Self::flatten_event)),
+    /// - with the `opentelemetry` feature, `dd.trace_id` and `dd.span_id`
+    ///   (`with_datadog_ids`) taken from the
+    ///   OpenTelemetry context that `tracing-opentelemetry` attaches to
+    ///   spans. Without that feature, or outside such a span, they are
+    ///   omitted.
+    ///
+    /// Each line has the shape:
+    ///
+    /// ```text
+    /// {"timestamp":"2026-02-20T12:00:00.000000Z","status":"info","message":"hello","user":"alice","target":"my_app","dd.trace_id":"1234567890","dd.span_id":"987654321","span":{"name":"req"},"spans":[{"name":"req"}]}
+    /// ```
+    ///
+    /// All other options keep their defaults and can still be changed.
+    ///
+    /// [Datadog]: https://docs.datadoghq.com/logs/log_collection/
+    pub fn datadog(make_writer: W) -> Self {
+        let mut layer = Self::new(make_writer)
+            .with_level_field_name("status")
+            .flatten_event(true);
+        layer.config.level_names = LevelNames::DATADOG;
+        #[cfg(feature = "opentelemetry")]
+        {
+            layer.config.display_datadog_ids = true;
+        }
+        layer
+    }
+
+    /// Create a `JsonLayer` preconfigured for the [Elastic Common Schema]
+    /// (ECS), for shipping to Elasticsearch.
+    ///
+    /// This is [`new`](Self::new) with:
+    ///
+    /// - the timestamp written under `@timestamp`,
+    /// - the level written under `log.level` in lowercase (`trace`, `debug`,
+    ///   `info`, `warn`, `error`),
+    /// - a constant `ecs.version` field,
+    /// - event fields, including `message`, written at the top level
+    ///   ([`flatten_event`](Self::flatten_event)).
+    ///
+    /// ECS field names are written as literal dotted keys, which
+    /// Elasticsearch expands into nested fields on ingest:
+    ///
+    /// ```text
+    /// {"@timestamp":"2026-02-20T12:00:00.000000Z","log.level":"info","ecs.version":"1.6.0","message":"hello","target":"my_app"}
+    /// ```
+    ///
+    /// All other options keep their defaults and can still be changed.
+    ///
+    /// [Elastic Common Schema]: https://www.elastic.co/guide/en/ecs/current/index.html
+    pub fn ecs(make_writer: W) -> Self {
+        let mut layer = Self::new(make_writer)
+            .with_timestamp_field_name("@timestamp")
+            .with_level_field_name("log.level")
+            .flatten_event(true);
+        layer.config.level_names = LevelNames::ECS;
+        layer
+            .config
+            .static_fields
+            .push((Key::new("ecs.version"), Self::ECS_VERSION));
+        layer
+    }
+
+    /// Create a `JsonLayer` configured from environment variables, so the
+    /// output can be tuned per deployment without recompiling.
+    ///
+    /// This is [`new`](Self::new) with each of these variables, if set,
+    /// applied through the matching builder:
+    ///
+    /// | Variable | Builder |
+    /// |---|---|
+    /// | `MICROJSON_TIME` | [`without_time`](Self::without_time) when false |
+    /// | `MICROJSON_TARGET` | [`with_target`](Self::with_target) |
+    /// | `MICROJSON_FILE` | [`with_file`](Self::with_file) |
+    /// | `MICROJSON_LINE_NUMBER` | [`with_line_number`](Self::with_line_number) |
+    /// | `MICROJSON_THREAD_IDS` | [`with_thread_ids`](Self::with_thread_ids) |
+    /// | `MICROJSON_THREAD_NAMES` | [`with_thread_names`](Self::with_thread_names) |
+    /// | `MICROJSON_CURRENT_SPAN` | [`with_current_span`](Self::with_current_span) |
+    /// | `MICROJSON_SPAN_LIST` | [`with_span_list`](Self::with_span_list) |
+    /// | `MICROJSON_FLATTEN` | [`flatten_event`](Self::flatten_event) |
+    ///
+    /// Accepted values are exactly `1` or `true`, and `0` or `false`.
+    /// Unset variables, and any other value, leave the default in place.
+    /// The variables are read once, here; builders called afterwards
+    /// override them.
+    ///
+    /// ```rust
+    /// use tracing_microjson::JsonLayer;
+    /// use tracing_subscriber::prelude::*;
+    ///
+    /// // MICROJSON_FLATTEN=1 MICROJSON_TARGET=0 ./my_app
+    /// tracing_subscriber::registry()
+    ///     .with(JsonLayer::from_env(std::io::stderr))
+    ///     .init();
+    /// ```
+    pub fn from_env(make_writer: W) -> Self {
+        let mut layer = Self::new(make_writer);
+        let config = &mut layer.config;
+        for (name, flag) in [
+            ("MICROJSON_TIME", &mut config.display_timestamp),
+            ("MICROJSON_TARGET", &mut config.display_target),
+            ("MICROJSON_FILE", &mut config.display_filename),
+            ("MICROJSON_LINE_NUMBER", &mut config.display_line_number),
+            ("MICROJSON_THREAD_IDS", &mut config.display_thread_id),
+            ("MICROJSON_THREAD_NAMES", &mut config.display_thread_name),
+            ("MICROJSON_CURRENT_SPAN", &mut config.display_current_span),
+            ("MICROJSON_SPAN_LIST", &mut config.display_span_list),
+            ("MICROJSON_FLATTEN", &mut config.flatten_event),
+        ] {
+            match std::env::var(name).as_deref() {
+                Ok("1" | "true") => *flag = true,
+                Ok("0" | "false") => *flag = false,
+                _ => {}
+            }
+        }
+        layer
+    }
+
+    /// Create a `JsonLayer` with the smallest useful output, for when
+    /// throughput matters more than context.
+    ///
+    /// This is [`new`](Self::new) with:
+    ///
+    /// - no timestamp ([`without_time`](Self::without_time)),
+    /// - no `target` ([`with_target`](Self::with_target)).
+    ///
+    /// Event fields stay nested under `"fields"`, and spans are still
+    /// written. Outside spans, each line has the shape:
+    ///
+    /// ```text
+    /// {"level":"INFO","fields":{"message":"hello","user":"alice"}}
+    /// ```
+    ///
+    /// All other options keep their defaults and can still be changed.
+    pub fn compact(make_writer: W) -> JsonLayer<W, ()> {
+        Self::new(make_writer).without_time().with_target(false)
+    }
+
+    /// Create a `JsonLayer` for reading logs during local development,
+    /// still as JSON.
+    ///
+    /// This is [`new`](Self::new) with:
+    ///
+    /// - indented, multi-line output ([`with_pretty`](Self::with_pretty)),
+    /// - event fields at the top level ([`flatten_event`](Self::flatten_event)),
+    /// - lowercase levels (`info`, `warn`, ...),
+    /// - the source `filename` and `line_number`
+    ///   ([`with_file`](Self::with_file),
+    ///   [`with_line_number`](Self::with_line_number)).
+    ///
+    /// Outside spans, each event is written as:
+    ///
+    /// ```text
+    /// {
+    ///   "timestamp": "2026-01-01T00:00:00.000000Z",
+    ///   "level": "info",
+    ///   "message": "hello",
+    ///   "target": "my_app",
+    ///   "filename": "src/main.rs",
+    ///   "line_number": 12
+    /// }
+    /// ```
+    ///
+    /// All other options keep their defaults and can still be changed.
+    pub fn dev(make_writer: W) -> Self {
+        let mut layer = Self::new(make_writer)
+            .with_pretty(true)
+            .flatten_event(true)
+            .with_file(true)
+            .with_line_number(true);
+        layer.config.level_names = LevelNames::LOWERCASE;
+        layer
+    }
+}
+
+impl<W> JsonLayer<SharedWriter<W>>
+where
+    W: Write + Send + 'static,
+{
+    /// Create a new `JsonLayer` that writes JSON lines to a plain
+    /// [`Write`] implementation, without implementing `MakeWriter` for it.
+    ///
+    /// Accepts either an owned writer (e.g. a `BufWriter<File>`) or an
+    /// `Arc<Mutex<W>>` the application keeps a handle to, for example to
+    /// flush it on shutdown. See [`SharedWriter`] for the locking behavior.
+    ///
+    /// ```rust
+    /// # use std::sync::{Arc, Mutex};
+    /// # use tracing_microjson::JsonLayer;
+    /// let file = Arc::new(Mutex::new(Vec::<u8>::new()));
+    /// let layer = JsonLayer::from_writer(Arc::clone(&file));
+    /// ```
+    pub fn from_writer(writer: impl Into<SharedWriter<W>>) -> Self {
+        Self::new(writer.into())
+    }
+}
+
+impl<W> JsonLayer<SingleThreadWriter<W>>
+where
+    W: Write + Send + 'static,
+{
+    /// Create a new `JsonLayer` that writes to `writer` without any locking,
+    /// for programs that only log from a single thread.
+    ///
+    /// The writer is bound to the thread that calls this constructor. Events
+    /// from any other thread are **discarded**; see [`SingleThreadWriter`]
+    /// for details. Prefer [`from_writer`](Self::from_writer) unless the
+    /// per-event lock is measurably significant.
+    pub fn single_threaded(writer: W) -> Self {
+        Self::new(SingleThreadWriter::new(writer))
+    }
+}
+
+impl<W, T> JsonLayer<W, T>
+where
+    W: for<'w> tracing_subscriber::fmt::MakeWriter<'w> + 'static,
+{
+    /// Set the key under which the timestamp is written.
+    ///
+    /// Default: **`"timestamp"`**.
+    pub fn with_timestamp_field_name(mut self, name: impl Into<String>) -> Self {
+        self.config.timestamp_key = Key::new(&name.into());
+        self
+    }
+
+    /// Set the key under which the level is written.
+    ///
+    /// Default: **`"level"`**.
+    pub fn with_level_field_name(mut self, name: impl Into<String>) -> Self {
+        self.config.level_key = Key::new(&name.into());
+        self
+    }
+
+    /// Set how the level is written: as tracing's name, in lowercase, as a
+    /// number, or as a single letter.
+    ///
+    /// Replaces any level names set by a preset such as
+    /// [`gcp`](Self::gcp).
+    ///
+    /// Default: [`LevelStyle::Name`].
+    pub fn with_level_style(mut self, style: LevelStyle) -> Self {
+        self.config.level_names = match style {
+            LevelStyle::Name | LevelStyle::Numeric => LevelNames::DEFAULT,
+            LevelStyle::Lowercase => LevelNames::LOWERCASE,
+            LevelStyle::ShortUpper => LevelNames::SHORT_UPPER,
+        };
+        self.config.level_numeric = style == LevelStyle::Numeric;
+        self
+    }
+
+    /// Write the level as the label `map` returns for it, for backends that
+    /// expect names of their own.
+    ///
+    /// `map` is called once for each level when this is set, not per event.
+    ///
+    /// ```rust
+    /// use tracing_microjson::{JsonLayer, Level};
+    ///
+    /// let layer = JsonLayer::new(std::io::stderr).with_level_map(|level| match level {
+    ///     Level::WARN => "warning",
+    ///     Level::ERROR => "critical",
+    ///     _ => level.as_str(),
+    /// });
+    /// ```
+    ///
+    /// Like [`with_level_style`](Self::with_level_style), replaces any level
+    /// names set by a preset.
+    ///
+    /// Default: tracing's names (`"INFO"`, ...).
+    pub fn with_level_map(mut self, map: impl Fn(Level) -> &'static str) -> Self {
+        self.config.level_names = LevelNames::from_fn(map);
+        self.config.level_numeric = false;
+        self
+    }
+
+    /// Set whether the `level_ord` field is included in output.
+    ///
+    /// This is tracing's own ordering of the level as a number, from `TRACE`
+    /// = 0 to `ERROR` = 4, allowing cheap numeric comparisons downstream
+    /// (e.g. `level_ord >= 3` for warnings and errors).
+    ///
+    /// Default: **`false`**.
+    pub fn with_level_ordinal(mut self, display_level_ordinal: bool) -> Self {
+        self.config.display_level_ordinal = display_level_ordinal;
+        self
+    }
+
+    /// Also write the level as a number under `name`, right after the
+    /// level itself, e.g. `{"level":"INFO","level_num":6}`.
+    ///
+    /// The numbers are syslog severities unless changed with
+    /// [`with_level_numbers`](Self::with_level_numbers): 7 for `TRACE` and
+    /// `DEBUG`, 6 for `INFO`, 4 for `WARN` and 3 for `ERROR`. The level
+    /// itself is still written as set by
+    /// [`with_level_style`](Self::with_level_style) or
+    /// [`with_level_map`](Self::with_level_map).
+    ///
+    /// Default: not written.
+    pub fn with_level_number_field(mut self, name: impl Into<String>) -> Self {
+        self.config.level_number_key = Some(Key::new(&name.into()));
+        self
+    }
+
+    /// Set the number written for each level by
+    /// [`with_level_number_field`](Self::with_level_number_field), for a
+    /// scale other than syslog's, such as OpenTelemetry severity numbers:
+    ///
+    /// ```rust
+    /// use tracing_microjson::{JsonLayer, Level};
+    ///
+    /// let layer = JsonLayer::new(std::io::stderr)
+    ///     .with_level_number_field("severity_number")
+    ///     .with_level_numbers(|level| match level {
+    ///         Level::TRACE => 1,
+    ///         Level::DEBUG => 5,
+    ///         Level::INFO => 9,
+    ///         Level::WARN => 13,
+    ///         Level::ERROR => 17,
+    ///     });
+    /// ```
+    ///
+    /// `numbers` is called once per level when the layer is configured.
+    ///
+    /// Default: syslog severities.
+    pub fn with_level_numbers(mut self, numbers: impl Fn(Level) -> u64) -> Self {
+        self.config.level_numbers = [
+            Level::TRACE,
+            Level::DEBUG,
+            Level::INFO,
+            Level::WARN,
+            Level::ERROR,
+        ]
+        .map(numbers);
+        self
+    }
+
+    /// Set whether the pairs added with
+    /// [`push_context`](crate::push_context) on the logging thread are
+    /// written on each event, as top-level string entries after the level.
+    ///
+    /// Default: **`false`**.
+    pub fn with_context_stack(mut self, context_stack: bool) -> Self {
+        self.config.context_stack = context_stack;
+        self
+    }
+
+    /// Call `hook` on every event to write extra top-level entries, e.g.
+    /// request-scoped metadata kept in a thread-local.
+    ///
+    /// The hook is called after the level and any
+    /// [`with_context_stack`](Self::with_context_stack) pairs, before the
+    /// event fields. It writes `"key":value` entries through the
+    /// [`JsonWriter`] methods; a comma is written before its first entry,
+    /// and it must separate any further entries with
+    /// [`comma`](JsonWriter::comma). Writing nothing is fine.
+    ///
+    /// ```rust
+    /// use tracing_microjson::JsonLayer;
+    ///
+    /// let layer = JsonLayer::new(std::io::stderr).with_field_hook(|jw| {
+    ///     jw.key("tenant");
+    ///     jw.val_str("acme");
+    ///     jw.comma();
+    ///     jw.key("shard");
+    ///     jw.val_u64(3);
+    /// });
+    /// ```
+    ///
+    /// The hook runs while the line is being built, so it must not log.
+    ///
+    /// Default: none.
+    pub fn with_field_hook<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&mut JsonWriter) + Send + Sync + 'static,
+    {
+        self.config.field_hook = Some(Arc::new(hook));
+        self
+    }
+
+    /// Set whether the `target` field (module path) is included in output.
+    ///
+    /// Default: **`true`**.
+    pub fn with_target(mut self, display_target: bool) -> Self {
+        self.config.display_target = display_target;
+        self
+    }
+
+    /// Strip `prefix` from the start of the `target` field.
+    ///
+    /// For targets that all share a redundant leading path, e.g. with
+    /// `"mycompany::"` the target `mycompany::billing::invoice` is written
+    /// as `billing::invoice`. Targets that do not start with the prefix, or
+    /// that consist of nothing else, are written unchanged.
+    ///
+    /// Default: none.
+    pub fn with_target_prefix_strip(mut self, prefix: impl Into<String>) -> Self {
+        self.config.target_prefix = prefix.into();
+        self
+    }
+
+    /// Set how the `target` field is written: unchanged, lowercased, or
+    /// only its last `::`-separated segment.
+    ///
+    /// Applied after [`with_target_prefix_strip`](Self::with_target_prefix_strip).
+    ///
+    /// Default: [`TargetTransform::AsIs`].
+    pub fn with_target_transform(mut self, transform: TargetTransform) -> Self {
+        self.config.target_transform = transform;
+        self
+    }
+
+    /// Set whether the `module_path` field is included in output.
+    ///
+    /// This is the Rust module the event was emitted from. It usually equals
+    /// the `target`, but still identifies the call site when the target was
+    /// overridden (e.g. `info!(target: "audit", ...)`). Omitted for events
+    /// without a module path.
+    ///
+    /// Default: **`false`**.
+    pub fn with_module_path(mut self, display_module_path: bool) -> Self {
+        self.config.display_module_path = display_module_path;
+        self
+    }
+
+    /// Set whether the `filename` field is included in output.
+    ///
+    /// Default: **`false`**.
+    pub fn with_file(mut self, display_filename: bool) -> Self {
+        self.config.display_filename = display_filename;
+        self
+    }
+
+    /// Set whether the `line_number` field is included in output.
+    ///
+    /// Default: **`false`**.
+    pub fn with_line_number(mut self, display_line: bool) -> Self {
+        self.config.display_line_number = display_line;
+        self
+    }
+
+    /// Set whether all source-location fields are included in output:
+    /// `module_path`, `filename` and `line_number`.
+    ///
+    /// Shorthand for calling [`with_module_path`](Self::with_module_path),
+    /// [`with_file`](Self::with_file) and
+    /// [`with_line_number`](Self::with_line_number) with the same value.
+    /// Any of them can still be changed afterwards, e.g.
+    /// `.with_location(true).with_module_path(false)`.
+    pub fn with_location(self, display_location: bool) -> Self {
+        self.with_module_path(display_location)
+            .with_file(display_location)
+            .with_line_number(display_location)
+    }
+
+    /// Set whether the source file and line are included as one
+    /// `"location"` string, such as `"src/main.rs:42"`, which log viewers
+    /// and terminals often turn into a link.
+    ///
+    /// Independent of [`with_file`](Self::with_file) and
+    /// [`with_line_number`](Self::with_line_number), which write them as
+    /// separate fields. The line is left off for events without one, and
+    /// the field is omitted for events without a file.
+    ///
+    /// Default: **`false`**.
+    pub fn with_code_location(mut self, code_location: bool) -> Self {
+        self.config.code_location = code_location;
+        self
+    }
+
+    /// Set whether the `threadId` field is included in output.
+    ///
+    /// Default: **`false`**.
+    pub fn with_thread_ids(mut self, display_thread_id: bool) -> Self {
+        self.config.display_thread_id = display_thread_id;
+        self
+    }
+
+    /// Set whether `threadId` is written as a plain number (`2`) rather than
+    /// the `Debug` form of [`ThreadId`](std::thread::ThreadId)
+    /// (`"ThreadId(2)"`). Passing `true` also enables the `threadId` field.
+    ///
+    /// `ThreadId` does not expose its value on stable Rust, so the number is
+    /// parsed from the digits of its `Debug` output. Should that output ever
+    /// stop containing a number, the `Debug` string is written instead.
+    ///
+    /// Default: **`false`**.
+    pub fn with_thread_ids_numeric(mut self, numeric: bool) -> Self {
+        self.config.thread_id_numeric = numeric;
+        self.config.display_thread_id |= numeric;
+        self
+    }
+
+    /// Set whether the `threadName` field is included in output.
+    ///
+    /// Default: **`false`**.
+    pub fn with_thread_names(mut self, display_thread_name: bool) -> Self {
+        self.config.display_thread_name = display_thread_name;
+        self
+    }
+
+    /// Set whether the `delta_ms` field is included in output: the time in
+    /// milliseconds since the previous event written on the same thread,
+    /// or 0 for a thread's first event.
+    ///
+    /// A lightweight profiling aid. The value is a float with microsecond
+    /// resolution. The previous-event time is tracked per thread, not per
+    /// layer, so enable this on only one layer of a subscriber.
+    ///
+    /// Default: **`false`**.
+    pub fn with_delta_time(mut self, delta_time: bool) -> Self {
+        self.config.delta_time = delta_time;
+        self
+    }
+
+    /// Set whether the `pid` field (the current process ID) is included in
+    /// output.
+    ///
+    /// The ID is read once, when this method is called.
+    ///
+    /// Default: **`false`**.
+    pub fn with_pid(mut self, display_pid: bool) -> Self {
+        self.config.pid = display_pid.then(std::process::id);
+        self
+    }
+
+    /// Set whether the `ppid` field (the parent process ID) is included in
+    /// output.
+    ///
+    /// The ID is read once, when this method is called. On platforms without
+    /// a parent process ID (non-Unix), the field is always omitted.
+    ///
+    /// Default: **`false`**.
+    pub fn with_ppid(mut self, display_ppid: bool) -> Self {
+        self.config.ppid = if display_ppid { parent_id() } else { None };
+        self
+    }
+
+    /// Set whether the `host_ip` field (this machine's primary non-loopback
+    /// IP address) is included in output.
+    ///
+    /// The address is detected once, when this method is called, by
+    /// connecting an unbound UDP socket to a public address and reading back
+    /// the local address the OS chose for the route. Connecting a UDP socket
+    /// sends no packets. If no route exists, or only a loopback address is
+    /// found, the field is omitted. Use
+    /// [`with_host_ip_value`](Self::with_host_ip_value) to set it explicitly.
+    ///
+    /// Requires the `host-ip` feature. Default: **`false`**.
+    #[cfg(feature = "host-ip")]
+    pub fn with_host_ip(mut self, display_host_ip: bool) -> Self {
+        self.config.host_ip = if display_host_ip {
+            detect_host_ip()
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Write `addr` as the `host_ip` field of every line.
+    ///
+    /// For hosts where the address is known from configuration, or where
+    /// auto-detection picks the wrong interface.
+    pub fn with_host_ip_value(mut self, addr: impl Into<IpAddr>) -> Self {
+        self.config.host_ip = Some(addr.into());
+        self
+    }
+
+    /// Set whether OpenTelemetry `trace_id` and `span_id` are written, for
+    /// correlating log lines with traces.
+    ///
+    /// The ids are taken from the innermost span in the event's scope that
+    /// has an OpenTelemetry context attached by
+    /// [`tracing-opentelemetry`](https://docs.rs/tracing-opentelemetry)'s
+    /// layer, which must be installed in the same subscriber. They are
+    /// written as lowercase hex strings (32 and 16 digits, as in W3C trace
+    /// context) and omitted for events outside any such span.
+    ///
+    /// Requires the `opentelemetry` feature. Default: **`false`**.
+    #[cfg(feature = "opentelemetry")]
+    pub fn with_otel_ids(mut self, display_otel_ids: bool) -> Self {
+        self.config.display_otel_ids = display_otel_ids;
+        self
+    }
+
+    /// Set whether the OpenTelemetry ids are written in Datadog's format,
+    /// as `dd.trace_id` and `dd.span_id`, for Datadog's log and trace
+    /// correlation.
+    ///
+    /// The ids come from the same span as with
+    /// [`with_otel_ids`](Self::with_otel_ids), and are likewise omitted for
+    /// events outside any span with an OpenTelemetry context. They are
+    /// written as decimal strings: the lower 64 bits of the trace id, which
+    /// is what Datadog keeps of a 128-bit id, and the span id.
+    ///
+    /// Requires the `opentelemetry` feature. Default: **`false`**.
+    #[cfg(feature = "opentelemetry")]
+    pub fn with_datadog_ids(mut self, display_datadog_ids: bool) -> Self {
+        self.config.display_datadog_ids = display_datadog_ids;
+        self
+    }
+
+    /// Set whether the `span` field (the innermost active span) is included
+    /// in output.
+    ///
+    /// Default: **`true`**.
+    pub fn with_current_span(mut self, display_current_span: bool) -> Self {
+        self.config.display_current_span = display_current_span;
+        self
+    }
+
+    /// Set whether the `spans` field (all active spans, root to leaf) is
+    /// included in output.
+    ///
+    /// Default: **`true`**.
+    pub fn with_span_list(mut self, display_span_list: bool) -> Self {
+        self.config.display_span_list = display_span_list;
+        self
+    }
+
+    /// Limit the `spans` list to the innermost `max` spans, dropping their
+    /// outer ancestors, to bound the length of lines from deeply nested
+    /// spans. The list keeps its root-to-leaf order.
+    ///
+    /// The `span` field, the innermost span, is always written regardless.
+    /// Deep nesting is otherwise only a matter of line length: the spans
+    /// are walked iteratively, without recursion.
+    ///
+    /// Default: no limit.
+    pub fn with_max_span_depth(mut self, max: usize) -> Self {
+        self.config.max_span_depth = Some(max);
+        self
+    }
+
+    /// Set the key under which the innermost span is written.
+    ///
+    /// Default: **`"span"`**.
+    pub fn with_span_field_name(mut self, name: impl Into<String>) -> Self {
+        self.config.span_key = Key::new(&name.into());
+        self
+    }
+
+    /// Set the key under which the list of active spans is written.
+    ///
+    /// Default: **`"spans"`**.
+    pub fn with_spans_field_name(mut self, name: impl Into<String>) -> Self {
+        self.config.spans_key = Key::new(&name.into());
+        self
+    }
+
+    /// Set whether each span object includes its numeric `id`.
+    ///
+    /// The ID is the one assigned by the subscriber (see
+    /// [`Id::into_u64`](tracing_core::span::Id::into_u64)) and is useful for
+    /// correlating spans with external systems.
+    ///
+    /// Default: **`false`**.
+    pub fn with_span_ids(mut self, display_span_ids: bool) -> Self {
+        self.config.display_span_ids = display_span_ids;
+        self
+    }
+
+    /// Set whether each span object includes the span's own `target`.
+    ///
+    /// Spans created by a library carry the library's target, which may
+    /// differ from the target of the event written inside them. The target
+    /// is written as recorded, after the `id` and before the span's fields;
+    /// [`with_target_prefix_strip`](Self::with_target_prefix_strip) and
+    /// [`with_target_transform`](Self::with_target_transform) only apply to
+    /// the event's `target`.
+    ///
+    /// Default: **`false`**.
+    pub fn with_span_target(mut self, span_target: bool) -> Self {
+        self.config.span_target = span_target;
+        self
+    }
+
+    /// Set whether each span object includes `opened_at`, the time the span
+    /// was created, e.g. to measure how long work waited in a queue.
+    ///
+    /// The time is formatted by the layer's timer, like the event
+    /// timestamp, when the span is created, and written after the span's
+    /// `id` and `target`. It is omitted when the timer writes nothing, as
+    /// with [`without_time`](Self::without_time). Enabling this formats
+    /// and stores a timestamp for every new span.
+    ///
+    /// Default: **`false`**.
+    pub fn with_span_open_time(mut self, span_open_time: bool) -> Self {
+        self.config.span_open_time = span_open_time;
+        self
+    }
+
+    /// Set whether events inside a span include top-level `span_id` and
+    /// `parent_span_id` fields: the numeric ids of the innermost span and of
+    /// its parent.
+    ///
+    /// Together they let the span tree be rebuilt offline from the log
+    /// alone. `parent_span_id` is omitted for root spans. Ids are the same
+    /// as those written by [`with_span_ids`](Self::with_span_ids). The
+    /// `span_id` key is shared with the OpenTelemetry ids, so enable only
+    /// one of the two.
+    ///
+    /// Default: **`false`**.
+    pub fn with_span_tree_ids(mut self, span_tree_ids: bool) -> Self {
+        self.config.span_tree_ids = span_tree_ids;
+        self
+    }
+
+    /// Set whether events inside a nested span include a top-level
+    /// `parent_span` field: the name of the innermost span's parent.
+    ///
+    /// Gives one level of context without the full
+    /// [`spans`](Self::with_span_list) list. Omitted when the innermost span
+    /// has no parent.
+    ///
+    /// Default: **`false`**.
+    pub fn with_parent_span(mut self, parent_span: bool) -> Self {
+        self.config.parent_span = parent_span;
+        self
+    }
+
+    /// Set whether events inside spans include a `span_path` field: the
+    /// names of all active spans, root to leaf, joined with the
+    /// [separator](Self::with_span_path_separator), e.g. `"outer>inner"`.
+    ///
+    /// A breadcrumb for viewers that show one string better than the
+    /// [`spans`](Self::with_span_list) list. A `\` or the separator inside
+    /// a span name is escaped with a preceding `\`, so the path can be
+    /// split unambiguously.
+    ///
+    /// Default: **`false`**.
+    pub fn with_span_path(mut self, span_path: bool) -> Self {
+        self.config.span_path = span_path;
+        self
+    }
+
+    /// Set the separator between span names in the
+    /// [`span_path`](Self::with_span_path) field.
+    ///
+    /// Default: **`">"`**.
+    pub fn with_span_path_separator(mut self, separator: impl Into<String>) -> Self {
+        self.config.span_path_separator = separator.into();
+        self
+    }
+
+    /// Set the shape of each element of the `"spans"` array: a full object
+    /// (the default), just the span name, or a `[name, fields]` pair.
+    ///
+    /// See [`SpanShape`] for examples of each.
+    ///
+    /// Default: **[`SpanShape::Object`]**.
+    pub fn with_span_element_shape(mut self, shape: SpanShape) -> Self {
+        self.config.span_shape = shape;
+        self
+    }
+
+    /// Set whether event fields are flattened to the top level of the JSON
+    /// object instead of being nested under a `"fields"` key.
+    ///
+    /// Default: **`false`** (fields are nested).
+    pub fn flatten_event(mut self, flatten: bool) -> Self {
+        self.config.flatten_event = flatten;
+        self
+    }
+
+    /// Set whether the `message` is written at the top level of the JSON
+    /// object while the other event fields stay nested under `"fields"`.
+    ///
+    /// ```text
+    /// {"level":"INFO","message":"hello","fields":{"user":"alice"}}
+    /// ```
+    ///
+    /// Has no effect with [`flatten_event`](Self::flatten_event), which
+    /// already puts every field at the top level.
+    ///
+    /// Default: **`false`**.
+    pub fn with_message_top_level(mut self, message_top_level: bool) -> Self {
+        self.config.fields.message_top_level = message_top_level;
+        self
+    }
+
+    /// Write the named event fields at the top level of the JSON object,
+    /// just before `"fields"`, while the others stay nested under it.
+    ///
+    /// ```text
+    /// {"level":"INFO","request_id":"abc","fields":{"message":"hello","user":"alice"}}
+    /// ```
+    ///
+    /// For fields that identify a line, such as a request id, and that log
+    /// backends should index without knowing the nesting. Combines with
+    /// [`with_message_top_level`](Self::with_message_top_level); like it,
+    /// has no effect with [`flatten_event`](Self::flatten_event). Span
+    /// fields of the same names stay in their spans.
+    ///
+    /// Default: none.
+    pub fn with_hoisted_fields<I>(mut self, names: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.config.fields.hoisted = names.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Set the key under which event fields are nested, e.g. `"attributes"`
+    /// or `"data"`.
+    ///
+    /// Has no effect with [`flatten_event`](Self::flatten_event), which
+    /// writes no such key.
+    ///
+    /// Default: **`"fields"`**.
+    pub fn with_fields_field_name(mut self, name: impl Into<String>) -> Self {
+        self.config.fields_key = Key::new(&name.into());
+        self
+    }
+
+    /// Set whether the `timestamp` field is written last instead of first.
+    ///
+    /// Placing the volatile timestamp at the end keeps the start of each line
+    /// stable, which can improve compression and suits parsers that expect
+    /// `level` to be the first key.
+    ///
+    /// Default: **`false`** (timestamp first).
+    pub fn with_timestamp_last(mut self, last: bool) -> Self {
+        self.config.timestamp_last = last;
+        self
+    }
+
+    /// Set whether each line ends with a `"hash"` of its own content, for
+    /// tamper evidence.
+    ///
+    /// The hash is the 64-bit [FNV-1a] hash of every byte of the line up to,
+    /// but not including, the `,"hash":` entry, written as 16 lowercase hex
+    /// digits. It is always the last key. To verify a line, strip the
+    /// trailing `,"hash":"…"}` and hash what remains:
+    ///
+    /// ```text
+    /// {"level":"INFO","fields":{"message":"hi"},"hash":"8ec72997025e21a5"}
+    /// └─────────────── hashed ────────────────┘
+    /// ```
+    ///
+    /// The hash is taken over the line as written, after any
+    /// [`with_newline_safety`](Self::with_newline_safety) repair, and is
+    /// never shortened by [`with_max_field_len`](Self::with_max_field_len).
+    /// A line replaced for exceeding
+    /// [`with_max_line_len`](Self::with_max_line_len) has no hash.
+    ///
+    /// FNV-1a detects accidental corruption and casual edits; it is not a
+    /// cryptographic MAC and does not stop a determined attacker.
+    ///
+    /// Default: **`false`**.
+    ///
+    /// [FNV-1a]: https://datatracker.ietf.org/doc/html/draft-eastlake-fnv
+    pub fn with_line_hash(mut self, line_hash: bool) -> Self {
+        self.config.line_hash = line_hash;
+        self
+    }
+
+    /// Set whether each line is checked for balanced quotes, braces and
+    /// brackets before it is written, panicking if it is not.
+    ///
+    /// Intended for tests and development, to catch a custom [`FormatTime`]
+    /// or other extension producing malformed output. The check is a single
+    /// pass over the line, not a full JSON parse, so it finds structural
+    /// breakage such as an unescaped quote but not, e.g., a missing comma.
+    ///
+    /// Default: **`false`**.
+    pub fn with_output_validation(mut self, validate: bool) -> Self {
+        self.config.validate_output = validate;
+        self
+    }
+
+    /// Set whether raw newlines in the finished line are repaired before it
+    /// is written, so that each event stays on exactly one line.
+    ///
+    /// Values are always escaped, but a custom [`FormatTime`] writes into
+    /// the line unescaped, and a raw newline there would split the line in
+    /// two. With this enabled, raw `\n` and `\r` bytes inside strings are
+    /// escaped and any outside strings are removed. The check runs after
+    /// everything but [`with_line_hash`](Self::with_line_hash), whose hash
+    /// covers the repaired line, and [`with_pretty`](Self::with_pretty).
+    /// Lines without raw newlines are unchanged.
+    ///
+    /// Default: **`false`**.
+    pub fn with_newline_safety(mut self, newline_safety: bool) -> Self {
+        self.config.newline_safety = newline_safety;
+        self
+    }
+
+    /// Set whether each event is written as indented, multi-line JSON, for
+    /// reading logs locally:
+    ///
+    /// ```text
+    /// {
+    ///   "level": "INFO",
+    ///   "fields": {
+    ///     "message": "hello"
+    ///   }
+    /// }
+    /// ```
+    ///
+    /// The output is no longer one JSON value per line, so tools that read
+    /// JSON lines cannot parse it; a streaming JSON parser still can. The
+    /// line is reformatted once it is otherwise finished, so
+    /// [`with_max_line_len`](Self::with_max_line_len) and
+    /// [`with_line_hash`](Self::with_line_hash) apply to the compact form.
+    ///
+    /// Default: **`false`**.
+    pub fn with_pretty(mut self, pretty: bool) -> Self {
+        self.config.pretty = pretty;
+        self
+    }
+
+    /// Set whether the writer is flushed after each line is written.
+    ///
+    /// For writers that buffer internally, such as a [`BufWriter`] around a
+    /// file, so that the last lines before a crash are not lost. This costs
+    /// a flush, typically a system call, per event.
+    ///
+    /// Default: **`false`**.
+    ///
+    /// [`BufWriter`]: std::io::BufWriter
+    pub fn with_flush(mut self, flush: bool) -> Self {
+        self.config.flush = flush;
+        self
+    }
+
+    /// Call `handler` with the error whenever a line cannot be written,
+    /// e.g. because the disk is full.
+    ///
+    /// The line is dropped either way; the handler is only told about it.
+    /// Like [`with_field_hook`](Self::with_field_hook), it runs inside the
+    /// layer and must not log.
+    ///
+    /// ```rust
+    /// use tracing_microjson::JsonLayer;
+    ///
+    /// let layer = JsonLayer::new(std::io::stderr).with_write_error_handler(|err| {
+    ///     eprintln!("log line dropped: {err}");
+    /// });
+    /// ```
+    ///
+    /// Default: none.
+    pub fn with_write_error_handler<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(std::io::Error) + Send + Sync + 'static,
+    {
+        self.config.write_error_handler = Some(Arc::new(handler));
+        self
+    }
+
+    /// The number of lines dropped so far because the writer returned an
+    /// error, counted across all clones of this layer.
+    ///
+    /// Errors from flushing, with [`with_flush`](Self::with_flush), are not
+    /// counted, as the line may still have been written.
+    pub fn dropped_lines(&self) -> u64 {
+        self.config.dropped_lines.load(Ordering::Relaxed)
+    }
+
+    /// Count the lines written at each level, and the bytes written, in
+    /// `metrics`, which can be read while the layer is in use:
+    ///
+    /// ```rust
+    /// use std::sync::Arc;
+    /// use tracing_microjson::{JsonLayer, LayerMetrics};
+    ///
+    /// let metrics = Arc::new(LayerMetrics::new());
+    /// let layer = JsonLayer::new(std::io::stderr).with_metrics(metrics.clone());
+    /// // ...install the layer, then, e.g. from a metrics endpoint:
+    /// let snapshot = metrics.snapshot();
+    /// println!("{} errors, {} bytes", snapshot.error, snapshot.bytes);
+    /// ```
+    ///
+    /// Lines the writer fails to write are not counted; see
+    /// [`dropped_lines`](Self::dropped_lines) for those.
+    ///
+    /// Default: not counted.
+    pub fn with_metrics(mut self, metrics: Arc<LayerMetrics>) -> Self {
+        self.config.metrics = Some(metrics);
+        self
+    }
+
+    /// Format just the fields of `event` as a JSON object, such as
+    /// `{"message":"login","user":"alice"}`, with no timestamp, level,
+    /// target or spans.
+    ///
+    /// The object is what this layer writes under `"fields"`, so the field
+    /// options apply: redaction, skipped and renamed fields, value limits
+    /// and a default message. Useful for reusing the field formatting in
+    /// another [`Layer`], such as one writing an audit log.
+    pub fn record_fields_to_string(&self, event: &Event<'_>) -> String {
+        let mut jw = JsonWriter::new().with_config(self.config.writer);
+        write_fields_object(
+            &mut jw,
+            event,
+            &self.config.fields,
+            default_message(event, &self.config),
+            Select::All,
+        );
+        String::from_utf8(jw.into_vec()).expect("JsonWriter output is UTF-8")
+    }
+
+    /// Set the terminator written after each line: `\n`, `\r\n`, or
+    /// nothing.
+    ///
+    /// With [`LineEnding::None`], lines are written back to back, for
+    /// transports that frame each write themselves. The closing `]` of
+    /// [`array_mode`](Self::array_mode) is always followed by `\n`.
+    ///
+    /// Default: [`LineEnding::Lf`].
+    pub fn with_line_ending(mut self, line_ending: LineEnding) -> Self {
+        self.config.line_ending = line_ending;
+        self
+    }
+
+    /// Set the maximum length of a line in bytes, including the line
+    /// ending.
+    ///
+    /// A line that would be longer is replaced by one that keeps only the
+    /// timestamp and level and adds `"truncated":true`:
+    ///
+    /// ```text
+    /// {"timestamp":"2024-01-01T00:00:00.000000Z","level":"INFO","truncated":true}
+    /// ```
+    ///
+    /// With [`with_timestamp_last`](Self::with_timestamp_last) the timestamp
+    /// comes after the level instead. Every other entry, including the
+    /// [`with_line_hash`](Self::with_line_hash) hash, is dropped. The
+    /// replacement line is written even if it is itself over the limit.
+    ///
+    /// To shorten long values rather than drop the event's content, see
+    /// [`with_max_field_len`](Self::with_max_field_len).
+    ///
+    /// Default: no limit.
+    pub fn with_max_line_len(mut self, max: usize) -> Self {
+        self.config.max_line_len = Some(max);
+        self
+    }
+
+    /// Set whether events are written as the elements of one JSON array
+    /// instead of as separate JSON lines, for tools that read a single JSON
+    /// document.
+    ///
+    /// Each event is still on its own line; the first is preceded by `[`,
+    /// the rest by `,`, and the closing `]` is written when the layer is
+    /// dropped (or `[]` if there were no events):
+    ///
+    /// ```text
+    /// [{"level":"INFO","fields":{"message":"one"}}
+    /// ,{"level":"INFO","fields":{"message":"two"}}
+    /// ]
+    /// ```
+    ///
+    /// The layer is dropped along with its subscriber, so the array is only
+    /// closed for subscribers that are themselves dropped, e.g. ones
+    /// installed with `tracing::subscriber::set_default`
+    /// rather than globally. A global subscriber is never dropped, leaving
+    /// the array unterminated. There is no explicit `finish()`, because the
+    /// layer is not reachable once it is part of a subscriber.
+    ///
+    /// Writing an event holds a lock shared by all threads, so that
+    /// separators stay in order. The writer must be `Clone`, as the closing
+    /// `]` is written through a copy of it.
+    ///
+    /// Default: **`false`**.
+    pub fn array_mode(mut self, array_mode: bool) -> Self
+    where
+        W: Clone + Send + Sync,
+    {
+        self.config.array = array_mode.then(|| ArrayFraming::new(&self.make_writer));
+        self
+    }
+
+    /// Set whether `key=value` pairs in the event message are also written
+    /// as a `"message_fields"` object, for messages that embed structured
+    /// data, such as `"user=5 action=login"`.
+    ///
+    /// The `message` itself is written unchanged. Parsing is deliberately
+    /// conservative: the message is split on single spaces, and a part is
+    /// taken as a pair only if it contains exactly one `=` with a non-empty
+    /// key made of ASCII letters, digits, `_`, `.` and `-` on the left and a
+    /// non-empty value on the right. Quoting is not understood, and every
+    /// value is written as a string. `message_fields` is omitted when no
+    /// pairs are found, and written after the event fields.
+    ///
+    /// This formats the message a second time, so it adds some cost per
+    /// event.
+    ///
+    /// Default: **`false`**.
+    pub fn with_message_kv_parsing(mut self, parse: bool) -> Self {
+        self.config.message_kv = parse;
+        self
+    }
+
+    /// Set whether only the last of several event fields with the same name
+    /// is written.
+    ///
+    /// An event can record the same field name more than once (e.g. with
+    /// `x = 1, x = 2`, or a hand-written [`Visit`](tracing_core::field::Visit)
+    /// caller), which produces a JSON object with duplicate keys. That is
+    /// valid JSON, but strict parsers reject it. When enabled, earlier
+    /// occurrences are dropped and the last value wins, in its own position.
+    /// Lines without duplicates are written unchanged.
+    ///
+    /// Default: **`false`**.
+    pub fn with_dedup_fields(mut self, dedup: bool) -> Self {
+        self.config.fields.dedup = dedup;
+        self
+    }
+
+    /// Set whether event fields are written in alphabetical order of their
+    /// names, regardless of the order they were recorded in.
+    ///
+    /// Useful for golden-file tests and diffing. Applies to event fields
+    /// only (including `message`); span fields keep their declaration order.
+    ///
+    /// Fields are still written directly into the line buffer; the offsets
+    /// of each entry are tracked and, if they are out of order, the entries
+    /// are copied once more into place. The buffers involved are reused per
+    /// thread, so this costs a copy of the fields but no allocation.
+    ///
+    /// Default: **`false`** (recording order).
+    pub fn with_sorted_fields(mut self, sorted: bool) -> Self {
+        self.config.fields.sorted = sorted;
+        self
+    }
+
+    /// Set whether each field value is wrapped in an object naming its type,
+    /// for schema-less consumers.
+    ///
+    /// Each field is written as `{"type":"u64","value":42}` instead of `42`.
+    /// The type is the [`Visit`](tracing_core::field::Visit) method the value
+    /// was recorded with: `str`, `u64`, `i64`, `u128`, `i128`, `f64`, `bool`,
+    /// `debug` or `error`. This applies to event and span fields alike,
+    /// except `message`, which is always written as a bare string.
+    ///
+    /// Default: **`false`**.
+    pub fn with_typed_fields(mut self, typed: bool) -> Self {
+        self.config.fields.typed = typed;
+        self
+    }
+
+    /// Limit how many string-valued and numeric fields are written per event
+    /// (and per span), to defend against pathological events.
+    ///
+    /// Strings are fields recorded as `str`, `Debug` or errors; numbers are
+    /// integers and floats. Booleans and the `message` are never limited.
+    /// Fields beyond a limit are dropped, and the number dropped is recorded
+    /// as `"dropped_string_fields"` / `"dropped_number_fields"` at the end
+    /// of the same object. Pass `usize::MAX` to leave a kind unlimited.
+    ///
+    /// Default: unlimited.
+    pub fn with_per_type_field_limits(mut self, strings: usize, numbers: usize) -> Self {
+        self.config.fields.string_limit = Some(strings);
+        self.config.fields.number_limit = Some(numbers);
+        self
+    }
+
+    /// Truncate string values longer than `max` bytes, appending `…` (see
+    /// [`with_truncation_marker`](Self::with_truncation_marker)).
+    ///
+    /// Applies to every string value written, including `Debug` and
+    /// `Display` output, which is cut off while it is being formatted rather
+    /// than after. The limit counts bytes of the value before JSON escaping,
+    /// and the cut is moved back to a character boundary so the output stays
+    /// valid UTF-8. Keys and the timestamp are not truncated.
+    ///
+    /// Default: no limit.
+    pub fn with_max_field_len(mut self, max: usize) -> Self {
+        self.config.writer.max_str_len = Some(max);
+        self
+    }
+
+    /// Set the text appended to string values shortened by
+    /// [`with_max_field_len`](Self::with_max_field_len), e.g. `"...(truncated)"`
+    /// or `""` for none. It is escaped like the value and does not count
+    /// towards the limit.
+    ///
+    /// Default: **`"…"`**.
+    pub fn with_truncation_marker(mut self, marker: &'static str) -> Self {
+        self.config.writer.truncation_marker = marker;
+        self
+    }
+
+    /// Set whether U+2028 (line separator) and U+2029 (paragraph
+    /// separator) in string values are escaped as `\u2028` and `\u2029`.
+    ///
+    /// Both are valid unescaped in JSON, but not in JavaScript string
+    /// literals before ES2019, so output that may be evaluated as JavaScript
+    /// needs them escaped. Parsed values are unchanged either way.
+    ///
+    /// Default: **`false`**.
+    pub fn with_js_safe_escape(mut self, js_safe: bool) -> Self {
+        self.config.writer.escape.js_separators = js_safe;
+        self
+    }
+
+    /// Set whether `/` in string values is escaped as `\/`.
+    ///
+    /// For JSON embedded in an HTML `<script>` element, where an unescaped
+    /// `</script>` in a value would end the element early. Parsed values
+    /// are unchanged either way.
+    ///
+    /// Default: **`false`**.
+    pub fn with_escape_forward_slash(mut self, escape: bool) -> Self {
+        self.config.writer.escape.forward_slash = escape;
+        self
+    }
+
+    /// Set whether boolean fields with a `false` value are omitted.
+    ///
+    /// Useful for flag-heavy events where only the flags that are set are of
+    /// interest. Fields recorded as `true` are always written.
+    ///
+    /// Default: **`false`** (all booleans are written).
+    pub fn skip_false_bools(mut self, skip: bool) -> Self {
+        self.config.fields.skip_false_bools = skip;
+        self
+    }
+
+    /// Set whether events without a `message` are skipped entirely.
+    ///
+    /// Field-only events such as `info!(x = 1)`, often emitted by
+    /// dependencies, are then not written. Whether an event has a message is
+    /// known from its callsite, so skipped events cost no formatting.
+    ///
+    /// Default: **`false`**.
+    pub fn with_require_message(mut self, require: bool) -> Self {
+        self.config.require_message = require;
+        self
+    }
+
+    /// Write `message` as the `message` of events that have none, for
+    /// consumers that require every line to have one.
+    ///
+    /// The fallback is written wherever the message would be, before the
+    /// event's other fields. It is not applied to events skipped by
+    /// [`with_require_message`](Self::with_require_message), which takes
+    /// precedence.
+    ///
+    /// Default: none (events without a message are written without one).
+    pub fn with_default_message(mut self, message: impl Into<String>) -> Self {
+        self.config.default_message = Some(message.into());
+        self
+    }
+
+    /// Redact the values of fields with any of the given names.
+    ///
+    /// Matching event and span fields are still written, but with the value
+    /// `"[REDACTED]"` in place of what was recorded.
+    ///
+    /// Default: no fields are redacted.
+    pub fn with_redacted_fields<I>(mut self, names: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.config.fields.redacted = names.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Write the values of fields with any of the given names as embedded
+    /// JSON rather than as strings.
+    ///
+    /// For values that are already serialized JSON, e.g. a payload received
+    /// from another service. The value, recorded as a `&str` or with `%`, is
+    /// written **verbatim**, unquoted and unescaped:
+    ///
+    /// ```rust
+    /// # use tracing_microjson::JsonLayer;
+    /// # use tracing_subscriber::prelude::*;
+    /// # let layer = JsonLayer::new(std::io::stderr).with_raw_json_fields(["payload"]);
+    /// # let _guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(layer));
+    /// let payload = r#"{"a":1}"#;
+    /// tracing::info!(payload, "received");
+    /// // ..."fields":{"payload":{"a":1},"message":"received"}...
+    /// ```
+    ///
+    /// The value is trusted: it is not checked, and anything that is not a
+    /// single valid JSON value (including an empty string, or text from an
+    /// untrusted source) makes the whole line invalid or lets it inject
+    /// extra keys. Only use this for values your own code produced or has
+    /// already parsed. Don't record the value with `?`: that writes its
+    /// `Debug` text verbatim, which for a string is quoted and escaped with
+    /// Rust's rules, so at best the JSON arrives as a string rather than
+    /// embedded. Other value types, and redaction, are unaffected;
+    /// [`with_max_field_len`](Self::with_max_field_len) does not apply.
+    ///
+    /// Default: none.
+    pub fn with_raw_json_fields<I>(mut self, names: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.config.fields.raw_json = names.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Set how [`Bytes`](crate::Bytes) field values are written.
+    ///
+    /// ```rust
+    /// # use tracing_microjson::{Bytes, BytesEncoding, JsonLayer};
+    /// # use tracing_subscriber::prelude::*;
+    /// # let layer = JsonLayer::new(std::io::stderr).with_bytes_encoding(BytesEncoding::Hex);
+    /// # let _guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(layer));
+    /// tracing::info!(digest = ?Bytes(&[0xca, 0xfe]), "hashed");
+    /// // ..."fields":{"digest":"cafe","message":"hashed"}...
+    /// ```
+    ///
+    /// Applies to event and span fields. With typed fields, the type is
+    /// `"bytes"`.
+    ///
+    /// Default: [`BytesEncoding::Base64`].
+    pub fn with_bytes_encoding(mut self, encoding: BytesEncoding) -> Self {
+        self.config.fields.bytes_encoding = encoding;
+        self
+    }
+
+    /// Set how [`DurationField`](crate::DurationField) values are written:
+    /// as whole nanoseconds, or as a `{"secs":…,"nanos":…}` object.
+    ///
+    /// Applies to event and span fields. With typed fields, the type is
+    /// `"duration"`.
+    ///
+    /// Default: [`DurationFormat::Nanos`].
+    pub fn with_duration_format(mut self, format: DurationFormat) -> Self {
+        self.config.fields.duration_format = format;
+        self
+    }
+
+    /// Set whether error fields include their full `source()` chain.
+    ///
+    /// When enabled, a field recorded as a `dyn Error` is written as an array
+    /// of messages, outermost first (e.g. `["outer","inner","root cause"]`).
+    /// When disabled, only the outermost error's `Display` output is written.
+    ///
+    /// Default: **`false`**.
+    pub fn with_error_chain(mut self, error_chain: bool) -> Self {
+        self.config.fields.error_chain = error_chain;
+        self
+    }
+
+    /// Set whether `Debug` field values are written as JSON when their
+    /// output has a shape that maps onto JSON unambiguously.
+    ///
+    /// Values recorded with `?`, such as `?Some(5)` or `?vec![1, 2]`, are
+    /// otherwise written as strings (`"Some(5)"`, `"[1, 2]"`). With this
+    /// enabled, the `Debug` output is parsed and written as JSON if it is
+    /// made up entirely of:
+    ///
+    /// - `None`, written as `null`, and `Some(x)`, written as `x`
+    /// - `true` and `false`
+    /// - numbers that are valid in JSON (so not `NaN` or `inf`)
+    /// - string literals, written without their `Debug` quotes and escapes
+    /// - char literals, such as `?'a'`, written as one-character strings
+    ///   (`"a"` rather than `"'a'"`)
+    /// - lists, `[a, b]`, written as arrays
+    /// - maps with string keys, `{"k": v}`, written as objects
+    ///
+    /// Anything else, anywhere in the value, leaves it a string: structs
+    /// and enum variants other than `Some` (their output starts with a
+    /// name), tuples, sets, and maps with non-string keys. Custom
+    /// `Debug` implementations that happen to print one of these shapes are
+    /// converted too, since only the text is seen. So are values recorded
+    /// with `%`, which reach the layer the same way, formatted with their
+    /// `Display` implementation. The `message` is always a string.
+    ///
+    /// Parsing costs a second pass over the `Debug` output of every such
+    /// field, so this is off by default.
+    ///
+    /// Default: **`false`**.
+    pub fn with_smart_debug(mut self, smart_debug: bool) -> Self {
+        self.config.fields.smart_debug = smart_debug;
+        self
+    }
+
+    /// Set whether non-finite floats are written as strings.
+    ///
+    /// JSON has no representation for NaN or infinity, so by default they are
+    /// written as `null`. When enabled they are written as the strings
+    /// `"NaN"`, `"Infinity"` and `"-Infinity"` instead, preserving which value
+    /// was recorded.
+    ///
+    /// Default: **`false`** (written as `null`).
+    pub fn with_non_finite_as_string(mut self, as_string: bool) -> Self {
+        self.config.writer.non_finite_as_string = as_string;
+        self
+    }
+
+    /// Set the capacity the per-thread formatting buffer is given up front.
+    ///
+    /// The buffer is allocated with this capacity for a thread's first
+    /// event, instead of growing from empty through several reallocations.
+    /// Set it to about the length of a typical line. It is also the size the
+    /// buffer is shrunk back to (see
+    /// [`with_buffer_capacity_limit`](Self::with_buffer_capacity_limit)).
+    ///
+    /// Default: **256** bytes.
+    pub fn with_buffer_hint(mut self, hint: usize) -> Self {
+        self.config.buf_hint = hint;
+        self
+    }
+
+    /// Set the capacity threshold at which the per-thread formatting buffer
+    /// is shrunk back to its default size after each event.
+    ///
+    /// The formatting buffer is reused across events on the same thread to
+    /// avoid allocations. If an unusually large event grows the buffer beyond
+    /// this limit, it is shrunk back to the
+    /// [`with_buffer_hint`](Self::with_buffer_hint) size (256 bytes by
+    /// default) after that event to reclaim memory.
+    ///
+    /// Default: **4096** bytes.
+    pub fn with_buffer_capacity_limit(mut self, limit: usize) -> Self {
+        self.config.buf_cap_limit = limit;
+        self
+    }
+
+    /// Return a [`LevelHandle`] that can change which levels this layer
+    /// writes while the program is running.
+    ///
+    /// The layer starts out writing every level; call
+    /// [`LevelHandle::set_level`] to restrict it. The level is checked for
+    /// each event this layer receives, so it only affects this layer's
+    /// output: other layers in the same subscriber still see every event.
+    ///
+    /// ```rust
+    /// # use tracing_microjson::{JsonLayer, LevelFilter};
+    /// let (layer, handle) = JsonLayer::new(std::io::stderr).with_level_handle();
+    /// handle.set_level(LevelFilter::WARN);
+    /// ```
+    pub fn with_level_handle(mut self) -> (Self, LevelHandle) {
+        let handle = LevelHandle::new(LevelFilter::TRACE);
+        self.config.level_handle = Some(handle.clone());
+        (self, handle)
+    }
+
+    /// Replace the writer, keeping the rest of the configuration.
+    ///
+    /// Since the writer is part of the layer's type, this makes it possible
+    /// to configure a layer first and choose where it writes afterwards:
+    ///
+    /// ```rust
+    /// use tracing_microjson::JsonLayer;
+    ///
+    /// let layer = JsonLayer::new(std::io::stderr).flatten_event(true);
+    /// // ...later, once the destination is known
+    /// let layer = layer.with_writer(std::io::stdout);
+    /// ```
+    ///
+    /// With [`array_mode`](Self::array_mode) enabled, the array is started
+    /// afresh in the new writer, which is why it must be `Clone` like the
+    /// one passed to `array_mode`.
+    pub fn with_writer<W2>(mut self, make_writer: W2) -> JsonLayer<W2, T>
+    where
+        W2: for<'w> tracing_subscriber::fmt::MakeWriter<'w> + Clone + Send + Sync + 'static,
+    {
+        if let Some(mut array) = self.config.array.take() {
+            // Nothing was written through the old writer by this layer, so
+            // it must not be closed either, unless a clone still uses it
+            if let Some(array) = Arc::get_mut(&mut array) {
+                array.close = Box::new(|_| {});
+            }
+            self.config.array = Some(ArrayFraming::new(&make_writer));
+        }
+        JsonLayer {
+            make_writer,
+            timer: self.timer,
+            config: self.config,
+        }
+    }
+
+    /// Use a custom [`FormatTime`] implementation for timestamps.
+    ///
+    /// This replaces the default [`SystemTimestamp`] formatter. Any type
+    /// implementing [`FormatTime`] can be used, including those from
+    /// `tracing-subscriber` such as `Uptime` and `ChronoUtc`.
+    ///
+    /// Pass `()` to disable timestamps entirely (equivalent to
+    /// [`without_time`](Self::without_time)).
+    pub fn with_timer<T2: FormatTime>(self, timer: T2) -> JsonLayer<W, T2> {
+        JsonLayer {
+            make_writer: self.make_writer,
+            timer,
+            config: self.config,
+        }
+    }
+
+    /// Read the current time from `clock` instead of [`SystemTime::now`],
+    /// keeping the default RFC 3339 timestamp format.
+    ///
+    /// This makes the default timestamp output deterministic in tests
+    /// without writing a custom [`FormatTime`]:
+    ///
+    /// ```rust
+    /// # use std::time::{Duration, SystemTime};
+    /// # use tracing_microjson::JsonLayer;
+    /// let layer = JsonLayer::new(std::io::stderr)
+    ///     .with_clock(|| SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+    /// ```
+    pub fn with_clock<F>(self, clock: F) -> JsonLayer<W, ClockTimestamp<F>>
+    where
+        F: Fn() -> SystemTime + Send + Sync + 'static,
+    {
+        self.with_timer(ClockTimestamp { clock })
+    }
+
+    /// Disable timestamps in the output.
+    ///
+    /// This is a convenience for `self.with_timer(())`.
+    pub fn without_time(self) -> JsonLayer<W, ()> {
+        self.with_timer(())
+    }
+}
+
+impl<W, T: FormatTime> JsonLayer<W, T> {
+    /// Write the `"timestamp"` entry, preceded by a comma if `leading_comma`.
+    ///
+    /// Returns whether anything was written: the entry is omitted entirely
+    /// when the timer produces no output (e.g. `()` / `without_time()`).
+    /// The value is written directly into the JsonWriter via fmt::Write to
+    /// avoid a temporary String allocation. It is NOT JSON-escaped;
+    /// FormatTime implementations are expected to produce only printable
+    /// ASCII (digits, dashes, colons, etc.).
+    fn write_timestamp_entry(&self, jw: &mut JsonWriter, leading_comma: bool) -> bool {
+        if !self.config.display_timestamp {
+            return false;
+        }
+        let rollback = jw.len();
+        if leading_comma {
+            jw.comma();
+        }
+        jw.key_from(&self.config.timestamp_key);
+        jw.push_byte(b'"');
+        let val_start = jw.len();
+        {
+            let mut fw = FmtWriter::new(jw);
+            let _ = self.timer.format_time(&mut fw);
+        }
+        if jw.len() > val_start {
+            jw.push_byte(b'"');
+            true
+        } else {
+            jw.truncate(rollback);
+            false
+        }
+    }
+}
+
+impl<S, W, T> Layer<S> for JsonLayer<W, T>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> tracing_subscriber::fmt::MakeWriter<'w> + 'static,
+    T: FormatTime + 'static,
+{
+    fn on_new_span(
+        &self,
+        attrs: &tracing_core::span::Attributes<'_>,
+        id: &tracing_core::span::Id,
+        ctx: Context<'_, S>,
+    ) {
+        let span = match ctx.span(id) {
+            Some(s) => s,
+            None => return,
+        };
+        let mut fields = SpanFields::default();
+        attrs.record(&mut fields);
+        let mut ext = span.extensions_mut();
+        ext.insert(fields);
+        if self.config.span_open_time {
+            let mut opened_at = String::new();
+            let _ = self.timer.format_time(&mut FmtWriter::new(&mut opened_at));
+            if !opened_at.is_empty() {
+                ext.insert(SpanOpenedAt(opened_at));
+            }
+        }
+    }
+
+    fn on_record(
+        &self,
+        id: &tracing_core::span::Id,
+        values: &tracing_core::span::Record<'_>,
+        ctx: Context<'_, S>,
+    ) {
+        let span = match ctx.span(id) {
+            Some(s) => s,
+            None => return,
+        };
+        let mut ext = span.extensions_mut();
+        if let Some(fields) = ext.get_mut::<SpanFields>() {
+            values.record(fields);
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        if let Some(handle) = &self.config.level_handle
+            && !handle.enabled(event.metadata().level())
+        {
+            return;
+        }
+
+        if self.config.require_message && event.metadata().fields().field("message").is_none() {
+            return;
+        }
+
+        EVENT_BUF.with(|cell| {
+            let mut buf = cell.take();
+            buf.clear();
+            // Only allocates for a thread's first event
+            buf.reserve(self.config.buf_hint);
+            let mut jw = JsonWriter::from_vec(buf).with_config(self.config.writer);
+
+            jw.obj_start();
+
+            // Timestamp first unless configured to go last
+            let wrote_timestamp =
+                !self.config.timestamp_last && self.write_timestamp_entry(&mut jw, false);
+
+            // level
+            if wrote_timestamp {
+                jw.comma();
+            }
+            jw.key_from(&self.config.level_key);
+            if self.config.level_numeric {
+                jw.val_u64(level::ordinal(event.metadata().level()));
+            } else {
+                jw.val_str(self.config.level_names.get(event.metadata().level()));
+            }
+            let level_end = jw.len();
+
+            if let Some(key) = &self.config.level_number_key {
+                let ordinal = level::ordinal(event.metadata().level());
+                jw.comma();
+                jw.key_from(key);
+                jw.val_u64(self.config.level_numbers[ordinal as usize]);
+            }
+
+            if self.config.display_level_ordinal {
+                jw.comma();
+                jw.key("level_ord");
+                jw.val_u64(level::ordinal(event.metadata().level()));
+            }
+
+            for (key, value) in &self.config.static_fields {
+                jw.comma();
+                jw.key_from(key);
+                jw.val_str(value);
+            }
+
+            if self.config.context_stack {
+                context::write_entries(&mut jw);
+            }
+
+            if let Some(hook) = &self.config.field_hook {
+                jw.comma();
+                let start = jw.len();
+                hook(&mut jw);
+                if jw.len() == start {
+                    // Nothing written: drop the comma
+                    jw.truncate(start - 1);
+                }
+            }
+
+            let default_message = default_message(event, &self.config);
+
+            if self.config.flatten_event {
+                // Event fields flattened to top level
+                if let Some(message) = default_message {
+                    jw.comma();
+                    jw.key("message");
+                    jw.val_str(message);
+                }
+                record_event_fields(&mut jw, event, &self.config.fields, true, Select::All);
+            } else {
+                let fields = &self.config.fields;
+                let select = if fields.message_top_level || !fields.hoisted.is_empty() {
+                    if let Some(message) = default_message.filter(|_| fields.message_top_level) {
+                        jw.comma();
+                        jw.key("message");
+                        jw.val_str(message);
+                    }
+                    record_event_fields(&mut jw, event, fields, true, Select::TopLevel);
+                    Select::Nested
+                } else {
+                    Select::All
+                };
+                // Event fields nested under "fields"
+                jw.comma();
+                jw.key_from(&self.config.fields_key);
+                write_fields_object(
+                    &mut jw,
+                    event,
+                    &self.config.fields,
+                    default_message.filter(|_| !self.config.fields.message_top_level),
+                    select,
+                );
+            }
+
+            if self.config.message_kv {
+                MESSAGE_BUF.with(|cell| {
+                    let mut message = cell.take();
+                    message.clear();
+                    event.record(&mut MessageVisitor(&mut message));
+                    write_message_fields(&mut jw, &message);
+                    cell.set(message);
+                });
+            }
+
+            // target
+            if self.config.display_target {
+                jw.comma();
+                jw.key("target");
+                let target = event.metadata().target();
+                let target = match target.strip_prefix(&*self.config.target_prefix) {
+                    Some(stripped) if !stripped.is_empty() => stripped,
+                    _ => target,
+                };
+                self.config.target_transform.write(target, &mut jw);
+            }
+
+            // module_path
+            if self.config.display_module_path
+                && let Some(module_path) = event.metadata().module_path()
+            {
+                jw.comma();
+                jw.key("module_path");
+                jw.val_str(module_path);
+            }
+
+            // filename
+            if self.config.display_filename
+                && let Some(file) = event.metadata().file()
+            {
+                jw.comma();
+                jw.key("filename");
+                jw.val_str(file);
+            }
+
+            // line_number
+            if self.config.display_line_number
+                && let Some(line) = event.metadata().line()
+            {
+                jw.comma();
+                jw.key("line_number");
+                jw.val_u64(line as u64);
+            }
+
+            // file and line as one string
+            if self.config.code_location
+                && let Some(file) = event.metadata().file()
+            {
+                jw.comma();
+                jw.key("location");
+                match event.metadata().line() {
+                    Some(line) => jw.val_display(&format_args!("{file}:{line}")),
+                    None => jw.val_str(file),
+                }
+            }
+
+            if self.config.delta_time {
+                let now = Instant::now();
+                let delta = LAST_EVENT
+                    .replace(Some(now))
+                    .map_or(0.0, |last| (now - last).as_secs_f64() * 1000.0);
+                jw.comma();
+                jw.key("delta_ms");
+                jw.val_f64((delta * 1000.0).round() / 1000.0);
+            }
+
+            // process IDs
+            if let Some(pid) = self.config.pid {
+                jw.comma();
+                jw.key("pid");
+                jw.val_u64(pid.into());
+            }
+            if let Some(ppid) = self.config.ppid {
+                jw.comma();
+                jw.key("ppid");
+                jw.val_u64(ppid.into());
+            }
+
+            if let Some(ip) = &self.config.host_ip {
+                jw.comma();
+                jw.key("host_ip");
+                jw.val_display(ip);
+            }
+
+            #[cfg(feature = "opentelemetry")]
+            if self.config.display_otel_ids {
+                otel::write_ids(&mut jw, event, &ctx);
+            }
+            #[cfg(feature = "opentelemetry")]
+            if self.config.display_datadog_ids {
+                otel::write_datadog_ids(&mut jw, event, &ctx);
+            }
+
+            if self.config.span_tree_ids
+                && let Some(leaf) = ctx.event_scope(event).and_then(|mut scope| scope.next())
+            {
+                jw.comma();
+                jw.key("span_id");
+                jw.val_u64(leaf.id().into_u64());
+                if let Some(parent) = leaf.parent() {
+                    jw.comma();
+                    jw.key("parent_span_id");
+                    jw.val_u64(parent.id().into_u64());
+                }
+            }
+
+            if self.config.parent_span
+                && let Some(parent) = ctx.event_scope(event).and_then(|mut scope| scope.nth(1))
+            {
+                jw.comma();
+                jw.key("parent_span");
+                jw.val_str(parent.name());
+            }
+
+            // current span and spans list
+            if (self.config.display_current_span
+                || self.config.display_span_list
+                || self.config.span_path)
+                && let Some(scope) = ctx.event_scope(event)
+            {
+                // Collect ids rather than `SpanRef`s so the buffer can be
+                // reused across events; each id is resolved again below.
+                let mut ids = SCOPE_IDS.take();
+                ids.clear();
+                ids.extend(scope.map(|span| span.id()));
+
+                // "span" = innermost (first in iterator = closest to current)
+                if self.config.display_current_span
+                    && let Some(leaf) = ids.first().and_then(|id| ctx.span(id))
+                {
+                    jw.comma();
+                    jw.key_from(&self.config.span_key);
+                    write_span(&mut jw, &leaf, &self.config);
+                }
+
+                // "spans" = all spans from root to leaf
+                if self.config.display_span_list {
+                    jw.comma();
+                    jw.key_from(&self.config.spans_key);
+                    jw.arr_start();
+                    let mut first = true;
+                    let depth = self.config.max_span_depth.unwrap_or(usize::MAX);
+                    for span in ids.iter().take(depth).rev().filter_map(|id| ctx.span(id)) {
+                        if !first {
+                            jw.comma();
+                        }
+                        first = false;
+                        write_span_element(&mut jw, &span, &self.config);
+                    }
+                    jw.arr_end();
+                }
+
+                if self.config.span_path {
+                    jw.comma();
+                    jw.key("span_path");
+                    jw.val_display(&SpanPath {
+                        ids: &ids,
+                        ctx: &ctx,
+                        separator: &self.config.span_path_separator,
+                    });
+                }
+
+                SCOPE_IDS.set(ids);
+            }
+
+            // thread name and ID, after the spans as tracing-subscriber
+            // writes them
+            if self.config.display_thread_name {
+                jw.comma();
+                jw.key("threadName");
+                if let Some(name) = std::thread::current().name() {
+                    jw.val_str(name);
+                } else {
+                    jw.val_str("");
+                }
+            }
+            if self.config.display_thread_id {
+                jw.comma();
+                jw.key("threadId");
+                let id = std::thread::current().id();
+                let number = self.config.thread_id_numeric.then(|| thread_id_number(id));
+                match number.flatten() {
+                    Some(n) => jw.val_u64(n),
+                    None => jw.val_debug(&id),
+                }
+            }
+
+            let timestamp_start = jw.len();
+            if self.config.timestamp_last {
+                self.write_timestamp_entry(&mut jw, true);
+            }
+            let timestamp_end = jw.len();
+
+            jw.obj_end();
+            jw.finish_line_with(self.config.line_ending);
+
+            if self.config.validate_output && !is_balanced(jw.as_bytes()) {
+                panic!(
+                    "tracing-microjson produced an unbalanced line: {}",
+                    String::from_utf8_lossy(jw.as_bytes()).trim_end()
+                );
+            }
+
+            // The hash is added last, over the line as written, but counts
+            // towards the length limit
+            let mut line_hash = self.config.line_hash;
+            let hash_len = if line_hash { HASH_ENTRY_LEN } else { 0 };
+            if let Some(max) = self.config.max_line_len
+                && jw.len() + hash_len > max
+            {
+                line_hash = false;
+                // Keep the leading timestamp and level, and a trailing
+                // timestamp if there is one
+                jw.keep_prefix_and(level_end, timestamp_start..timestamp_end);
+                jw.comma();
+                jw.key("truncated");
+                jw.val_bool(true);
+                jw.obj_end();
+                jw.finish_line_with(self.config.line_ending);
+            }
+
+            if self.config.newline_safety {
+                FIELD_SCRATCH.with(|cell| {
+                    let mut scratch = cell.take();
+                    jw.escape_raw_newlines(&mut scratch);
+                    cell.set(scratch);
+                });
+            }
+
+            if line_hash {
+                write_line_hash(&mut jw, self.config.line_ending);
+            }
+
+            if self.config.pretty {
+                FIELD_SCRATCH.with(|cell| {
+                    let mut scratch = cell.take();
+                    jw.pretty_print(&mut scratch);
+                    cell.set(scratch);
+                });
+            }
+
+            let mut writer = self.make_writer.make_writer_for(event.metadata());
+            let written = if let Some(array) = &self.config.array {
+                let mut started = array.started.lock().unwrap_or_else(|e| e.into_inner());
+                // One write for both, so that a failed line does not leave
+                // its separator behind
+                jw.prepend(if *started { b"," } else { b"[" });
+                writer
+                    .write_all(jw.as_bytes())
+                    .inspect(|()| *started = true)
+                    .map(|()| jw.len())
+            } else {
+                writer.write_all(jw.as_bytes()).map(|()| jw.len())
+            };
+            match written {
+                Ok(bytes) => {
+                    if let Some(metrics) = &self.config.metrics {
+                        metrics.record(event.metadata().level(), bytes);
+                    }
+                }
+                Err(err) => {
+                    self.config.dropped_lines.fetch_add(1, Ordering::Relaxed);
+                    if let Some(handler) = &self.config.write_error_handler {
+                        handler(err);
+                    }
+                }
+            }
+            if self.config.flush {
+                let _ = writer.flush();
+            }
+
+            // Return buffer for reuse, shrinking if an outlier event grew it
+            let mut buf = jw.into_vec();
+            if buf.capacity() > self.config.buf_cap_limit {
+                buf.shrink_to(self.config.buf_hint);
+            }
+            cell.set(buf);
+        });
+    }
+}
+
+#[cfg(unix)]
+fn parent_id() -> Option<u32> {
+    Some(std::os::unix::process::parent_id())
+}
+
+#[cfg(not(unix))]
+fn parent_id() -> Option<u32> {
+    None
+}
+
+/// The local address the OS would use to reach the internet, trying IPv4
+/// then IPv6. No packets are sent: connecting a UDP socket only selects a
+/// route.
+#[cfg(feature = "host-ip")]
+fn detect_host_ip() -> Option<IpAddr> {
+    use std::net::UdpSocket;
+
+    [("0.0.0.0:0", "192.0.2.1:9"), ("[::]:0", "[2001:db8::1]:9")]
+        .into_iter()
+        .find_map(|(bind, remote)| {
+            let socket = UdpSocket::bind(bind).ok()?;
+            socket.connect(remote).ok()?;
+            let ip = socket.local_addr().ok()?.ip();
+            (!ip.is_loopback() && !ip.is_unspecified()).then_some(ip)
+        })
+}
+
+/// The number inside a [`ThreadId`](std::thread::ThreadId)'s `Debug` output
+/// (`ThreadId(2)`), or `None` if it has no digits or they overflow.
+fn thread_id_number(id: std::thread::ThreadId) -> Option<u64> {
+    /// Accumulates the decimal digits written to it, ignoring other text.
+    #[derive(Default)]
+    struct Digits {
+        value: u64,
+        found: bool,
+    }
+
+    impl std::fmt::Write for Digits {
+        fn write_str(&mut self, s: &str) -> std::fmt::Result {
+            for digit in s.bytes().filter(u8::is_ascii_digit) {
+                self.value = self
+                    .value
+                    .checked_mul(10)
+                    .and_then(|v| v.checked_add(u64::from(digit - b'0')))
+                    .ok_or(std::fmt::Error)?;
+                self.found = true;
+            }
+            Ok(())
+        }
+    }
+
+    let mut digits = Digits::default();
+    std::fmt::write(&mut digits, format_args!("{id:?}")).ok()?;
+    digits.found.then_some(digits.value)
+}
+
+/// Write an event's fields as object entries, preceded by a comma if
+/// `continuing`, deduplicating and sorting them if configured.
+fn record_event_fields(
+    jw: &mut JsonWriter,
+    event: &Event<'_>,
+    config: &FieldConfig,
+    continuing: bool,
+    select: Select,
+) {
+    let visitor = if continuing {
+        JsonVisitor::continuing(jw, config)
+    } else {
+        JsonVisitor::with_config(jw, config)
+    }
+    .selecting(select);
+    if config.dedup || config.sorted {
+        let mut entries = FIELD_ENTRIES.take();
+        let mut visitor = visitor.tracking_entries(&mut entries);
+        event.record(&mut visitor);
+        visitor.write_dropped_counts();
+        let mut scratch = FIELD_SCRATCH.take();
+        visitor.finish(&mut scratch);
+        FIELD_SCRATCH.set(scratch);
+        FIELD_ENTRIES.set(entries);
+    } else {
+        let mut visitor = visitor;
+        event.record(&mut visitor);
+        visitor.write_dropped_counts();
+    }
+}
+
+/// The configured message to write in place of a missing one, before the
+/// other fields.
+fn default_message<'a>(event: &Event<'_>, config: &'a Config) -> Option<&'a str> {
+    config
+        .default_message
+        .as_deref()
+        .filter(|_| event.metadata().fields().field("message").is_none())
+}
+
+/// Write the selected event fields as a JSON object, starting with
+/// `default_message` as the `message` if given.
+fn write_fields_object(
+    jw: &mut JsonWriter,
+    event: &Event<'_>,
+    config: &FieldConfig,
+    default_message: Option<&str>,
+    select: Select,
+) {
+    jw.obj_start();
+    if let Some(message) = default_message {
+        jw.key("message");
+        jw.val_str(message);
+    }
+    record_event_fields(jw, event, config, default_message.is_some(), select);
+    jw.obj_end();
+}
+
+/// Write a span as a JSON object containing its name and recorded fields.
+fn write_span<S>(jw: &mut JsonWriter, span: &SpanRef<'_, S>, config: &Config)
+where
+    S: for<'a> LookupSpan<'a>,
+{
+    jw.obj_start();
+    jw.key("name");
+    jw.val_str(span.name());
+    write_span_entries(jw, span, config, false);
+    jw.obj_end();
+}
+
+/// Displays the names of the spans in `ids`, given leaf first, as a
+/// root-to-leaf path joined by `separator`.
+struct SpanPath<'a, S> {
+    ids: &'a [Id],
+    ctx: &'a Context<'a, S>,
+    separator: &'a str,
+}
+
+impl<S> fmt::Display for SpanPath<'_, S>
+where
+    S: Subscriber + for<'l> LookupSpan<'l>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut first = true;
+        for span in self.ids.iter().rev().filter_map(|id| self.ctx.span(id)) {
+            if !first {
+                f.write_str(self.separator)?;
+            }
+            first = false;
+            let name = span.name();
+            if !name.contains('\\') && (self.separator.is_empty() || !name.contains(self.separator))
+            {
+                f.write_str(name)?;
+                continue;
+            }
+            // Escape so the path can still be split on the separator
+            let mut rest = name;
+            while let Some(c) = rest.chars().next() {
+                if c == '\\' {
+                    f.write_str("\\\\")?;
+                    rest = &rest[1..];
+                } else if !self.separator.is_empty() && rest.starts_with(self.separator) {
+                    f.write_str("\\")?;
+                    f.write_str(self.separator)?;
+                    rest = &rest[self.separator.len()..];
+                } else {
+                    fmt::Write::write_char(f, c)?;
+                    rest = &rest[c.len_utf8()..];
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Write one element of the `"spans"` array in the configured [`SpanShape`].
+fn write_span_element<S>(jw: &mut JsonWriter, span: &SpanRef<'_, S>, config: &Config)
+where
+    S: for<'a> LookupSpan<'a>,
+{
+    match config.span_shape {
+        SpanShape::Object => write_span(jw, span, config),
+        SpanShape::Name => jw.val_str(span.name()),
+        SpanShape::Tuple => {
+            jw.arr_start();
+            jw.val_str(span.name());
+            jw.comma();
+            jw.obj_start();
+            write_span_entries(jw, span, config, true);
+            jw.obj_end();
+            jw.arr_end();
+        }
+    }
+}
+
+/// Write a span's `id`, `target` and `opened_at` (if enabled) and fields
+/// into an already-open object.
+/// `first` is whether the object is still empty.
+fn write_span_entries<S>(jw: &mut JsonWriter, span: &SpanRef<'_, S>, config: &Config, first: bool)
+where
+    S: for<'a> LookupSpan<'a>,
+{
+    let mut first = first;
+    if config.display_span_ids {
+        if !first {
+            jw.comma();
+        }
+        first = false;
+        jw.key("id");
+        jw.val_u64(span.id().into_u64());
+    }
+    if config.span_target {
+        if !first {
+            jw.comma();
+        }
+        first = false;
+        jw.key("target");
+        jw.val_str(span.metadata().target());
+    }
+    let ext = span.extensions();
+    if config.span_open_time
+        && let Some(SpanOpenedAt(opened_at)) = ext.get::<SpanOpenedAt>()
+    {
+        if !first {
+            jw.comma();
+        }
+        first = false;
+        // Unescaped, like the event timestamp
+        jw.key("opened_at");
+        jw.push_byte(b'"');
+        jw.raw(opened_at.as_bytes());
+        jw.push_byte(b'"');
+    }
+    if let Some(fields) = ext.get::<SpanFields>() {
+        let mut visitor = if first {
+            JsonVisitor::with_config(jw, &config.fields)
+        } else {
+            JsonVisitor::continuing(jw, &config.fields)
+        };
+        fields.record(&mut visitor);
+        visitor.write_dropped_counts();
+    }
+}
+
+/// Write the `key=value` pairs found in `message` as a `"message_fields"`
+/// object, preceded by a comma. Writes nothing if there are none.
+///
+/// The message is split on spaces, and a part is a pair only if it has
+/// exactly one `=`, a non-empty key of ASCII letters, digits, `_`, `.` and
+/// `-`, and a non-empty value. Values are written as strings.
+fn write_message_fields(jw: &mut JsonWriter, message: &str) {
+    let mut first = true;
+    for (key, value) in message.split(' ').filter_map(|part| {
+        let (key, value) = part.split_once('=')?;
+        let key_ok = key
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'_' | b'.' | b'-'));
+        (key_ok && !key.is_empty() && !value.is_empty() && !value.contains('='))
+            .then_some((key, value))
+    }) {
+        if first {
+            jw.comma();
+            jw.key("message_fields");
+            jw.obj_start();
+            first = false;
+        } else {
+            jw.comma();
+        }
+        jw.key(key);
+        jw.val_str(value);
+    }
+    if !first {
+        jw.obj_end();
+    }
+}
+
+/// The length of the `,"hash":"…"` entry written by [`write_line_hash`].
+const HASH_ENTRY_LEN: usize = r#","hash":"""#.len() + 16 + 1;
+
+/// Add a `"hash"` entry to the finished line in `jw`, hashing everything
+/// before it. The 16 hex digits are written raw, so that no value option
+/// such as a length limit can alter them.
+fn write_line_hash(jw: &mut JsonWriter, ending: LineEnding) {
+    use std::fmt::Write as _;
+
+    // Reopen the line: drop the terminator and the closing brace
+    jw.truncate(jw.len() - ending.as_bytes().len() - 1);
+    let hash = fnv1a_64(jw.as_bytes());
+    jw.comma();
+    jw.key("hash");
+    jw.push_byte(b'"');
+    let _ = write!(jw, "{hash:016x}");
+    jw.push_byte(b'"');
+    jw.obj_end();
+    jw.finish_line_with(ending);
+}
+
+/// The 64-bit FNV-1a hash of `bytes`.
+fn fnv1a_64(bytes: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    bytes.iter().fold(OFFSET_BASIS, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(PRIME)
+    })
+}
+
+/// Whether the quotes, braces and brackets in `line` are balanced and
+/// properly nested, skipping over string contents and escapes.
+///
+/// A cheap structural check, not a full JSON parse.
+fn is_balanced(line: &[u8]) -> bool {
+    let mut open = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    for &b in line {
+        if in_string {
+            match b {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match b {
+            b'"' => in_string = true,
+            b'{' | b'[' => open.push(b),
+            b'}' if open.pop() != Some(b'{') => return false,
+            b']' if open.pop() != Some(b'[') => return false,
+            _ => {}
+        }
+    }
+    !in_string && open.is_empty()
+}
+
+/// Write a `SystemTime` as RFC 3339 with microsecond precision in UTC directly
+/// into any `fmt::Write` sink, avoiding an intermediate `String` allocation.
+fn write_timestamp(t: SystemTime, w: &mut impl std::fmt::Write) -> std::fmt::Result {
+    let (secs, micros) = match t.duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(dur) => (dur.as_secs() as i64, dur.subsec_micros()),
+        // Before the epoch: round down to the previous whole microsecond, so
+        // the fractional part still counts forwards from `secs`
+        Err(err) => {
+            let dur = err.duration();
+            let secs = -(dur.as_secs() as i64);
+            match dur.subsec_nanos() {
+                0 => (secs, 0),
+                nanos => (secs - 1, (1_000_000_000 - nanos) / 1000),
+            }
+        }
+    };
+
+    let (year, month, day, hour, min, sec) = secs_to_datetime(secs);
+
+    write!(
+        w,
+        "{year:04}-{month:02}-{day:02}T{hour:02}:{min:02}:{sec:02}.{micros:06}Z"
+    )
+}
+
+/// Format a `SystemTime` as RFC 3339 with microsecond precision in UTC.
+/// e.g. "2026-02-20T12:00:00.000000Z"
+#[cfg(test)]
+fn format_timestamp(t: SystemTime) -> String {
+    let mut buf = String::with_capacity(27);
+    write_timestamp(t, &mut buf).unwrap();
+    buf
+}
+
+/// Convert Unix seconds, negative before 1970, to
+/// (year, month, day, hour, min, sec) in UTC.
+fn secs_to_datetime(secs: i64) -> (i64, u64, u64, u64, u64, u64) {
+    let time = secs.rem_euclid(86_400) as u64;
+    let days = secs.div_euclid(86_400);
+    let sec = time % 60;
+    let min = time / 60 % 60;
+    let hour = time / 3600;
+
+    // Compute year, month, day from days since epoch (1970-01-01)
+    let (year, month, day) = days_to_ymd(days);
+
+    (year, month, day, hour, min, sec)
+}
+
+fn days_to_ymd(days: i64) -> (i64, u64, u64) {
+    // Using the algorithm from civil_from_days (Howard Hinnant's date algorithms)
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097) as u64;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let y = yoe as i64 + era * 400;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = if m <= 2 { y + 1 } else { y };
+    (y, m, d)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::writer::escape_json;
+    use std::borrow::Cow;
+
+    /// Convert a JsonWriter to a String for test assertions.
+    fn to_string(jw: JsonWriter) -> String {
+        String::from_utf8(jw.into_vec()).unwrap()
+    }
+
+    /// Helper: write a string through val_str and return the raw buffer content.
+    fn val_str_output(s: &str) -> String {
+        let mut jw = JsonWriter::new();
+        jw.val_str(s);
+        to_string(jw)
+    }
+
+    #[test]
+    fn test_val_str_basic() {
+        assert_eq!(val_str_output("hello"), r#""hello""#);
+        assert_eq!(val_str_output("say \"hi\""), r#""say \"hi\"""#);
+        assert_eq!(val_str_output("back\\slash"), r#""back\\slash""#);
+        assert_eq!(val_str_output(""), r#""""#);
+    }
+
+    #[test]
+    fn test_val_str_control_chars() {
+        assert_eq!(val_str_output("\n"), r#""\n""#);
+        assert_eq!(val_str_output("\r"), r#""\r""#);
+        assert_eq!(val_str_output("\t"), r#""\t""#);
+        assert_eq!(val_str_output("\x08"), r#""\b""#);
+        assert_eq!(val_str_output("\x0C"), r#""\f""#);
+        // U+0001 → \u0001
+        assert_eq!(val_str_output("\x01"), r#""\u0001""#);
+        assert_eq!(val_str_output("\x1F"), r#""\u001f""#);
+    }
+
+    #[test]
+    fn test_val_str_unicode_passthrough() {
+        // Non-ASCII but above U+001F should pass through unescaped
+        assert_eq!(val_str_output("café"), "\"café\"");
+        assert_eq!(val_str_output("日本語"), "\"日本語\"");
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn test_val_str_round_trips(s in proptest::prelude::any::<String>()) {
+            let out = val_str_output(&s);
+            let parsed: String = serde_json::from_str(&out).unwrap();
+            proptest::prop_assert_eq!(parsed, s);
+        }
+
+        #[test]
+        fn test_val_display_round_trips(s in proptest::prelude::any::<String>()) {
+            let mut jw = JsonWriter::new();
+            jw.val_display(&s);
+            let parsed: String = serde_json::from_str(&to_string(jw)).unwrap();
+            proptest::prop_assert_eq!(parsed, s);
+        }
+
+        #[test]
+        fn test_val_str_control_and_separator_chars(
+            s in "[\\u{0}-\\u{1f}\\u{7f}\\u{2028}\\u{2029}\"\\\\a-z]*"
+        ) {
+            let out = val_str_output(&s);
+            let parsed: String = serde_json::from_str(&out).unwrap();
+            proptest::prop_assert_eq!(parsed, s);
+        }
+    }
+
+    #[test]
+    fn test_f64_edge_cases() {
+        let mut jw = JsonWriter::new();
+        jw.val_f64(f64::NAN);
+        assert_eq!(to_string(jw), "null");
+
+        let mut jw = JsonWriter::new();
+        jw.val_f64(f64::INFINITY);
+        assert_eq!(to_string(jw), "null");
+
+        let mut jw = JsonWriter::new();
+        jw.val_f64(f64::NEG_INFINITY);
+        assert_eq!(to_string(jw), "null");
+
+        let mut jw = JsonWriter::new();
+        jw.val_f64(-0.0_f64);
+        let s = to_string(jw);
+        // -0.0 should be written as a number (not null)
+        assert!(
+            s == "-0" || s == "0" || s == "-0.0" || s == "0.0",
+            "got: {s}"
+        );
+
+        let mut jw = JsonWriter::new();
+        jw.val_f64(2.78);
+        let s = to_string(jw);
+        assert!(s.contains("2.78"), "got: {s}");
+    }
+
+    #[test]
+    fn test_128_bit_extremes() {
+        let mut jw = JsonWriter::new();
+        jw.val_u128(u128::MIN);
+        assert_eq!(to_string(jw), r#""0""#);
+
+        let mut jw = JsonWriter::new();
+        jw.val_u128(u128::MAX);
+        assert_eq!(
+            to_string(jw),
+            r#""340282366920938463463374607431768211455""#
+        );
+
+        let mut jw = JsonWriter::new();
+        jw.val_i128(i128::MIN);
+        assert_eq!(
+            to_string(jw),
+            r#""-170141183460469231731687303715884105728""#
+        );
+
+        let mut jw = JsonWriter::new();
+        jw.val_i128(i128::MAX);
+        assert_eq!(
+            to_string(jw),
+            r#""170141183460469231731687303715884105727""#
+        );
+    }
+
+    #[test]
+    fn test_thread_id_number() {
+        let id = std::thread::current().id();
+        let debug = format!("{id:?}");
+        let n = thread_id_number(id).expect("ThreadId Debug contains a number");
+        assert_eq!(debug, format!("ThreadId({n})"));
+    }
+
+    #[test]
+    fn test_fnv1a_64() {
+        // Reference vectors from the FNV specification
+        assert_eq!(fnv1a_64(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a_64(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv1a_64(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn test_escape_json_borrows_when_unchanged() {
+        for s in ["", "hello world", "unicode: ü 日本 🎉", "slash / ok"] {
+            assert!(
+                matches!(escape_json(s), Cow::Borrowed(b) if b == s),
+                "{s:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_escape_json_owned_when_escaped() {
+        for (s, expected) in [
+            ("a\"b", r#"a\"b"#),
+            ("back\\slash", r#"back\\slash"#),
+            ("line\nbreak\t", r#"line\nbreak\t"#),
+            ("\x01ü", r#"\u0001ü"#),
+        ] {
+            let escaped = escape_json(s);
+            assert!(matches!(escaped, Cow::Owned(_)), "{s:?}");
+            assert_eq!(escaped, expected);
+        }
+    }
+
+    #[test]
+    fn test_is_balanced() {
+        assert!(is_balanced(br#"{"a":[1,{"b":"}]\"["}]}"#));
+        assert!(is_balanced(b"{}\n"));
+        assert!(!is_balanced(br#"{"a":"x"y"}"#));
+        assert!(!is_balanced(br#"{"a":[1}"#));
+        assert!(!is_balanced(br#"{"a":1"#));
+        assert!(!is_balanced(br#"{"a":1}}"#));
+    }
+
+    #[test]
+    fn test_f64_non_finite_as_string() {
+        let config = WriterConfig {
+            non_finite_as_string: true,
+            ..WriterConfig::default()
+        };
+        for (v, expected) in [
+            (f64::NAN, r#""NaN""#),
+            (f64::INFINITY, r#""Infinity""#),
+            (f64::NEG_INFINITY, r#""-Infinity""#),
+        ] {
+            let mut jw = JsonWriter::new().with_config(config);
+            jw.val_f64(v);
+            assert_eq!(to_string(jw), expected);
+        }
+
+        // Finite values are unaffected
+        let mut jw = JsonWriter::new().with_config(config);
+        jw.val_f64(1.5);
+        assert_eq!(to_string(jw), "1.5");
+    }
+
+    #[test]
+    fn test_f64_matches_serde_json() {
+        let cases = [
+            0.0,
+            -0.0,
+            1.0,
+            -1.5,
+            0.1 + 0.2,
+            100.0,
+            2.78,
+            123_456_789.0,
+            0.000_123,
+            1e-5,
+            1e-7,
+            -1.234e-7,
+            1e15,
+            1e16,
+            1.5e16,
+            1e21,
+            1.5e300,
+            f64::MAX,
+            f64::MIN,
+            f64::MIN_POSITIVE,
+            f64::EPSILON,
+            5e-324,
+        ];
+        for v in cases {
+            let mut jw = JsonWriter::new();
+            jw.val_f64(v);
+            assert_eq!(
+                to_string(jw),
+                serde_json::to_string(&v).unwrap(),
+                "mismatch for {v:e}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_timestamp_format() {
+        // Test known SystemTime value: Unix epoch
+        let epoch = SystemTime::UNIX_EPOCH;
+        let s = format_timestamp(epoch);
+        assert_eq!(s, "1970-01-01T00:00:00.000000Z");
+
+        // Test another known value: 2026-02-20T12:00:00Z = 1771588800 seconds
+        let t = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1771588800);
+        let s = format_timestamp(t);
+        assert_eq!(s, "2026-02-20T12:00:00.000000Z");
+    }
+
+    #[test]
+    fn test_timestamp_microsecond_precision() {
+        // 2026-02-20T12:00:00Z + 123456 µs → .123456
+        let t = SystemTime::UNIX_EPOCH
+            + std::time::Duration::from_micros(1_771_588_800 * 1_000_000 + 123_456);
+        let s = format_timestamp(t);
+        assert_eq!(s, "2026-02-20T12:00:00.123456Z");
+
+        // Exactly 1 µs past epoch
+        let t = SystemTime::UNIX_EPOCH + std::time::Duration::from_micros(1);
+        let s = format_timestamp(t);
+        assert_eq!(s, "1970-01-01T00:00:00.000001Z");
+
+        // 999999 µs (all six digits occupied)
+        let t = SystemTime::UNIX_EPOCH + std::time::Duration::from_micros(999_999);
+        let s = format_timestamp(t);
+        assert_eq!(s, "1970-01-01T00:00:00.999999Z");
+    }
+
+    #[test]
+    fn test_days_to_ymd_far_future() {
+        for (days, ymd) in [
+            (47_540, (2100, 2, 28)),
+            // 2100 is not a leap year
+            (47_541, (2100, 3, 1)),
+            // 2400 is
+            (157_113, (2400, 2, 29)),
+            (157_114, (2400, 3, 1)),
+            (376_200, (3000, 1, 1)),
+            (2_932_896, (9999, 12, 31)),
+        ] {
+            assert_eq!(days_to_ymd(days), ymd, "days = {days}");
+        }
+
+        let t =
+            SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(2_932_896 * 86_400 + 86_399);
+        assert_eq!(format_timestamp(t), "9999-12-31T23:59:59.000000Z");
+    }
+
+    #[test]
+    fn test_timestamp_before_epoch() {
+        let before = |d| SystemTime::UNIX_EPOCH - d;
+
+        let t = before(std::time::Duration::from_secs(1));
+        assert_eq!(format_timestamp(t), "1969-12-31T23:59:59.000000Z");
+
+        let t = before(std::time::Duration::from_secs(86_400));
+        assert_eq!(format_timestamp(t), "1969-12-31T00:00:00.000000Z");
+
+        // Fractions count forwards from the previous second
+        let t = before(std::time::Duration::from_micros(1));
+        assert_eq!(format_timestamp(t), "1969-12-31T23:59:59.999999Z");
+
+        // 1900-03-01T12:34:56.5Z, after the non-leap 1900-02-28
+        let t = before(std::time::Duration::from_millis(2_203_845_903_500));
+        assert_eq!(format_timestamp(t), "1900-03-01T12:34:56.500000Z");
+    }
+}
@@ -0,0 +1,133 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tracing_core::{Level, LevelFilter};
+
+/// A handle for changing a [`JsonLayer`](crate::JsonLayer)'s maximum level at
+/// runtime, e.g. from an admin endpoint.
+///
+/// Created by [`JsonLayer::with_level_handle`](crate::JsonLayer::with_level_handle).
+/// Handles are cheap to clone and can be sent across threads; every clone
+/// controls the same layer.
+#[derive(Clone, Debug)]
+pub struct LevelHandle {
+    level: Arc<AtomicUsize>,
+}
+
+impl LevelHandle {
+    pub(crate) fn new(level: LevelFilter) -> Self {
+        Self {
+            level: Arc::new(AtomicUsize::new(filter_to_usize(level))),
+        }
+    }
+
+    /// Set the most verbose level the layer will write.
+    ///
+    /// Takes effect for the next event on any thread.
+    pub fn set_level(&self, level: LevelFilter) {
+        self.level.store(filter_to_usize(level), Ordering::Relaxed);
+    }
+
+    /// The most verbose level the layer currently writes.
+    pub fn level(&self) -> LevelFilter {
+        match self.level.load(Ordering::Relaxed) {
+            0 => LevelFilter::OFF,
+            1 => LevelFilter::ERROR,
+            2 => LevelFilter::WARN,
+            3 => LevelFilter::INFO,
+            4 => LevelFilter::DEBUG,
+            _ => LevelFilter::TRACE,
+        }
+    }
+
+    /// Whether an event at `level` should currently be written.
+    pub(crate) fn enabled(&self, level: &Level) -> bool {
+        level <= &self.level()
+    }
+}
+
+fn filter_to_usize(level: LevelFilter) -> usize {
+    match level.into_level() {
+        None => 0,
+        Some(Level::ERROR) => 1,
+        Some(Level::WARN) => 2,
+        Some(Level::INFO) => 3,
+        Some(Level::DEBUG) => 4,
+        Some(Level::TRACE) => 5,
+    }
+}
+
+/// How the level is written.
+///
+/// Set with [`JsonLayer::with_level_style`](crate::JsonLayer::with_level_style).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LevelStyle {
+    /// tracing's names: `"TRACE"`, `"DEBUG"`, `"INFO"`, `"WARN"`, `"ERROR"`.
+    #[default]
+    Name,
+    /// The names in lowercase: `"trace"` to `"error"`.
+    Lowercase,
+    /// tracing's ordering as a number, from `TRACE` = 0 to `ERROR` = 4, as
+    /// in [`with_level_ordinal`](crate::JsonLayer::with_level_ordinal).
+    Numeric,
+    /// A single uppercase letter, as Android logcat priorities: `"V"`
+    /// (verbose) for `TRACE`, then `"D"`, `"I"`, `"W"`, `"E"`.
+    ShortUpper,
+}
+
+/// tracing's own ordering of a level, from `TRACE` = 0 to `ERROR` = 4.
+pub(crate) fn ordinal(level: &Level) -> u64 {
+    match *level {
+        Level::TRACE => 0,
+        Level::DEBUG => 1,
+        Level::INFO => 2,
+        Level::WARN => 3,
+        Level::ERROR => 4,
+    }
+}
+
+/// Syslog severities, indexed by [`ordinal`]: `debug` (7) for both `TRACE`
+/// and `DEBUG`, then `informational` (6), `warning` (4) and `error` (3).
+pub(crate) const SYSLOG_SEVERITIES: [u64; 5] = [7, 7, 6, 4, 3];
+
+/// The label written for each level, indexed by [`ordinal`].
+#[derive(Clone, Copy)]
+pub(crate) struct LevelNames([&'static str; 5]);
+
+impl LevelNames {
+    /// tracing's own names: `TRACE`, `DEBUG`, `INFO`, `WARN`, `ERROR`.
+    pub(crate) const DEFAULT: Self = Self(["TRACE", "DEBUG", "INFO", "WARN", "ERROR"]);
+
+    /// Google Cloud Logging `severity` values.
+    pub(crate) const GCP: Self = Self(["DEBUG", "DEBUG", "INFO", "WARNING", "ERROR"]);
+
+    /// Datadog `status` values, with `TRACE` folded into `debug`.
+    pub(crate) const DATADOG: Self = Self(["debug", "debug", "info", "warning", "error"]);
+
+    /// Android logcat priority letters.
+    pub(crate) const SHORT_UPPER: Self = Self(["V", "D", "I", "W", "E"]);
+
+    /// tracing's names in lowercase.
+    pub(crate) const LOWERCASE: Self = Self(["trace", "debug", "info", "warn", "error"]);
+
+    /// Elastic Common Schema `log.level` values, which are tracing's names
+    /// in lowercase.
+    pub(crate) const ECS: Self = Self::LOWERCASE;
+
+    /// The labels `map` gives each level.
+    pub(crate) fn from_fn(map: impl Fn(Level) -> &'static str) -> Self {
+        Self(
+            [
+                Level::TRACE,
+                Level::DEBUG,
+                Level::INFO,
+                Level::WARN,
+                Level::ERROR,
+            ]
+            .map(map),
+        )
+    }
+
+    pub(crate) fn get(&self, level: &Level) -> &'static str {
+        self.0[ordinal(level) as usize]
+    }
+}
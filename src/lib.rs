@@ -36,16 +36,138 @@
 //!
 //! | Method | Default | Effect |
 //! |---|---|---|
+//! | [`JsonLayer::with_timestamp_field_name`] | `"timestamp"` | Key for the timestamp |
+//! | [`JsonLayer::with_level_style`] | `Name` | Write the level as its name, in lowercase, as a number, or as one letter |
+//! | [`JsonLayer::with_level_map`] | tracing's names | Write the level as a custom label per level |
+//! | [`JsonLayer::with_level_field_name`] | `"level"` | Key for the level |
+//! | [`JsonLayer::with_level_ordinal`] | `false` | Include the level as a number (`TRACE` = 0 … `ERROR` = 4) |
+//! | [`JsonLayer::with_level_number_field`] | not written | Also write the level as a number under the given key |
+//! | [`JsonLayer::with_level_numbers`] | syslog severities | The numbers written by `with_level_number_field` |
+//! | [`JsonLayer::with_context_stack`] | `false` | Include the pairs added with [`push_context`] on the current thread |
+//! | [`JsonLayer::with_field_hook`] | none | Call a closure on each event to write extra top-level entries |
 //! | [`JsonLayer::with_target`] | `true` | Include the event target (module path) |
+//! | [`JsonLayer::with_target_prefix_strip`] | none | Strip a leading prefix from the `target` |
+//! | [`JsonLayer::with_target_transform`] | `AsIs` | Lowercase the `target` or keep only its last segment |
+//! | [`JsonLayer::with_module_path`] | `false` | Include the module path of the call site |
 //! | [`JsonLayer::with_file`] | `false` | Include the source filename |
 //! | [`JsonLayer::with_line_number`] | `false` | Include the source line number |
+//! | [`JsonLayer::with_location`] | `false` | Include the module path, filename and line number together |
+//! | [`JsonLayer::with_code_location`] | `false` | Include the filename and line number as one `"file:line"` string |
 //! | [`JsonLayer::with_thread_ids`] | `false` | Include the thread ID |
+//! | [`JsonLayer::with_thread_ids_numeric`] | `false` | Write the thread ID as a number instead of `"ThreadId(n)"` |
 //! | [`JsonLayer::with_thread_names`] | `false` | Include the thread name |
+//! | [`JsonLayer::with_delta_time`] | `false` | Include milliseconds since the thread's previous event |
+//! | [`JsonLayer::with_pid`] | `false` | Include the process ID |
+//! | [`JsonLayer::with_ppid`] | `false` | Include the parent process ID (Unix only) |
+//! | `JsonLayer::with_host_ip` | `false` | Include the detected primary IP address as `host_ip` (`host-ip` feature) |
+//! | [`JsonLayer::with_host_ip_value`] | none | Include the given IP address as `host_ip` |
+//! | `JsonLayer::with_otel_ids` | `false` | Include OpenTelemetry `trace_id` / `span_id` (`opentelemetry` feature) |
+//! | `JsonLayer::with_datadog_ids` | `false` | Include Datadog `dd.trace_id` / `dd.span_id` (`opentelemetry` feature) |
+//! | [`JsonLayer::with_current_span`] | `true` | Include the innermost span as `"span"` |
+//! | [`JsonLayer::with_span_list`] | `true` | Include all active spans as `"spans"` |
+//! | [`JsonLayer::with_max_span_depth`] | no limit | Keep only the innermost spans in `"spans"` |
+//! | [`JsonLayer::with_span_field_name`] | `"span"` | Key for the innermost span |
+//! | [`JsonLayer::with_spans_field_name`] | `"spans"` | Key for the list of active spans |
+//! | [`JsonLayer::with_span_ids`] | `false` | Include each span's numeric `id` in span objects |
+//! | [`JsonLayer::with_span_target`] | `false` | Include each span's own `target` in span objects |
+//! | [`JsonLayer::with_span_open_time`] | `false` | Include when each span was created as `opened_at` in span objects |
+//! | [`JsonLayer::with_span_tree_ids`] | `false` | Include the innermost span's `span_id` and `parent_span_id` |
+//! | [`JsonLayer::with_parent_span`] | `false` | Include the name of the innermost span's parent |
+//! | [`JsonLayer::with_span_path`] | `false` | Include the active span names joined into one `"span_path"` string |
+//! | [`JsonLayer::with_span_path_separator`] | `">"` | Separator between names in `"span_path"` |
+//! | [`JsonLayer::with_span_element_shape`] | [`SpanShape::Object`] | Write `"spans"` elements as objects, names, or `[name, fields]` pairs |
 //! | [`JsonLayer::flatten_event`] | `false` | Flatten event fields to the top level instead of nesting under `"fields"` |
+//! | [`JsonLayer::with_message_top_level`] | `false` | Write only the `message` at the top level, nesting the other fields |
+//! | [`JsonLayer::with_hoisted_fields`] | none | Write the named fields at the top level, nesting the others |
+//! | [`JsonLayer::with_fields_field_name`] | `"fields"` | Key for the nested event fields |
+//! | [`JsonLayer::with_timestamp_last`] | `false` | Write the timestamp as the last key instead of the first |
+//! | [`JsonLayer::with_output_validation`] | `false` | Panic on lines with unbalanced quotes or brackets (for tests) |
+//! | [`JsonLayer::with_newline_safety`] | `false` | Escape or remove raw newlines so each event stays on one line |
+//! | [`JsonLayer::with_pretty`] | `false` | Write each event as indented, multi-line JSON |
+//! | [`JsonLayer::with_flush`] | `false` | Flush the writer after each line |
+//! | [`JsonLayer::with_write_error_handler`] | none | Call a closure with the error when a line cannot be written |
+//! | [`JsonLayer::with_metrics`] | none | Count the lines written per level, and bytes written, in a [`LayerMetrics`] |
+//! | [`JsonLayer::with_line_ending`] | `Lf` | The terminator written after each line |
+//! | [`JsonLayer::with_max_line_len`] | no limit | Replace over-long lines with `{timestamp, level, "truncated":true}` |
+//! | [`JsonLayer::array_mode`] | `false` | Write events as the elements of one JSON array, closed when the layer is dropped |
+//! | [`JsonLayer::with_message_kv_parsing`] | `false` | Also write `key=value` pairs in the message as `"message_fields"` |
+//! | [`JsonLayer::with_dedup_fields`] | `false` | Keep only the last of repeated event field names |
+//! | [`JsonLayer::with_line_hash`] | `false` | Append a `"hash"` of the line's content (FNV-1a) |
+//! | [`JsonLayer::with_sorted_fields`] | `false` | Write event fields in alphabetical order |
+//! | [`JsonLayer::with_typed_fields`] | `false` | Write each field as `{"type":…,"value":…}` |
+//! | [`JsonLayer::with_per_type_field_limits`] | unlimited | Cap the number of string and numeric fields, counting what was dropped |
+//! | [`JsonLayer::with_max_field_len`] | no limit | Truncate long string values, appending `…` |
+//! | [`JsonLayer::with_truncation_marker`] | `"…"` | Text appended to values shortened by `with_max_field_len` |
+//! | [`JsonLayer::with_js_safe_escape`] | `false` | Escape U+2028 / U+2029 for JavaScript compatibility |
+//! | [`JsonLayer::with_escape_forward_slash`] | `false` | Escape `/` as `\/` for embedding in HTML |
+//! | [`JsonLayer::skip_false_bools`] | `false` | Omit boolean fields whose value is `false` |
+//! | [`JsonLayer::with_require_message`] | `false` | Skip events that have no `message` |
+//! | [`JsonLayer::with_default_message`] | none | A `message` written for events that have none |
+//! | [`JsonLayer::with_redacted_fields`] | none | Replace the values of the named fields with `"[REDACTED]"` |
+//! | [`JsonLayer::with_raw_json_fields`] | none | Write the named fields' values as embedded, pre-serialized JSON |
+//! | [`JsonLayer::with_bytes_encoding`] | `Base64` | How [`Bytes`] field values are written |
+//! | [`JsonLayer::with_duration_format`] | `Nanos` | How [`DurationField`] values are written |
+//! | [`JsonLayer::with_error_chain`] | `false` | Write error fields as an array of messages following `source()` |
+//! | [`JsonLayer::with_smart_debug`] | `false` | Write `Debug` values such as `Some(5)` or `[1, 2]` as JSON rather than strings |
+//! | [`JsonLayer::with_non_finite_as_string`] | `false` | Write NaN/infinite floats as strings instead of `null` |
+//! | [`JsonLayer::with_level_handle`] | — | Change the maximum level at runtime via a [`LevelHandle`] |
+//! | [`JsonLayer::with_writer`] | — | Replace the writer, keeping the rest of the configuration |
 //! | [`JsonLayer::with_timer`] | [`SystemTimestamp`] | Use a custom [`FormatTime`] implementation for timestamps |
+//! | [`JsonLayer::with_clock`] | [`SystemTime::now`](std::time::SystemTime::now) | Read the time for the default timestamp format from a custom clock |
 //! | [`JsonLayer::without_time`] | — | Disable timestamps entirely |
+//! | [`JsonLayer::with_buffer_hint`] | `256` | Initial capacity of the per-thread formatting buffer |
 //! | [`JsonLayer::with_buffer_capacity_limit`] | `4096` | Capacity threshold for per-thread buffer shrinking |
 //!
+//! # Presets
+//!
+//! Constructors that preconfigure the layer for a particular log backend.
+//! Every option can still be overridden afterwards.
+//!
+//! | Constructor | Target |
+//! |---|---|
+//! | [`JsonLayer::gcp`] | Google Cloud Logging (`time`, `severity`, top-level `message`) |
+//! | [`JsonLayer::ecs`] | Elastic Common Schema (`@timestamp`, `log.level`, `ecs.version`, top-level `message`) |
+//! | [`JsonLayer::datadog`] | Datadog (`status`, top-level `message`, `dd.trace_id` / `dd.span_id` with `opentelemetry`) |
+//! | [`JsonLayer::compact`] | Minimal output (no timestamp or `target`) |
+//! | [`JsonLayer::dev`] | Readable local output (pretty-printed, flattened, lowercase levels, file and line) |
+//! | [`JsonLayer::from_env`] | Options read from `MICROJSON_*` environment variables |
+//!
+//! # Optional features
+//!
+//! All are off by default except `std`; the default build depends only on
+//! `tracing-core` and `tracing-subscriber`.
+//!
+//! | Feature | Adds |
+//! |---|---|
+//! | `std` (default) | Everything except the core writer: [`JsonLayer`], the writers, spans and capture types, and the `tracing-core` / `tracing-subscriber` dependencies |
+//! | `opentelemetry` | `JsonLayer::with_otel_ids` and `JsonLayer::with_datadog_ids`, writing trace and span ids from `tracing-opentelemetry` |
+//! | `host-ip` | `JsonLayer::with_host_ip`, detecting the machine's IP address (no extra dependencies) |
+//! | `msgpack` | `MsgPackLayer`, writing the same event structure as MessagePack (no extra dependencies) |
+//! | `valuable` | Fields recorded with `tracing::field::valuable` written as nested JSON objects and arrays |
+//!
+//! `valuable` also needs the `tracing_unstable` cfg, as `tracing`'s own
+//! support does: build with `RUSTFLAGS="--cfg tracing_unstable"`. Event
+//! fields are then written structurally:
+//!
+//! ```text
+//! tracing::info!(user = tracing::field::valuable(&user), "login");
+//! // ..."fields":{"user":{"name":"alice","roles":["admin"]},"message":"login"}...
+//! ```
+//!
+//! Structs and maps become objects, lists and tuples arrays, and enums are
+//! written as serde writes them by default. Span fields are stored when
+//! recorded and keep the value's `Debug` form.
+//!
+//! ## `no_std`
+//!
+//! With `default-features = false` the crate is `no_std`, needs only
+//! `alloc`, and has no dependencies. What remains is the core writer:
+//! [`JsonWriter`], with its string, number, bool and null writers,
+//! [`Checkpoint`], [`LineEnding`] and [`escape_json`]. The layer and
+//! everything tied to `tracing`, along with `JsonWriter::val_bytes` and
+//! `JsonWriter::val_duration`, need `std`; the other optional features
+//! enable it.
+//!
 //! # Output format
 //!
 //! Every event is written as a single JSON line. The fields present depend on
@@ -57,31 +179,111 @@
 //!
 //! - `timestamp` — RFC 3339 with microsecond precision in UTC by default.
 //!   Customisable via [`with_timer`](JsonLayer::with_timer) or disabled with
-//!   [`without_time`](JsonLayer::without_time).
+//!   [`without_time`](JsonLayer::without_time). Moved to the end of the line
+//!   with [`with_timestamp_last`](JsonLayer::with_timestamp_last).
 //! - `level` — always present (`TRACE`, `DEBUG`, `INFO`, `WARN`, `ERROR`).
-//! - `fields` — event fields, nested under `"fields"` by default. With
+//! - level number — the level as a number, syslog's severity by default,
+//!   under the key set with
+//!   [`with_level_number_field`](JsonLayer::with_level_number_field).
+//! - `level_ord` — the level as a number from 0 (`TRACE`) to 4 (`ERROR`),
+//!   present when enabled via [`with_level_ordinal`](JsonLayer::with_level_ordinal).
+//! - context pairs — each pair added with [`push_context`] as a top-level
+//!   string entry, present when enabled via
+//!   [`with_context_stack`](JsonLayer::with_context_stack).
+//! - hook entries — any written by the closure set with
+//!   [`with_field_hook`](JsonLayer::with_field_hook).
+//! - `fields` — event fields, nested under `"fields"` by default, or the
+//!   key set with [`with_fields_field_name`](JsonLayer::with_fields_field_name). With
 //!   [`flatten_event(true)`](JsonLayer::flatten_event) they appear at the top
-//!   level instead.
+//!   level instead; with
+//!   [`with_message_top_level(true)`](JsonLayer::with_message_top_level)
+//!   only the `message` does, just before `"fields"`, as do any fields named
+//!   with [`with_hoisted_fields`](JsonLayer::with_hoisted_fields). Numbers and bools
+//!   keep their JSON type; values recorded with `%` are written as their
+//!   `Display` string and those recorded with `?` as their `Debug` string.
+//! - `message_fields` — `key=value` pairs parsed from the message, present
+//!   when enabled via [`with_message_kv_parsing`](JsonLayer::with_message_kv_parsing)
+//!   and the message has any.
 //! - `target` — module path, present when [`with_target`](JsonLayer::with_target)
 //!   is `true`.
+//! - `module_path` — the call site's module, present when enabled via
+//!   [`with_module_path`](JsonLayer::with_module_path).
 //! - `filename` / `line_number` — source location, present when enabled via
 //!   [`with_file`](JsonLayer::with_file) / [`with_line_number`](JsonLayer::with_line_number).
-//! - `threadId` / `threadName` — thread info, present when enabled via
-//!   [`with_thread_ids`](JsonLayer::with_thread_ids) / [`with_thread_names`](JsonLayer::with_thread_names).
-//! - `span` — the innermost active span (if any).
-//! - `spans` — all active spans from root to leaf (if any).
+//! - `location` — source file and line as one string, e.g.
+//!   `"src/main.rs:42"`, present when enabled via
+//!   [`with_code_location`](JsonLayer::with_code_location).
+//! - `delta_ms` — milliseconds since the previous event on the same thread,
+//!   present when enabled via [`with_delta_time`](JsonLayer::with_delta_time).
+//! - `pid` / `ppid` — process and parent process IDs, present when enabled via
+//!   [`with_pid`](JsonLayer::with_pid) / [`with_ppid`](JsonLayer::with_ppid).
+//! - `host_ip` — the machine's IP address, present when set via
+//!   [`with_host_ip_value`](JsonLayer::with_host_ip_value) or detected via
+//!   `with_host_ip` (requires the `host-ip` feature).
+//! - `trace_id` / `span_id` — OpenTelemetry ids of the innermost span with
+//!   trace context, present when enabled via `with_otel_ids` (requires the
+//!   `opentelemetry` feature).
+//! - `span_id` / `parent_span_id` — numeric ids of the innermost span and its
+//!   parent, present when enabled via
+//!   [`with_span_tree_ids`](JsonLayer::with_span_tree_ids).
+//! - `parent_span` — the name of the innermost span's parent, present when
+//!   enabled via [`with_parent_span`](JsonLayer::with_parent_span) and the
+//!   innermost span has a parent.
+//! - `span` — the innermost active span (if any), unless disabled via
+//!   [`with_current_span`](JsonLayer::with_current_span).
+//! - `spans` — all active spans from root to leaf (if any), unless disabled
+//!   via [`with_span_list`](JsonLayer::with_span_list), and limited to the
+//!   innermost with [`with_max_span_depth`](JsonLayer::with_max_span_depth).
+//!   Each element is an object unless changed with
+//!   [`with_span_element_shape`](JsonLayer::with_span_element_shape).
+//! - `span_path` — the names of all active spans from root to leaf joined
+//!   into one string, e.g. `"outer>inner"`, present when enabled via
+//!   [`with_span_path`](JsonLayer::with_span_path).
+//! - `threadName` / `threadId` — thread info, present when enabled via
+//!   [`with_thread_names`](JsonLayer::with_thread_names) / [`with_thread_ids`](JsonLayer::with_thread_ids).
+//! - `hash` — an FNV-1a hash of the preceding line content, always last,
+//!   present when enabled via [`with_line_hash`](JsonLayer::with_line_hash).
+//! - `truncated` — `true` on a line that replaced one longer than the
+//!   [`with_max_line_len`](JsonLayer::with_max_line_len) limit, which then
+//!   has only `timestamp` and `level` besides.
+//!
+//! Entries that tracing-subscriber's `json` formatter also writes come in
+//! the same order, so with the same options lines match byte for byte,
+//! timestamps aside. The one intentional difference is inside span objects:
+//! `name` comes first, followed by the span's fields in the order they were
+//! recorded, where tracing-subscriber writes the fields sorted by key and
+//! `name` last.
+
+#![cfg_attr(not(feature = "std"), no_std)]
 
-use std::cell::Cell;
-use std::io::Write;
-use std::time::SystemTime;
-use tracing_core::{Event, Subscriber};
-use tracing_subscriber::Layer;
-use tracing_subscriber::fmt::format::Writer as FmtWriter;
-use tracing_subscriber::layer::Context;
-use tracing_subscriber::registry::LookupSpan;
+extern crate alloc;
 
+#[cfg(feature = "std")]
 pub use tracing_subscriber::fmt::time::FormatTime;
 
+#[cfg(feature = "std")]
+mod capture;
+#[cfg(feature = "std")]
+mod context;
+#[cfg(feature = "std")]
+mod debug_shape;
+#[cfg(feature = "std")]
+mod layer;
+#[cfg(feature = "std")]
+mod level;
+#[cfg(feature = "std")]
+mod make_writer;
+#[cfg(feature = "std")]
+mod metrics;
+#[cfg(feature = "msgpack")]
+mod msgpack;
+#[cfg(feature = "opentelemetry")]
+mod otel;
+#[cfg(feature = "std")]
+mod span;
+#[cfg(all(tracing_unstable, feature = "valuable"))]
+mod structured;
+#[cfg(feature = "std")]
 mod visitor;
 
 #[cfg(feature = "_bench_internals")]
@@ -89,525 +291,27 @@ pub mod writer;
 #[cfg(not(feature = "_bench_internals"))]
 mod writer;
 
-use visitor::JsonVisitor;
-use writer::JsonWriter;
-
-/// A timestamp formatter that produces RFC 3339 timestamps with microsecond
-/// precision in UTC (e.g. `2026-02-20T12:00:00.000000Z`).
-///
-/// This is the default timer used by [`JsonLayer`]. It uses a hand-written
-/// formatter for minimal overhead — no chrono or time crate required.
-pub struct SystemTimestamp;
-
-impl FormatTime for SystemTimestamp {
-    fn format_time(&self, w: &mut FmtWriter<'_>) -> std::fmt::Result {
-        write_timestamp(SystemTime::now(), w)
-    }
-}
-
-// Extension type stored in span data
-struct SpanFields(Vec<u8>);
-
-thread_local! {
-    static EVENT_BUF: Cell<Vec<u8>> = const { Cell::new(Vec::new()) };
-}
-
-/// A [`tracing_subscriber::Layer`] that formats events as JSON lines.
-///
-/// See the [crate-level docs](crate) for configuration options and output
-/// format details.
-pub struct JsonLayer<W, T = SystemTimestamp> {
-    make_writer: W,
-    timer: T,
-    display_target: bool,
-    display_filename: bool,
-    display_line_number: bool,
-    display_thread_id: bool,
-    display_thread_name: bool,
-    flatten_event: bool,
-    buf_cap_limit: usize,
-}
-
-impl<W, T> JsonLayer<W, T> {
-    const DEFAULT_BUF_CAPACITY: usize = 256;
-    const DEFAULT_BUF_CAP_LIMIT: usize = 4096;
-}
-
-impl<W> JsonLayer<W>
-where
-    W: for<'w> tracing_subscriber::fmt::MakeWriter<'w> + 'static,
-{
-    /// Create a new `JsonLayer` that writes JSON lines to `make_writer`.
-    ///
-    /// Accepts anything implementing [`tracing_subscriber::fmt::MakeWriter`],
-    /// e.g. `std::io::stderr` or `std::io::stdout`.
-    pub fn new(make_writer: W) -> Self {
-        Self {
-            make_writer,
-            timer: SystemTimestamp,
-            display_target: true,
-            display_filename: false,
-            display_line_number: false,
-            display_thread_id: false,
-            display_thread_name: false,
-            flatten_event: false,
-            buf_cap_limit: Self::DEFAULT_BUF_CAP_LIMIT,
-        }
-    }
-}
-
-impl<W, T> JsonLayer<W, T>
-where
-    W: for<'w> tracing_subscriber::fmt::MakeWriter<'w> + 'static,
-{
-    /// Set whether the `target` field (module path) is included in output.
-    ///
-    /// Default: **`true`**.
-    pub fn with_target(mut self, display_target: bool) -> Self {
-        self.display_target = display_target;
-        self
-    }
-
-    /// Set whether the `filename` field is included in output.
-    ///
-    /// Default: **`false`**.
-    pub fn with_file(mut self, display_filename: bool) -> Self {
-        self.display_filename = display_filename;
-        self
-    }
-
-    /// Set whether the `line_number` field is included in output.
-    ///
-    /// Default: **`false`**.
-    pub fn with_line_number(mut self, display_line: bool) -> Self {
-        self.display_line_number = display_line;
-        self
-    }
-
-    /// Set whether the `threadId` field is included in output.
-    ///
-    /// Default: **`false`**.
-    pub fn with_thread_ids(mut self, display_thread_id: bool) -> Self {
-        self.display_thread_id = display_thread_id;
-        self
-    }
-
-    /// Set whether the `threadName` field is included in output.
-    ///
-    /// Default: **`false`**.
-    pub fn with_thread_names(mut self, display_thread_name: bool) -> Self {
-        self.display_thread_name = display_thread_name;
-        self
-    }
-
-    /// Set whether event fields are flattened to the top level of the JSON
-    /// object instead of being nested under a `"fields"` key.
-    ///
-    /// Default: **`false`** (fields are nested).
-    pub fn flatten_event(mut self, flatten: bool) -> Self {
-        self.flatten_event = flatten;
-        self
-    }
-
-    /// Set the capacity threshold at which the per-thread formatting buffer
-    /// is shrunk back to its default size after each event.
-    ///
-    /// The formatting buffer is reused across events on the same thread to
-    /// avoid allocations. If an unusually large event grows the buffer beyond
-    /// this limit, it is shrunk back to 256 bytes after that event to reclaim
-    /// memory.
-    ///
-    /// Default: **4096** bytes.
-    pub fn with_buffer_capacity_limit(mut self, limit: usize) -> Self {
-        self.buf_cap_limit = limit;
-        self
-    }
-
-    /// Use a custom [`FormatTime`] implementation for timestamps.
-    ///
-    /// This replaces the default [`SystemTimestamp`] formatter. Any type
-    /// implementing [`FormatTime`] can be used, including those from
-    /// `tracing-subscriber` such as `Uptime` and `ChronoUtc`.
-    ///
-    /// Pass `()` to disable timestamps entirely (equivalent to
-    /// [`without_time`](Self::without_time)).
-    pub fn with_timer<T2: FormatTime>(self, timer: T2) -> JsonLayer<W, T2> {
-        JsonLayer {
-            make_writer: self.make_writer,
-            timer,
-            display_target: self.display_target,
-            display_filename: self.display_filename,
-            display_line_number: self.display_line_number,
-            display_thread_id: self.display_thread_id,
-            display_thread_name: self.display_thread_name,
-            flatten_event: self.flatten_event,
-            buf_cap_limit: self.buf_cap_limit,
-        }
-    }
-
-    /// Disable timestamps in the output.
-    ///
-    /// This is a convenience for `self.with_timer(())`.
-    pub fn without_time(self) -> JsonLayer<W, ()> {
-        self.with_timer(())
-    }
-}
-
-impl<S, W, T> Layer<S> for JsonLayer<W, T>
-where
-    S: Subscriber + for<'a> LookupSpan<'a>,
-    W: for<'w> tracing_subscriber::fmt::MakeWriter<'w> + 'static,
-    T: FormatTime + 'static,
-{
-    fn on_new_span(
-        &self,
-        attrs: &tracing_core::span::Attributes<'_>,
-        id: &tracing_core::span::Id,
-        ctx: Context<'_, S>,
-    ) {
-        let span = match ctx.span(id) {
-            Some(s) => s,
-            None => return,
-        };
-        let mut jw = JsonWriter::new();
-        let mut visitor = JsonVisitor::new(&mut jw);
-        attrs.record(&mut visitor);
-        span.extensions_mut().insert(SpanFields(jw.into_vec()));
-    }
-
-    fn on_record(
-        &self,
-        id: &tracing_core::span::Id,
-        values: &tracing_core::span::Record<'_>,
-        ctx: Context<'_, S>,
-    ) {
-        let span = match ctx.span(id) {
-            Some(s) => s,
-            None => return,
-        };
-        let mut ext = span.extensions_mut();
-        if let Some(fields) = ext.get_mut::<SpanFields>() {
-            let has_existing = !fields.0.is_empty();
-            let mut jw = JsonWriter::continuing(&fields.0);
-            let mut visitor = if has_existing {
-                JsonVisitor::continuing(&mut jw)
-            } else {
-                JsonVisitor::new(&mut jw)
-            };
-            values.record(&mut visitor);
-            fields.0 = jw.into_vec();
-        }
-    }
-
-    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
-        EVENT_BUF.with(|cell| {
-            let mut buf = cell.take();
-            buf.clear();
-            let mut jw = JsonWriter::from_vec(buf);
-
-            jw.obj_start();
-
-            // Timestamp (absent when timer is `()` / `without_time()`).
-            // Written directly into the JsonWriter via fmt::Write to avoid a
-            // temporary String allocation. The value is NOT JSON-escaped;
-            // FormatTime implementations are expected to produce only
-            // printable ASCII (digits, dashes, colons, etc.).
-            let wrote_timestamp = {
-                let rollback = jw.len();
-                jw.raw(b"\"timestamp\":\"");
-                let val_start = jw.len();
-                {
-                    let mut fw = FmtWriter::new(&mut jw);
-                    let _ = self.timer.format_time(&mut fw);
-                }
-                if jw.len() > val_start {
-                    jw.push_byte(b'"');
-                    true
-                } else {
-                    jw.truncate(rollback);
-                    false
-                }
-            };
-
-            // level
-            if wrote_timestamp {
-                jw.comma();
-            }
-            jw.key("level");
-            jw.val_str(event.metadata().level().as_str());
-
-            if self.flatten_event {
-                // Event fields flattened to top level
-                let mut visitor = JsonVisitor::continuing(&mut jw);
-                event.record(&mut visitor);
-            } else {
-                // Event fields nested under "fields"
-                jw.comma();
-                jw.key("fields");
-                jw.obj_start();
-                let mut visitor = JsonVisitor::new(&mut jw);
-                event.record(&mut visitor);
-                jw.obj_end();
-            }
-
-            // target
-            if self.display_target {
-                jw.comma();
-                jw.key("target");
-                jw.val_str(event.metadata().target());
-            }
-
-            // filename
-            if self.display_filename
-                && let Some(file) = event.metadata().file()
-            {
-                jw.comma();
-                jw.key("filename");
-                jw.val_str(file);
-            }
-
-            // line_number
-            if self.display_line_number
-                && let Some(line) = event.metadata().line()
-            {
-                jw.comma();
-                jw.key("line_number");
-                jw.val_u64(line as u64);
-            }
-
-            // thread ID
-            if self.display_thread_id {
-                jw.comma();
-                jw.key("threadId");
-                jw.val_debug(&std::thread::current().id());
-            }
-
-            // thread name
-            if self.display_thread_name {
-                jw.comma();
-                jw.key("threadName");
-                if let Some(name) = std::thread::current().name() {
-                    jw.val_str(name);
-                } else {
-                    jw.val_str("");
-                }
-            }
-
-            // current span and spans list
-            if let Some(scope) = ctx.event_scope(event) {
-                let spans: Vec<_> = scope.collect();
-
-                // "span" = innermost (first in iterator = closest to current)
-                if let Some(leaf) = spans.first() {
-                    jw.comma();
-                    jw.key("span");
-                    jw.obj_start();
-                    jw.key("name");
-                    jw.val_str(leaf.name());
-                    let ext = leaf.extensions();
-                    if let Some(fields) = ext.get::<SpanFields>()
-                        && !fields.0.is_empty()
-                    {
-                        jw.comma();
-                        jw.raw(&fields.0);
-                    }
-                    jw.obj_end();
-                }
-
-                // "spans" = all spans from root to leaf
-                jw.comma();
-                jw.key("spans");
-                jw.arr_start();
-                for (i, span) in spans.iter().rev().enumerate() {
-                    if i > 0 {
-                        jw.comma();
-                    }
-                    jw.obj_start();
-                    jw.key("name");
-                    jw.val_str(span.name());
-                    let ext = span.extensions();
-                    if let Some(fields) = ext.get::<SpanFields>()
-                        && !fields.0.is_empty()
-                    {
-                        jw.comma();
-                        jw.raw(&fields.0);
-                    }
-                    jw.obj_end();
-                }
-                jw.arr_end();
-            }
-
-            jw.obj_end();
-            jw.finish_line();
-
-            let mut writer = self.make_writer.make_writer();
-            let _ = writer.write_all(jw.as_bytes());
-
-            // Return buffer for reuse, shrinking if an outlier event grew it
-            let mut buf = jw.into_vec();
-            if buf.capacity() > self.buf_cap_limit {
-                buf.shrink_to(Self::DEFAULT_BUF_CAPACITY);
-            }
-            cell.set(buf);
-        });
-    }
-}
-
-/// Write a `SystemTime` as RFC 3339 with microsecond precision in UTC directly
-/// into any `fmt::Write` sink, avoiding an intermediate `String` allocation.
-fn write_timestamp(t: SystemTime, w: &mut impl std::fmt::Write) -> std::fmt::Result {
-    let dur = t.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
-    let secs = dur.as_secs();
-    let micros = dur.subsec_micros();
-
-    let (year, month, day, hour, min, sec) = secs_to_datetime(secs);
-
-    write!(
-        w,
-        "{year:04}-{month:02}-{day:02}T{hour:02}:{min:02}:{sec:02}.{micros:06}Z"
-    )
-}
-
-/// Format a `SystemTime` as RFC 3339 with microsecond precision in UTC.
-/// e.g. "2026-02-20T12:00:00.000000Z"
-#[cfg(test)]
-fn format_timestamp(t: SystemTime) -> String {
-    let mut buf = String::with_capacity(27);
-    write_timestamp(t, &mut buf).unwrap();
-    buf
-}
-
-/// Convert Unix seconds to (year, month, day, hour, min, sec) in UTC.
-fn secs_to_datetime(secs: u64) -> (u64, u64, u64, u64, u64, u64) {
-    let sec = secs % 60;
-    let mins = secs / 60;
-    let min = mins % 60;
-    let hours = mins / 60;
-    let hour = hours % 24;
-    let days = hours / 24;
-
-    // Compute year, month, day from days since epoch (1970-01-01)
-    let (year, month, day) = days_to_ymd(days);
-
-    (year, month, day, hour, min, sec)
-}
-
-fn days_to_ymd(days: u64) -> (u64, u64, u64) {
-    // Using the algorithm from civil_from_days (Howard Hinnant's date algorithms)
-    let z = days + 719468;
-    let era = z / 146097;
-    let doe = z % 146097;
-    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
-    let y = yoe + era * 400;
-    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
-    let mp = (5 * doy + 2) / 153;
-    let d = doy - (153 * mp + 2) / 5 + 1;
-    let m = if mp < 10 { mp + 3 } else { mp - 9 };
-    let y = if m <= 2 { y + 1 } else { y };
-    (y, m, d)
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    /// Convert a JsonWriter to a String for test assertions.
-    fn to_string(jw: JsonWriter) -> String {
-        String::from_utf8(jw.into_vec()).unwrap()
-    }
-
-    /// Helper: write a string through val_str and return the raw buffer content.
-    fn val_str_output(s: &str) -> String {
-        let mut jw = JsonWriter::new();
-        jw.val_str(s);
-        to_string(jw)
-    }
-
-    #[test]
-    fn test_val_str_basic() {
-        assert_eq!(val_str_output("hello"), r#""hello""#);
-        assert_eq!(val_str_output("say \"hi\""), r#""say \"hi\"""#);
-        assert_eq!(val_str_output("back\\slash"), r#""back\\slash""#);
-        assert_eq!(val_str_output(""), r#""""#);
-    }
-
-    #[test]
-    fn test_val_str_control_chars() {
-        assert_eq!(val_str_output("\n"), r#""\n""#);
-        assert_eq!(val_str_output("\r"), r#""\r""#);
-        assert_eq!(val_str_output("\t"), r#""\t""#);
-        assert_eq!(val_str_output("\x08"), r#""\b""#);
-        assert_eq!(val_str_output("\x0C"), r#""\f""#);
-        // U+0001 → \u0001
-        assert_eq!(val_str_output("\x01"), r#""\u0001""#);
-        assert_eq!(val_str_output("\x1F"), r#""\u001f""#);
-    }
-
-    #[test]
-    fn test_val_str_unicode_passthrough() {
-        // Non-ASCII but above U+001F should pass through unescaped
-        assert_eq!(val_str_output("café"), "\"café\"");
-        assert_eq!(val_str_output("日本語"), "\"日本語\"");
-    }
-
-    #[test]
-    fn test_f64_edge_cases() {
-        let mut jw = JsonWriter::new();
-        jw.val_f64(f64::NAN);
-        assert_eq!(to_string(jw), "null");
-
-        let mut jw = JsonWriter::new();
-        jw.val_f64(f64::INFINITY);
-        assert_eq!(to_string(jw), "null");
-
-        let mut jw = JsonWriter::new();
-        jw.val_f64(f64::NEG_INFINITY);
-        assert_eq!(to_string(jw), "null");
-
-        let mut jw = JsonWriter::new();
-        jw.val_f64(-0.0_f64);
-        let s = to_string(jw);
-        // -0.0 should be written as a number (not null)
-        assert!(
-            s == "-0" || s == "0" || s == "-0.0" || s == "0.0",
-            "got: {s}"
-        );
-
-        let mut jw = JsonWriter::new();
-        jw.val_f64(2.78);
-        let s = to_string(jw);
-        assert!(s.contains("2.78"), "got: {s}");
-    }
-
-    #[test]
-    fn test_timestamp_format() {
-        // Test known SystemTime value: Unix epoch
-        let epoch = SystemTime::UNIX_EPOCH;
-        let s = format_timestamp(epoch);
-        assert_eq!(s, "1970-01-01T00:00:00.000000Z");
-
-        // Test another known value: 2026-02-20T12:00:00Z = 1771588800 seconds
-        let t = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1771588800);
-        let s = format_timestamp(t);
-        assert_eq!(s, "2026-02-20T12:00:00.000000Z");
-    }
-
-    #[test]
-    fn test_timestamp_microsecond_precision() {
-        // 2026-02-20T12:00:00Z + 123456 µs → .123456
-        let t = SystemTime::UNIX_EPOCH
-            + std::time::Duration::from_micros(1_771_588_800 * 1_000_000 + 123_456);
-        let s = format_timestamp(t);
-        assert_eq!(s, "2026-02-20T12:00:00.123456Z");
-
-        // Exactly 1 µs past epoch
-        let t = SystemTime::UNIX_EPOCH + std::time::Duration::from_micros(1);
-        let s = format_timestamp(t);
-        assert_eq!(s, "1970-01-01T00:00:00.000001Z");
-
-        // 999999 µs (all six digits occupied)
-        let t = SystemTime::UNIX_EPOCH + std::time::Duration::from_micros(999_999);
-        let s = format_timestamp(t);
-        assert_eq!(s, "1970-01-01T00:00:00.999999Z");
-    }
-}
+#[cfg(feature = "std")]
+pub use capture::{Bytes, BytesEncoding, DurationField, DurationFormat};
+#[cfg(feature = "std")]
+pub use context::{ContextGuard, push_context};
+#[cfg(feature = "std")]
+pub use layer::{ClockTimestamp, JsonLayer, SystemTimestamp, TargetTransform};
+#[cfg(feature = "std")]
+pub use level::{LevelHandle, LevelStyle};
+#[cfg(feature = "std")]
+pub use make_writer::{
+    BufferedMakeWriter, BufferedWriterGuard, SharedWriter, SharedWriterGuard, SingleThreadWriter,
+    SingleThreadWriterGuard,
+};
+#[cfg(feature = "std")]
+pub use metrics::{LayerMetrics, MetricsSnapshot};
+#[cfg(feature = "msgpack")]
+pub use msgpack::MsgPackLayer;
+#[cfg(feature = "std")]
+pub use span::SpanShape;
+#[cfg(feature = "std")]
+pub use tracing_core::{Level, LevelFilter};
+#[cfg(feature = "std")]
+pub use visitor::JsonVisitor;
+pub use writer::{Checkpoint, JsonWriter, LineEnding, escape_json};
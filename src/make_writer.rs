@@ -0,0 +1,304 @@
+use std::cell::{RefCell, RefMut};
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::ThreadId;
+use tracing_subscriber::fmt::MakeWriter;
+
+/// A [`MakeWriter`] over a shared, mutex-protected [`Write`] implementation.
+///
+/// Created by [`JsonLayer::from_writer`](crate::JsonLayer::from_writer), or
+/// directly from an `Arc<Mutex<W>>` the application already owns. The mutex
+/// is locked once per event, only for the duration of the write of that
+/// event's line, so lines from different threads are never interleaved.
+///
+/// A poisoned mutex is recovered rather than propagated: logging never
+/// panics because another thread panicked while holding the lock.
+pub struct SharedWriter<W> {
+    inner: Arc<Mutex<W>>,
+}
+
+impl<W> SharedWriter<W> {
+    /// Wrap an already-shared writer.
+    pub fn new(inner: Arc<Mutex<W>>) -> Self {
+        Self { inner }
+    }
+}
+
+impl<W> Clone for SharedWriter<W> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<W> From<Arc<Mutex<W>>> for SharedWriter<W> {
+    fn from(inner: Arc<Mutex<W>>) -> Self {
+        Self::new(inner)
+    }
+}
+
+impl<W> From<W> for SharedWriter<W>
+where
+    W: Write,
+{
+    fn from(writer: W) -> Self {
+        Self::new(Arc::new(Mutex::new(writer)))
+    }
+}
+
+impl<'a, W> MakeWriter<'a> for SharedWriter<W>
+where
+    W: Write + 'a,
+{
+    type Writer = SharedWriterGuard<'a, W>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        let guard = self
+            .inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        SharedWriterGuard(guard)
+    }
+}
+
+/// The per-event writer returned by [`SharedWriter`], holding its lock.
+pub struct SharedWriterGuard<'a, W>(MutexGuard<'a, W>);
+
+impl<W: Write> Write for SharedWriterGuard<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.0.write_all(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// A [`MakeWriter`] that collects lines in memory and writes them to the
+/// inner writer in batches, to save a system call per event.
+///
+/// Lines are appended to a buffer of `capacity` bytes; once it is full, or
+/// holds as many lines as set with [`with_max_lines`](Self::with_max_lines),
+/// they are written to the inner writer together. Whatever is still
+/// buffered is written, and the writer flushed, when the
+/// `BufferedMakeWriter` is dropped, i.e. when the subscriber owning the
+/// layer is. Until then the last lines may sit in memory, so they are lost
+/// if the process aborts.
+///
+/// If the inner writer fails, the lines it did not take stay buffered and
+/// are retried with the next batch; the error is reported for the event
+/// whose line started the write. While the writer keeps failing, the
+/// buffer keeps growing.
+///
+/// ```rust
+/// use tracing_microjson::{BufferedMakeWriter, JsonLayer};
+///
+/// let file = std::io::sink(); // e.g. a `File`
+/// let layer = JsonLayer::new(BufferedMakeWriter::new(file, 8192));
+/// ```
+///
+/// Like [`SharedWriter`], the buffer is behind a mutex locked once per
+/// event, so it can be used from any thread and lines are never
+/// interleaved. [`JsonLayer::with_flush`](crate::JsonLayer::with_flush)
+/// writes the buffer out on every event, which undoes the batching.
+pub struct BufferedMakeWriter<W: Write> {
+    inner: Mutex<Buffered<W>>,
+}
+
+struct Buffered<W> {
+    writer: W,
+    buf: Vec<u8>,
+    capacity: usize,
+    /// Lines in `buf`, counted as calls to `write_all`.
+    lines: usize,
+    max_lines: usize,
+}
+
+impl<W: Write> BufferedMakeWriter<W> {
+    /// Wrap `writer`, writing to it once at least `capacity` bytes are
+    /// buffered.
+    pub fn new(writer: W, capacity: usize) -> Self {
+        Self {
+            inner: Mutex::new(Buffered {
+                writer,
+                buf: Vec::with_capacity(capacity),
+                capacity,
+                lines: 0,
+                max_lines: usize::MAX,
+            }),
+        }
+    }
+
+    /// Also write the buffer out once it holds `max_lines` lines, however
+    /// few bytes they take, to bound how far the output lags behind.
+    ///
+    /// Default: no limit.
+    pub fn with_max_lines(mut self, max_lines: usize) -> Self {
+        let inner = self.inner.get_mut().unwrap_or_else(|e| e.into_inner());
+        inner.max_lines = max_lines;
+        self
+    }
+}
+
+impl<W: Write> Buffered<W> {
+    /// Write out the buffer, keeping whatever the writer did not take if it
+    /// fails, like `BufWriter`.
+    fn write_buffered(&mut self) -> io::Result<()> {
+        let mut written = 0;
+        let result = loop {
+            if written == self.buf.len() {
+                break Ok(());
+            }
+            match self.writer.write(&self.buf[written..]) {
+                Ok(0) => break Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => written += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => break Err(e),
+            }
+        };
+        self.buf.drain(..written);
+        if self.buf.is_empty() {
+            self.lines = 0;
+        }
+        result
+    }
+}
+
+impl<W: Write> Drop for BufferedMakeWriter<W> {
+    fn drop(&mut self) {
+        let inner = self.inner.get_mut().unwrap_or_else(|e| e.into_inner());
+        let _ = inner.write_buffered();
+        let _ = inner.writer.flush();
+    }
+}
+
+impl<'a, W> MakeWriter<'a> for BufferedMakeWriter<W>
+where
+    W: Write + 'a,
+{
+    type Writer = BufferedWriterGuard<'a, W>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        let guard = self
+            .inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        BufferedWriterGuard(guard)
+    }
+}
+
+/// The per-event writer returned by [`BufferedMakeWriter`], holding its
+/// lock.
+pub struct BufferedWriterGuard<'a, W>(MutexGuard<'a, Buffered<W>>);
+
+impl<W: Write> Write for BufferedWriterGuard<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        let inner = &mut *self.0;
+        inner.buf.extend_from_slice(buf);
+        inner.lines += 1;
+        if inner.buf.len() >= inner.capacity || inner.lines >= inner.max_lines {
+            inner.write_buffered()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.write_buffered()?;
+        self.0.writer.flush()
+    }
+}
+
+/// A lock-free [`MakeWriter`] for programs that only log from one thread.
+///
+/// Created by [`JsonLayer::single_threaded`](crate::JsonLayer::single_threaded).
+/// The writer is owned directly, without a mutex, and is bound to the thread
+/// that created it. Each event checks that it is running on that thread and
+/// borrows the writer; no lock is taken.
+///
+/// Events emitted from any other thread are **discarded**, as are events
+/// emitted re-entrantly while a line is being written (for example, by the
+/// writer itself logging). Their writes fail, so they are counted by
+/// [`JsonLayer::dropped_lines`](crate::JsonLayer::dropped_lines) and passed
+/// to any
+/// [`with_write_error_handler`](crate::JsonLayer::with_write_error_handler).
+/// Use [`SharedWriter`] if other threads may log.
+pub struct SingleThreadWriter<W> {
+    inner: RefCell<W>,
+    owner: ThreadId,
+}
+
+impl<W> SingleThreadWriter<W> {
+    /// Wrap `writer`, binding it to the current thread.
+    pub fn new(writer: W) -> Self {
+        Self {
+            inner: RefCell::new(writer),
+            owner: std::thread::current().id(),
+        }
+    }
+}
+
+// SAFETY: the `RefCell` is only ever borrowed on the `owner` thread (checked
+// in `make_writer` before borrowing), and the returned borrow is `!Send`, so
+// it is never accessed from two threads. `W: Send` is required because the
+// writer may be dropped on a different thread than the one it was used on.
+unsafe impl<W: Send> Sync for SingleThreadWriter<W> {}
+
+impl<'a, W> MakeWriter<'a> for SingleThreadWriter<W>
+where
+    W: Write + 'a,
+{
+    type Writer = SingleThreadWriterGuard<'a, W>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        let borrow = if std::thread::current().id() == self.owner {
+            self.inner.try_borrow_mut().ok()
+        } else {
+            None
+        };
+        SingleThreadWriterGuard(borrow)
+    }
+}
+
+/// The per-event writer returned by [`SingleThreadWriter`]. Writes fail
+/// when the event was not on the owning thread, or was re-entrant.
+pub struct SingleThreadWriterGuard<'a, W>(Option<RefMut<'a, W>>);
+
+fn discarded() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::WouldBlock,
+        "single-threaded writer used from another thread or re-entrantly",
+    )
+}
+
+impl<W: Write> Write for SingleThreadWriterGuard<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.0 {
+            Some(w) => w.write(buf),
+            None => Err(discarded()),
+        }
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        match &mut self.0 {
+            Some(w) => w.write_all(buf),
+            None => Err(discarded()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.0 {
+            Some(w) => w.flush(),
+            None => Ok(()),
+        }
+    }
+}
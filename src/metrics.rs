@@ -0,0 +1,73 @@
+use crate::level;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tracing_core::Level;
+
+/// Counters of what a [`JsonLayer`](crate::JsonLayer) has written, for
+/// monitoring the layer itself.
+///
+/// Shared with a layer through
+/// [`JsonLayer::with_metrics`](crate::JsonLayer::with_metrics), which
+/// updates it after each line is written; read it at any time with
+/// [`snapshot`](Self::snapshot). One `LayerMetrics` may be shared by
+/// several layers to count their output together.
+#[derive(Debug, Default)]
+pub struct LayerMetrics {
+    /// Lines written per level, indexed by ordinal.
+    events: [AtomicU64; 5],
+    bytes: AtomicU64,
+}
+
+impl LayerMetrics {
+    /// Create a set of counters, all zero.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count a line at `level` of `bytes` bytes.
+    pub(crate) fn record(&self, level: &Level, bytes: usize) {
+        self.events[level::ordinal(level) as usize].fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// The current counts.
+    ///
+    /// Each counter is read separately, so a snapshot taken while events
+    /// are being written may be a line behind in some of them.
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let [trace, debug, info, warn, error] =
+            self.events.each_ref().map(|n| n.load(Ordering::Relaxed));
+        MetricsSnapshot {
+            trace,
+            debug,
+            info,
+            warn,
+            error,
+            bytes: self.bytes.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// The counts in a [`LayerMetrics`] at one point in time.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct MetricsSnapshot {
+    /// Lines written for `TRACE` events.
+    pub trace: u64,
+    /// Lines written for `DEBUG` events.
+    pub debug: u64,
+    /// Lines written for `INFO` events.
+    pub info: u64,
+    /// Lines written for `WARN` events.
+    pub warn: u64,
+    /// Lines written for `ERROR` events.
+    pub error: u64,
+    /// Total bytes written, including line endings.
+    pub bytes: u64,
+}
+
+impl MetricsSnapshot {
+    /// Lines written at any level.
+    pub fn events(&self) -> u64 {
+        self.trace + self.debug + self.info + self.warn + self.error
+    }
+}
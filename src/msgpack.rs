@@ -0,0 +1,386 @@
+use crate::SystemTimestamp;
+use crate::span::SpanFields;
+use std::cell::Cell;
+use std::fmt::{self, Write as _};
+use std::io::Write;
+use tracing_core::field::{Field, Visit};
+use tracing_core::{Event, Subscriber};
+use tracing_subscriber::Layer;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::fmt::format::Writer as FmtWriter;
+use tracing_subscriber::fmt::time::FormatTime;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::{LookupSpan, SpanRef};
+
+thread_local! {
+    static EVENT_BUF: Cell<Vec<u8>> = const { Cell::new(Vec::new()) };
+}
+
+/// A [`tracing_subscriber::Layer`] that writes events as [MessagePack] maps,
+/// the binary counterpart of [`JsonLayer`](crate::JsonLayer).
+///
+/// Each event is written as one MessagePack map with the same keys and
+/// nesting as `JsonLayer`'s default output: `timestamp`, `level`, a `fields`
+/// map, `target`, and, inside spans, a `span` map and a `spans` array.
+/// Values keep their types: integers and floats are MessagePack numbers,
+/// booleans are booleans, and `Debug`/`Display` values are strings. 128-bit
+/// integers are written as decimal strings, as in the JSON output.
+///
+/// Maps and arrays are written as they are visited and their lengths
+/// patched afterwards, so encoding streams into a reused buffer like the
+/// JSON path does. Events are written back to back without separators; a
+/// MessagePack decoder reads them as a sequence of values.
+///
+/// Requires the `msgpack` feature, which adds no dependencies.
+///
+/// ```rust
+/// use tracing_microjson::MsgPackLayer;
+/// use tracing_subscriber::prelude::*;
+///
+/// tracing_subscriber::registry()
+///     .with(MsgPackLayer::new(std::io::stdout))
+///     .init();
+/// ```
+///
+/// [MessagePack]: https://msgpack.org
+pub struct MsgPackLayer<W, T = SystemTimestamp> {
+    make_writer: W,
+    timer: T,
+    display_target: bool,
+}
+
+impl<W> MsgPackLayer<W>
+where
+    W: for<'w> MakeWriter<'w> + 'static,
+{
+    /// Create a new `MsgPackLayer` that writes to `make_writer`.
+    pub fn new(make_writer: W) -> Self {
+        Self {
+            make_writer,
+            timer: SystemTimestamp,
+            display_target: true,
+        }
+    }
+}
+
+impl<W, T> MsgPackLayer<W, T> {
+    /// Set whether the event target is included.
+    ///
+    /// Default: **`true`**.
+    pub fn with_target(mut self, display_target: bool) -> Self {
+        self.display_target = display_target;
+        self
+    }
+
+    /// Use a custom [`FormatTime`] implementation for the `timestamp` string.
+    pub fn with_timer<T2: FormatTime>(self, timer: T2) -> MsgPackLayer<W, T2> {
+        MsgPackLayer {
+            make_writer: self.make_writer,
+            timer,
+            display_target: self.display_target,
+        }
+    }
+
+    /// Disable timestamps in the output.
+    pub fn without_time(self) -> MsgPackLayer<W, ()> {
+        self.with_timer(())
+    }
+}
+
+/// Span fields stored for [`MsgPackLayer`], kept apart from `JsonLayer`'s so
+/// both layers can be installed in the same subscriber.
+struct MsgPackSpanFields(SpanFields);
+
+impl<S, W, T> Layer<S> for MsgPackLayer<W, T>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + 'static,
+    T: FormatTime + 'static,
+{
+    fn on_new_span(
+        &self,
+        attrs: &tracing_core::span::Attributes<'_>,
+        id: &tracing_core::span::Id,
+        ctx: Context<'_, S>,
+    ) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut fields = SpanFields::default();
+        attrs.record(&mut fields);
+        span.extensions_mut().insert(MsgPackSpanFields(fields));
+    }
+
+    fn on_record(
+        &self,
+        id: &tracing_core::span::Id,
+        values: &tracing_core::span::Record<'_>,
+        ctx: Context<'_, S>,
+    ) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut ext = span.extensions_mut();
+        if let Some(fields) = ext.get_mut::<MsgPackSpanFields>() {
+            values.record(&mut fields.0);
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        EVENT_BUF.with(|cell| {
+            let mut buf = cell.take();
+            buf.clear();
+            let mut w = MsgPackWriter { buf };
+
+            let top = w.begin_map();
+            let mut entries = 0;
+
+            let rollback = w.buf.len();
+            w.str("timestamp");
+            let written = w.str_with(|s| self.timer.format_time(&mut FmtWriter::new(s)));
+            if written > 0 {
+                entries += 1;
+            } else {
+                w.buf.truncate(rollback);
+            }
+
+            w.str("level");
+            w.str(event.metadata().level().as_str());
+            entries += 1;
+
+            w.str("fields");
+            let fields = w.begin_map();
+            let mut visitor = MsgPackVisitor::new(&mut w);
+            event.record(&mut visitor);
+            let count = visitor.count;
+            w.end_map(fields, count);
+            entries += 1;
+
+            if self.display_target {
+                w.str("target");
+                w.str(event.metadata().target());
+                entries += 1;
+            }
+
+            if let Some(leaf) = ctx.event_scope(event).and_then(|mut scope| scope.next()) {
+                w.str("span");
+                write_span(&mut w, &leaf);
+                entries += 1;
+
+                w.str("spans");
+                let spans = w.begin_array();
+                let mut count = 0;
+                for span in leaf.scope().from_root() {
+                    write_span(&mut w, &span);
+                    count += 1;
+                }
+                w.end_array(spans, count);
+                entries += 1;
+            }
+
+            w.end_map(top, entries);
+
+            let mut writer = self.make_writer.make_writer_for(event.metadata());
+            let _ = writer.write_all(&w.buf);
+
+            cell.set(w.buf);
+        });
+    }
+}
+
+/// Write a span as a map of its name and recorded fields.
+fn write_span<S>(w: &mut MsgPackWriter, span: &SpanRef<'_, S>)
+where
+    S: for<'a> LookupSpan<'a>,
+{
+    let map = w.begin_map();
+    w.str("name");
+    w.str(span.name());
+    let mut count = 1;
+    if let Some(fields) = span.extensions().get::<MsgPackSpanFields>() {
+        let mut visitor = MsgPackVisitor::new(w);
+        fields.0.record(&mut visitor);
+        count += visitor.count;
+    }
+    w.end_map(map, count);
+}
+
+/// A [`Visit`] implementation that writes fields as MessagePack map entries,
+/// counting them so the enclosing map's length can be patched.
+struct MsgPackVisitor<'a> {
+    writer: &'a mut MsgPackWriter,
+    count: u32,
+}
+
+impl<'a> MsgPackVisitor<'a> {
+    fn new(writer: &'a mut MsgPackWriter) -> Self {
+        Self { writer, count: 0 }
+    }
+
+    fn key(&mut self, field: &Field) -> &mut MsgPackWriter {
+        self.count += 1;
+        self.writer.str(field.name());
+        self.writer
+    }
+}
+
+impl Visit for MsgPackVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.key(field).str(value);
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.key(field).uint(value);
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.key(field).int(value);
+    }
+
+    fn record_u128(&mut self, field: &Field, value: u128) {
+        self.key(field).str_with(|s| write!(s, "{value}"));
+    }
+
+    fn record_i128(&mut self, field: &Field, value: i128) {
+        self.key(field).str_with(|s| write!(s, "{value}"));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.key(field).f64(value);
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.key(field).bool(value);
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.key(field).str_with(|s| write!(s, "{value:?}"));
+    }
+
+    fn record_error(&mut self, field: &Field, value: &(dyn std::error::Error + 'static)) {
+        self.key(field).str_with(|s| write!(s, "{value}"));
+    }
+}
+
+/// A MessagePack encoder over a byte buffer.
+///
+/// Containers are opened with a 32-bit length placeholder and patched when
+/// closed; strings of unknown length likewise. Everything else uses the
+/// smallest encoding for the value.
+struct MsgPackWriter {
+    buf: Vec<u8>,
+}
+
+/// The offset of a container's length placeholder.
+struct Container(usize);
+
+impl MsgPackWriter {
+    fn bool(&mut self, value: bool) {
+        self.buf.push(if value { 0xc3 } else { 0xc2 });
+    }
+
+    fn uint(&mut self, value: u64) {
+        if value <= 0x7f {
+            self.buf.push(value as u8);
+        } else if let Ok(v) = u8::try_from(value) {
+            self.buf.extend_from_slice(&[0xcc, v]);
+        } else if let Ok(v) = u16::try_from(value) {
+            self.buf.push(0xcd);
+            self.buf.extend_from_slice(&v.to_be_bytes());
+        } else if let Ok(v) = u32::try_from(value) {
+            self.buf.push(0xce);
+            self.buf.extend_from_slice(&v.to_be_bytes());
+        } else {
+            self.buf.push(0xcf);
+            self.buf.extend_from_slice(&value.to_be_bytes());
+        }
+    }
+
+    fn int(&mut self, value: i64) {
+        if value >= 0 {
+            self.uint(value as u64);
+        } else if value >= -32 {
+            // negative fixint
+            self.buf.push(value as u8);
+        } else if let Ok(v) = i8::try_from(value) {
+            self.buf.extend_from_slice(&[0xd0, v as u8]);
+        } else if let Ok(v) = i16::try_from(value) {
+            self.buf.push(0xd1);
+            self.buf.extend_from_slice(&v.to_be_bytes());
+        } else if let Ok(v) = i32::try_from(value) {
+            self.buf.push(0xd2);
+            self.buf.extend_from_slice(&v.to_be_bytes());
+        } else {
+            self.buf.push(0xd3);
+            self.buf.extend_from_slice(&value.to_be_bytes());
+        }
+    }
+
+    fn f64(&mut self, value: f64) {
+        self.buf.push(0xcb);
+        self.buf.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn str(&mut self, value: &str) {
+        let len = value.len();
+        if len < 32 {
+            self.buf.push(0xa0 | len as u8);
+        } else if let Ok(len) = u8::try_from(len) {
+            self.buf.extend_from_slice(&[0xd9, len]);
+        } else if let Ok(len) = u16::try_from(len) {
+            self.buf.push(0xda);
+            self.buf.extend_from_slice(&len.to_be_bytes());
+        } else {
+            self.buf.push(0xdb);
+            self.buf.extend_from_slice(&(len as u32).to_be_bytes());
+        }
+        self.buf.extend_from_slice(value.as_bytes());
+    }
+
+    /// Write a string produced by `f` as a str32, patching its length
+    /// afterwards. Returns the length written.
+    fn str_with(&mut self, f: impl FnOnce(&mut StrSink<'_>) -> fmt::Result) -> usize {
+        let header = self.placeholder(0xdb);
+        let _ = f(&mut StrSink(&mut self.buf));
+        let len = self.buf.len() - header.0 - 4;
+        self.patch(header, len as u32);
+        len
+    }
+
+    fn begin_map(&mut self) -> Container {
+        self.placeholder(0xdf)
+    }
+
+    fn end_map(&mut self, map: Container, entries: u32) {
+        self.patch(map, entries);
+    }
+
+    fn begin_array(&mut self) -> Container {
+        self.placeholder(0xdd)
+    }
+
+    fn end_array(&mut self, array: Container, elements: u32) {
+        self.patch(array, elements);
+    }
+
+    fn placeholder(&mut self, marker: u8) -> Container {
+        self.buf.push(marker);
+        let offset = self.buf.len();
+        self.buf.extend_from_slice(&[0; 4]);
+        Container(offset)
+    }
+
+    fn patch(&mut self, container: Container, len: u32) {
+        self.buf[container.0..container.0 + 4].copy_from_slice(&len.to_be_bytes());
+    }
+}
+
+/// Appends formatted text to a [`MsgPackWriter`]'s buffer, unescaped.
+struct StrSink<'a>(&'a mut Vec<u8>);
+
+impl fmt::Write for StrSink<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.extend_from_slice(s.as_bytes());
+        Ok(())
+    }
+}
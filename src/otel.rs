@@ -0,0 +1,60 @@
+use crate::writer::JsonWriter;
+use tracing_core::{Event, Subscriber};
+use tracing_opentelemetry::OtelData;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+
+/// Write `"trace_id"` and `"span_id"` entries for the innermost span in the
+/// event's scope that carries a built OpenTelemetry context.
+///
+/// `tracing-opentelemetry`'s layer stores an [`OtelData`] in each span's
+/// extensions. Its context is built when the span is first entered (or when
+/// the context is requested explicitly); spans whose context has not been
+/// built yet, or whose ids are the all-zero invalid ids, are skipped in
+/// favour of their parents. Nothing is written outside of any such span.
+pub(crate) fn write_ids<S>(jw: &mut JsonWriter, event: &Event<'_>, ctx: &Context<'_, S>)
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let Some((trace_id, span_id)) = find_ids(event, ctx) else {
+        return;
+    };
+    jw.comma();
+    jw.key("trace_id");
+    jw.val_display(&format_args!("{:032x}", u128::from_be_bytes(trace_id)));
+    jw.comma();
+    jw.key("span_id");
+    jw.val_display(&format_args!("{:016x}", u64::from_be_bytes(span_id)));
+}
+
+/// Write the same ids as [`write_ids`] as Datadog's `"dd.trace_id"` and
+/// `"dd.span_id"`: decimal strings of the trace id's lower 64 bits, which
+/// is what Datadog keeps of a 128-bit trace id, and of the span id.
+pub(crate) fn write_datadog_ids<S>(jw: &mut JsonWriter, event: &Event<'_>, ctx: &Context<'_, S>)
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let Some((trace_id, span_id)) = find_ids(event, ctx) else {
+        return;
+    };
+    jw.comma();
+    jw.key("dd.trace_id");
+    jw.val_display(&(u128::from_be_bytes(trace_id) as u64));
+    jw.comma();
+    jw.key("dd.span_id");
+    jw.val_display(&u64::from_be_bytes(span_id));
+}
+
+/// The trace and span ids, as big-endian bytes, of the innermost span in
+/// the event's scope with a valid, built context.
+fn find_ids<S>(event: &Event<'_>, ctx: &Context<'_, S>) -> Option<([u8; 16], [u8; 8])>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    ctx.event_scope(event)?.find_map(|span| {
+        let ext = span.extensions();
+        let data = ext.get::<OtelData>()?;
+        let (trace_id, span_id) = (data.trace_id()?.to_bytes(), data.span_id()?.to_bytes());
+        (trace_id != [0; 16] && span_id != [0; 8]).then_some((trace_id, span_id))
+    })
+}
@@ -0,0 +1,171 @@
+use crate::capture::{self, Bytes, Captured, DurationField};
+use std::fmt::{self, Write as _};
+use std::time::Duration;
+use tracing_core::field::{Field, Visit};
+
+/// The shape of each element of the `"spans"` array.
+///
+/// Set with [`JsonLayer::with_span_element_shape`](crate::JsonLayer::with_span_element_shape).
+/// Only the `"spans"` list is affected; the `"span"` entry is always an
+/// object.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SpanShape {
+    /// `{"name":"req","user":"alice"}`, as tracing-subscriber writes it.
+    #[default]
+    Object,
+    /// Just the span name: `"req"`.
+    Name,
+    /// A two-element array of the name and an object of the remaining
+    /// entries: `["req",{"user":"alice"}]`.
+    Tuple,
+}
+
+/// Extension type stored in span data: the span's recorded fields.
+///
+/// Values are kept typed rather than pre-rendered so that they are written
+/// at event time through the same [`JsonVisitor`](crate::visitor::JsonVisitor)
+/// as event fields, and field options such as redaction apply to both.
+#[derive(Default)]
+pub(crate) struct SpanFields(Vec<(Field, SpanValue)>);
+
+/// Extension type stored in span data: when the span was created, as
+/// formatted by the layer's timer. Only stored with
+/// [`JsonLayer::with_span_open_time`](crate::JsonLayer::with_span_open_time).
+pub(crate) struct SpanOpenedAt(pub(crate) String);
+
+enum SpanValue {
+    Str(String),
+    U64(u64),
+    I64(i64),
+    U128(u128),
+    I128(i128),
+    F64(f64),
+    Bool(bool),
+    /// `Debug` output, formatted when the value was recorded.
+    Debug(String),
+    /// A [`Bytes`] value, kept so it is encoded at event time.
+    Bytes(Vec<u8>),
+    /// A [`DurationField`] value.
+    Duration(Duration),
+    Error(RecordedError),
+}
+
+impl SpanFields {
+    /// Replay the stored fields into `visitor`, in recording order.
+    pub(crate) fn record(&self, visitor: &mut dyn Visit) {
+        for (field, value) in &self.0 {
+            match value {
+                SpanValue::Str(v) => visitor.record_str(field, v),
+                SpanValue::U64(v) => visitor.record_u64(field, *v),
+                SpanValue::I64(v) => visitor.record_i64(field, *v),
+                SpanValue::U128(v) => visitor.record_u128(field, *v),
+                SpanValue::I128(v) => visitor.record_i128(field, *v),
+                SpanValue::F64(v) => visitor.record_f64(field, *v),
+                SpanValue::Bool(v) => visitor.record_bool(field, *v),
+                SpanValue::Debug(v) => visitor.record_debug(field, &Verbatim(v)),
+                SpanValue::Bytes(v) => visitor.record_debug(field, &Bytes(v)),
+                SpanValue::Duration(v) => visitor.record_debug(field, &DurationField(*v)),
+                SpanValue::Error(v) => visitor.record_error(field, v),
+            }
+        }
+    }
+
+    /// Store `value` for `field`, replacing the value of an earlier
+    /// recording of the same field, e.g. by `Span::record`, in place.
+    fn push(&mut self, field: &Field, value: SpanValue) {
+        match self.0.iter_mut().find(|(f, _)| f == field) {
+            Some((_, existing)) => *existing = value,
+            None => self.0.push((field.clone(), value)),
+        }
+    }
+}
+
+impl Visit for SpanFields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.push(field, SpanValue::Str(value.to_owned()));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.push(field, SpanValue::U64(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.push(field, SpanValue::I64(value));
+    }
+
+    fn record_u128(&mut self, field: &Field, value: u128) {
+        self.push(field, SpanValue::U128(value));
+    }
+
+    fn record_i128(&mut self, field: &Field, value: i128) {
+        self.push(field, SpanValue::I128(value));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.push(field, SpanValue::F64(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.push(field, SpanValue::Bool(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        let mut debug = String::new();
+        let captured = capture::capture(|| {
+            let _ = write!(debug, "{value:?}");
+        });
+        let value = match captured {
+            Some(Captured::Bytes(bytes)) if debug.is_empty() => SpanValue::Bytes(bytes.to_vec()),
+            Some(Captured::Duration(duration)) if debug.is_empty() => SpanValue::Duration(duration),
+            Some(_) => {
+                // A wrapper nested in a larger value: keep it plain
+                debug.clear();
+                let _ = write!(debug, "{value:?}");
+                SpanValue::Debug(debug)
+            }
+            None => SpanValue::Debug(debug),
+        };
+        self.push(field, value);
+    }
+
+    fn record_error(&mut self, field: &Field, value: &(dyn std::error::Error + 'static)) {
+        self.push(field, SpanValue::Error(RecordedError::new(value)));
+    }
+}
+
+/// Writes a previously formatted `Debug` string back out unchanged.
+struct Verbatim<'a>(&'a str);
+
+impl fmt::Debug for Verbatim<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+/// An owned copy of an error and its `source()` chain.
+#[derive(Debug)]
+struct RecordedError {
+    message: String,
+    source: Option<Box<RecordedError>>,
+}
+
+impl RecordedError {
+    fn new(err: &(dyn std::error::Error + 'static)) -> Self {
+        Self {
+            message: err.to_string(),
+            source: err.source().map(|source| Box::new(Self::new(source))),
+        }
+    }
+}
+
+impl fmt::Display for RecordedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for RecordedError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.source.as_deref().map(|e| e as _)
+    }
+}
@@ -0,0 +1,143 @@
+use crate::writer::JsonWriter;
+use valuable::{Fields, NamedValues, Value, Visit};
+
+/// Write a [`valuable`] value as nested JSON.
+///
+/// Structs and maps are written as objects, lists and tuples as arrays, and
+/// enums as serde writes them by default: a unit variant as its name, any
+/// other as `{"Variant":contents}`. Tuple structs and variants with a single
+/// field are written as that field, and `()` and unit structs as `null`.
+/// Map keys that are not strings are written as their `Debug` form.
+pub(crate) fn write_value(jw: &mut JsonWriter, value: Value<'_>) {
+    match value {
+        Value::Bool(v) => jw.val_bool(v),
+        Value::Char(v) => jw.val_display(&v),
+        Value::F32(v) => jw.val_f64(v.into()),
+        Value::F64(v) => jw.val_f64(v),
+        Value::I8(v) => jw.val_i64(v.into()),
+        Value::I16(v) => jw.val_i64(v.into()),
+        Value::I32(v) => jw.val_i64(v.into()),
+        Value::I64(v) => jw.val_i64(v),
+        Value::Isize(v) => jw.val_i64(v as i64),
+        Value::I128(v) => jw.val_i128(v),
+        Value::U8(v) => jw.val_u64(v.into()),
+        Value::U16(v) => jw.val_u64(v.into()),
+        Value::U32(v) => jw.val_u64(v.into()),
+        Value::U64(v) => jw.val_u64(v),
+        Value::Usize(v) => jw.val_u64(v as u64),
+        Value::U128(v) => jw.val_u128(v),
+        Value::String(v) => jw.val_str(v),
+        Value::Path(v) => jw.val_display(&v.display()),
+        Value::Error(v) => jw.val_display(v),
+        Value::Unit => jw.val_null(),
+        Value::Tuplable(v) if v.definition().is_unit() => jw.val_null(),
+        Value::Listable(v) => {
+            jw.arr_start();
+            v.visit(&mut Contents::new(jw));
+            jw.arr_end();
+        }
+        Value::Tuplable(v) => {
+            jw.arr_start();
+            v.visit(&mut Contents::new(jw));
+            jw.arr_end();
+        }
+        Value::Mappable(v) => {
+            jw.obj_start();
+            v.visit(&mut Contents::new(jw));
+            jw.obj_end();
+        }
+        Value::Structable(v) => {
+            write_fields(jw, v.definition().fields(), |contents| v.visit(contents));
+        }
+        Value::Enumerable(v) => {
+            let variant = v.variant();
+            if let Fields::Unnamed(0) = variant.fields() {
+                jw.val_str(variant.name());
+            } else {
+                jw.obj_start();
+                key(jw, variant.name());
+                write_fields(jw, variant.fields(), |contents| v.visit(contents));
+                jw.obj_end();
+            }
+        }
+        _ => jw.val_debug(&value),
+    }
+}
+
+/// Write the fields of a struct or enum variant, in the shape given by
+/// `fields`, by passing a [`Contents`] visitor to `visit`.
+fn write_fields(jw: &mut JsonWriter, fields: &Fields<'_>, visit: impl FnOnce(&mut Contents<'_>)) {
+    match fields {
+        Fields::Named(_) => {
+            jw.obj_start();
+            visit(&mut Contents::new(jw));
+            jw.obj_end();
+        }
+        Fields::Unnamed(0) => jw.val_null(),
+        Fields::Unnamed(1) => visit(&mut Contents::new(jw)),
+        Fields::Unnamed(_) => {
+            jw.arr_start();
+            visit(&mut Contents::new(jw));
+            jw.arr_end();
+        }
+    }
+}
+
+/// Write an object key that may need escaping, unlike field names.
+fn key(jw: &mut JsonWriter, name: &str) {
+    jw.val_str(name);
+    jw.raw(b":");
+}
+
+/// Writes the elements or entries of one array or object, separated by
+/// commas; the caller writes the brackets.
+struct Contents<'a> {
+    jw: &'a mut JsonWriter,
+    first: bool,
+}
+
+impl<'a> Contents<'a> {
+    fn new(jw: &'a mut JsonWriter) -> Self {
+        Self { jw, first: true }
+    }
+
+    fn separate(&mut self) {
+        if !self.first {
+            self.jw.comma();
+        }
+        self.first = false;
+    }
+}
+
+impl Visit for Contents<'_> {
+    fn visit_value(&mut self, value: Value<'_>) {
+        self.separate();
+        write_value(self.jw, value);
+    }
+
+    fn visit_named_fields(&mut self, named_values: &NamedValues<'_>) {
+        for (field, value) in named_values.iter() {
+            self.separate();
+            key(self.jw, field.name());
+            write_value(self.jw, *value);
+        }
+    }
+
+    fn visit_unnamed_fields(&mut self, values: &[Value<'_>]) {
+        for value in values {
+            self.visit_value(*value);
+        }
+    }
+
+    fn visit_entry(&mut self, k: Value<'_>, value: Value<'_>) {
+        self.separate();
+        match k {
+            Value::String(name) => key(self.jw, name),
+            _ => {
+                self.jw.val_debug(&k);
+                self.jw.raw(b":");
+            }
+        }
+        write_value(self.jw, value);
+    }
+}
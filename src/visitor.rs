@@ -1,82 +1,586 @@
+use crate::capture::{self, BytesEncoding, Captured, DurationFormat};
+use crate::debug_shape;
 use crate::writer::JsonWriter;
+use std::fmt::Write as _;
+use std::ops::Range;
 use tracing_core::field::{Field, Visit};
 
-/// A [`Visit`] implementation that writes fields as JSON key-value pairs.
-pub(crate) struct JsonVisitor<'a> {
+/// Options controlling how individual field values are rendered.
+#[derive(Clone, Default)]
+pub(crate) struct FieldConfig {
+    /// Render errors as an array of messages following `Error::source()`.
+    pub(crate) error_chain: bool,
+    /// Write `Debug` output with a recognizable JSON shape as JSON.
+    pub(crate) smart_debug: bool,
+    /// Omit boolean fields whose value is `false`.
+    pub(crate) skip_false_bools: bool,
+    /// Names of fields whose values are replaced with [`REDACTED`].
+    pub(crate) redacted: Vec<String>,
+    /// Names of fields whose string and `Debug` values are already JSON and
+    /// are written verbatim.
+    pub(crate) raw_json: Vec<String>,
+    /// How [`Bytes`](crate::Bytes) values are written.
+    pub(crate) bytes_encoding: BytesEncoding,
+    /// How [`DurationField`](crate::DurationField) values are written.
+    pub(crate) duration_format: DurationFormat,
+    /// Keep only the last of several fields with the same name.
+    pub(crate) dedup: bool,
+    /// Write fields ordered by name.
+    pub(crate) sorted: bool,
+    /// Wrap each value as `{"type":…,"value":…}`.
+    pub(crate) typed: bool,
+    /// Maximum number of [`Kind::String`] fields written.
+    pub(crate) string_limit: Option<usize>,
+    /// Maximum number of [`Kind::Number`] fields written.
+    pub(crate) number_limit: Option<usize>,
+    /// Write the `message` beside the nested event fields, not among them.
+    pub(crate) message_top_level: bool,
+    /// Names of other event fields written beside the nested ones.
+    pub(crate) hoisted: Vec<String>,
+}
+
+impl FieldConfig {
+    /// Whether the event field `name` is written at the top level rather
+    /// than nested.
+    fn is_top_level(&self, name: &str) -> bool {
+        (self.message_top_level && name == "message") || self.hoisted.iter().any(|h| h == name)
+    }
+}
+
+/// The categories of field that [`FieldConfig`] limits separately.
+#[derive(Clone, Copy)]
+enum Kind {
+    /// `str`, `Debug` and error values.
+    String = 0,
+    /// Integers and floats.
+    Number = 1,
+}
+
+/// Which fields a [`JsonVisitor`] writes, for writing an event's `message`
+/// and hoisted fields apart from its other fields.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Select {
+    All,
+    TopLevel,
+    Nested,
+}
+
+/// A field name and the byte range of its `"key":value` entry, excluding any
+/// separating comma.
+pub(crate) type Entry = (&'static str, Range<usize>);
+
+/// The value written in place of a redacted field.
+const REDACTED: &str = "[REDACTED]";
+
+/// No field options set, for [`JsonVisitor::new`].
+static DEFAULT_FIELD_CONFIG: FieldConfig = FieldConfig {
+    error_chain: false,
+    smart_debug: false,
+    skip_false_bools: false,
+    redacted: Vec::new(),
+    raw_json: Vec::new(),
+    bytes_encoding: BytesEncoding::Base64,
+    duration_format: DurationFormat::Nanos,
+    dedup: false,
+    sorted: false,
+    typed: false,
+    string_limit: None,
+    number_limit: None,
+    message_top_level: false,
+    hoisted: Vec::new(),
+};
+
+/// A [`Visit`] implementation that writes fields as JSON key-value pairs
+/// into a [`JsonWriter`].
+///
+/// This is the visitor [`JsonLayer`](crate::JsonLayer) uses for event and
+/// span fields, exposed so that other layers and tools can reuse its
+/// escaping and value formatting. Each recorded field is written as a
+/// `"name":value` entry, comma-separated; the caller writes any enclosing
+/// object:
+///
+/// ```rust
+/// use std::sync::{Arc, Mutex};
+/// use tracing_microjson::{JsonVisitor, JsonWriter};
+/// use tracing_subscriber::layer::{Context, Layer};
+/// use tracing_subscriber::prelude::*;
+///
+/// /// Keeps each event's fields as a standalone JSON object.
+/// struct FieldsLayer(Arc<Mutex<Vec<String>>>);
+///
+/// impl<S: tracing::Subscriber> Layer<S> for FieldsLayer {
+///     fn on_event(&self, event: &tracing::Event<'_>, _ctx: Context<'_, S>) {
+///         let mut jw = JsonWriter::new();
+///         jw.obj_start();
+///         event.record(&mut JsonVisitor::new(&mut jw));
+///         jw.obj_end();
+///         let json = String::from_utf8(jw.into_vec()).unwrap();
+///         self.0.lock().unwrap().push(json);
+///     }
+/// }
+///
+/// let lines = Arc::new(Mutex::new(Vec::new()));
+/// let subscriber = tracing_subscriber::registry().with(FieldsLayer(lines.clone()));
+/// tracing::subscriber::with_default(subscriber, || {
+///     tracing::info!(user = "alice", attempt = 2, "signed in");
+/// });
+/// assert_eq!(
+///     lines.lock().unwrap()[0],
+///     r#"{"message":"signed in","user":"alice","attempt":2}"#
+/// );
+/// ```
+pub struct JsonVisitor<'a> {
     writer: &'a mut JsonWriter,
+    config: &'a FieldConfig,
     first: bool,
+    /// Every entry written, when tracking for [`finish`](Self::finish).
+    entries: Option<&'a mut Vec<Entry>>,
+    /// Whether the first tracked entry was preceded by a comma.
+    leading_comma: bool,
+    select: Select,
+    /// Fields written and dropped so far, indexed by [`Kind`].
+    written: [usize; 2],
+    dropped: [usize; 2],
 }
 
 impl<'a> JsonVisitor<'a> {
+    /// Create a visitor that appends fields to `writer` with the default
+    /// formatting, the first one without a leading comma.
+    pub fn new(writer: &'a mut JsonWriter) -> Self {
+        Self::with_config(writer, &DEFAULT_FIELD_CONFIG)
+    }
+
     /// Create a new visitor that writes the first field without a leading comma.
-    pub(crate) fn new(writer: &'a mut JsonWriter) -> Self {
+    pub(crate) fn with_config(writer: &'a mut JsonWriter, config: &'a FieldConfig) -> Self {
         Self {
             writer,
+            config,
             first: true,
+            entries: None,
+            leading_comma: false,
+            select: Select::All,
+            written: [0; 2],
+            dropped: [0; 2],
         }
     }
 
     /// Create a visitor that treats the writer as already having content,
     /// so all fields are preceded by a comma.
-    pub(crate) fn continuing(writer: &'a mut JsonWriter) -> Self {
+    pub(crate) fn continuing(writer: &'a mut JsonWriter, config: &'a FieldConfig) -> Self {
         Self {
             writer,
+            config,
             first: false,
+            entries: None,
+            leading_comma: false,
+            select: Select::All,
+            written: [0; 2],
+            dropped: [0; 2],
+        }
+    }
+
+    /// Write only the fields chosen by `select`.
+    pub(crate) fn selecting(mut self, select: Select) -> Self {
+        self.select = select;
+        self
+    }
+
+    /// Whether `field` is left out by the [`Select`] option.
+    fn skip(&self, field: &Field) -> bool {
+        match self.select {
+            Select::All => false,
+            Select::TopLevel => !self.config.is_top_level(field.name()),
+            Select::Nested => self.config.is_top_level(field.name()),
+        }
+    }
+
+    /// Record the byte range of each entry written into `entries` (cleared
+    /// first), so that [`finish`](Self::finish) can rewrite them.
+    pub(crate) fn tracking_entries(mut self, entries: &'a mut Vec<Entry>) -> Self {
+        entries.clear();
+        self.leading_comma = !self.first;
+        self.entries = Some(entries);
+        self
+    }
+
+    /// Apply the entry-level options to the entries written by this visitor:
+    /// drop all but the last entry for each repeated name if `dedup` is set,
+    /// and order them by name if `sorted` is set.
+    ///
+    /// Does nothing unless entries were tracked. The buffer is only
+    /// rewritten when the entries actually change; sorting copies the
+    /// entries through `scratch`.
+    pub(crate) fn finish(self, scratch: &mut Vec<u8>) {
+        let Some(entries) = self.entries else {
+            return;
+        };
+        let Some(first) = entries.first() else {
+            return;
+        };
+        let start = first.1.start - usize::from(self.leading_comma);
+
+        // Each entry runs up to the comma before the next one
+        let mut end = self.writer.len();
+        for entry in entries.iter_mut().rev() {
+            entry.1.end = end;
+            end = entry.1.start - 1;
+        }
+
+        let count = entries.len();
+        if self.config.dedup {
+            let mut i = 0;
+            while i < entries.len() {
+                if entries[i + 1..]
+                    .iter()
+                    .any(|(name, _)| *name == entries[i].0)
+                {
+                    entries.remove(i);
+                } else {
+                    i += 1;
+                }
+            }
         }
+
+        if self.config.sorted && !entries.is_sorted_by_key(|(name, _)| *name) {
+            // Repeated names (if kept) stay in recording order
+            entries.sort_unstable_by_key(|(name, range)| (*name, range.start));
+            let ranges = entries.iter().map(|(_, range)| range.clone());
+            self.writer
+                .reorder_entries(start, ranges, self.leading_comma, scratch);
+        } else if entries.len() < count {
+            let ranges = entries.iter().map(|(_, range)| range.clone());
+            self.writer
+                .retain_entries(start, ranges, self.leading_comma);
+        }
+    }
+
+    /// Write a `dropped_string_fields` / `dropped_number_fields` entry for
+    /// each kind of field that went over its limit.
+    pub(crate) fn write_dropped_counts(&mut self) {
+        for (kind, name) in [
+            (Kind::String, "dropped_string_fields"),
+            (Kind::Number, "dropped_number_fields"),
+        ] {
+            let dropped = self.dropped[kind as usize];
+            if dropped > 0 {
+                self.write_key_str(name);
+                self.writer.val_u64(dropped as u64);
+            }
+        }
+    }
+
+    /// Count a field of `kind`, returning `true` if it is over the
+    /// configured limit and must be dropped. The `message` is never dropped.
+    fn over_limit(&mut self, field: &Field, kind: Kind) -> bool {
+        let limit = match kind {
+            Kind::String => self.config.string_limit,
+            Kind::Number => self.config.number_limit,
+        };
+        let Some(limit) = limit else {
+            return false;
+        };
+        if field.name() == "message" {
+            return false;
+        }
+        if self.written[kind as usize] >= limit {
+            self.dropped[kind as usize] += 1;
+            return true;
+        }
+        self.written[kind as usize] += 1;
+        false
     }
 
     fn write_key(&mut self, field: &Field) {
+        self.write_key_str(field.name());
+    }
+
+    fn write_key_str(&mut self, name: &'static str) {
         if !self.first {
             self.writer.comma();
         }
         self.first = false;
-        self.writer.key(field.name());
+        if let Some(entries) = &mut self.entries {
+            let offset = self.writer.len();
+            entries.push((name, offset..offset));
+        }
+        self.writer.key(name);
+    }
+
+    /// If `field` is configured for redaction, write it with a placeholder
+    /// value and return `true`.
+    fn redact(&mut self, field: &Field) -> bool {
+        if !self.config.redacted.iter().any(|name| name == field.name()) {
+            return false;
+        }
+        self.begin_value(field, "str");
+        self.writer.val_str(REDACTED);
+        self.end_value(field);
+        true
+    }
+
+    /// Whether `field` holds pre-serialized JSON to be written verbatim.
+    fn is_raw_json(&self, field: &Field) -> bool {
+        self.config.raw_json.iter().any(|name| name == field.name())
+    }
+
+    /// Whether `field`'s value is wrapped with its type. The `message` is
+    /// always written bare so consumers can find it without unwrapping.
+    fn is_typed(&self, field: &Field) -> bool {
+        self.config.typed && field.name() != "message"
+    }
+
+    /// Write the key for `field` and, if typed fields are enabled, open the
+    /// `{"type":…,"value":` wrapper. Must be paired with [`end_value`](Self::end_value).
+    fn begin_value(&mut self, field: &Field, type_name: &str) {
+        self.write_key(field);
+        if self.is_typed(field) {
+            self.writer.obj_start();
+            self.writer.key("type");
+            self.writer.val_str(type_name);
+            self.writer.comma();
+            self.writer.key("value");
+        }
+    }
+
+    /// Close the wrapper opened by [`begin_value`](Self::begin_value), if any.
+    fn end_value(&mut self, field: &Field) {
+        if self.is_typed(field) {
+            self.writer.obj_end();
+        }
     }
 }
 
 impl<'a> Visit for JsonVisitor<'a> {
     fn record_str(&mut self, field: &Field, value: &str) {
-        self.write_key(field);
-        self.writer.val_str(value);
+        if self.skip(field) {
+            return;
+        }
+        if self.over_limit(field, Kind::String) {
+            return;
+        }
+        if self.redact(field) {
+            return;
+        }
+        if self.is_raw_json(field) {
+            self.begin_value(field, "json");
+            self.writer.val_json_raw(value);
+        } else {
+            self.begin_value(field, "str");
+            self.writer.val_str(value);
+        }
+        self.end_value(field);
     }
 
     fn record_u64(&mut self, field: &Field, value: u64) {
-        self.write_key(field);
+        if self.skip(field) {
+            return;
+        }
+        if self.over_limit(field, Kind::Number) {
+            return;
+        }
+        if self.redact(field) {
+            return;
+        }
+        self.begin_value(field, "u64");
         self.writer.val_u64(value);
+        self.end_value(field);
     }
 
     fn record_i64(&mut self, field: &Field, value: i64) {
-        self.write_key(field);
+        if self.skip(field) {
+            return;
+        }
+        if self.over_limit(field, Kind::Number) {
+            return;
+        }
+        if self.redact(field) {
+            return;
+        }
+        self.begin_value(field, "i64");
         self.writer.val_i64(value);
+        self.end_value(field);
     }
 
     fn record_u128(&mut self, field: &Field, value: u128) {
-        self.write_key(field);
+        if self.skip(field) {
+            return;
+        }
+        if self.over_limit(field, Kind::Number) {
+            return;
+        }
+        if self.redact(field) {
+            return;
+        }
+        self.begin_value(field, "u128");
         self.writer.val_u128(value);
+        self.end_value(field);
     }
 
     fn record_i128(&mut self, field: &Field, value: i128) {
-        self.write_key(field);
+        if self.skip(field) {
+            return;
+        }
+        if self.over_limit(field, Kind::Number) {
+            return;
+        }
+        if self.redact(field) {
+            return;
+        }
+        self.begin_value(field, "i128");
         self.writer.val_i128(value);
+        self.end_value(field);
     }
 
     fn record_f64(&mut self, field: &Field, value: f64) {
-        self.write_key(field);
+        if self.skip(field) {
+            return;
+        }
+        if self.over_limit(field, Kind::Number) {
+            return;
+        }
+        if self.redact(field) {
+            return;
+        }
+        self.begin_value(field, "f64");
         self.writer.val_f64(value);
+        self.end_value(field);
     }
 
     fn record_bool(&mut self, field: &Field, value: bool) {
-        self.write_key(field);
+        if self.skip(field) {
+            return;
+        }
+        if !value && self.config.skip_false_bools {
+            return;
+        }
+        if self.redact(field) {
+            return;
+        }
+        self.begin_value(field, "bool");
         self.writer.val_bool(value);
+        self.end_value(field);
     }
 
+    // Also reached for `%` values: tracing has no `record_display`, and
+    // wraps them in a `Debug` impl that forwards to `Display`
     fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
-        self.write_key(field);
-        self.writer.val_debug(value);
+        if self.skip(field) {
+            return;
+        }
+        if self.over_limit(field, Kind::String) {
+            return;
+        }
+        if self.redact(field) {
+            return;
+        }
+        if self.is_raw_json(field) {
+            self.begin_value(field, "json");
+            // Unescaped, straight into the buffer
+            let _ = write!(self.writer, "{value:?}");
+        } else {
+            let (mark, first) = (self.writer.len(), self.first);
+            self.begin_value(field, "debug");
+            let value_start = self.writer.len();
+            // The `message` is always a string
+            let smart = self.config.smart_debug && field.name() != "message";
+            let writer = &mut *self.writer;
+            let captured = capture::capture(|| {
+                if smart {
+                    debug_shape::write(writer, value);
+                } else {
+                    writer.val_debug(value);
+                }
+            });
+            if let Some(captured) = captured {
+                if self.writer.len() == value_start + 2 {
+                    // Nothing but the quotes: the value was a bare wrapper
+                    self.writer.truncate(mark);
+                    self.first = first;
+                    if let Some(entries) = &mut self.entries {
+                        entries.pop();
+                    }
+                    match captured {
+                        Captured::Bytes(bytes) => {
+                            self.begin_value(field, "bytes");
+                            self.writer.val_bytes(&bytes, self.config.bytes_encoding);
+                        }
+                        Captured::Duration(duration) => {
+                            self.begin_value(field, "duration");
+                            self.writer
+                                .val_duration(duration, self.config.duration_format);
+                        }
+                    }
+                } else {
+                    // A wrapper nested in a larger value: write it plainly
+                    drop(captured);
+                    self.writer.truncate(value_start);
+                    self.writer.val_debug(value);
+                }
+            }
+        }
+        self.end_value(field);
+    }
+
+    #[cfg(all(tracing_unstable, feature = "valuable"))]
+    fn record_value(&mut self, field: &Field, value: valuable::Value<'_>) {
+        if self.skip(field) {
+            return;
+        }
+        if self.over_limit(field, Kind::String) {
+            return;
+        }
+        if self.redact(field) {
+            return;
+        }
+        self.begin_value(field, "value");
+        crate::structured::write_value(self.writer, value);
+        self.end_value(field);
     }
 
     fn record_error(&mut self, field: &Field, value: &(dyn std::error::Error + 'static)) {
-        self.write_key(field);
-        self.writer.val_display(value);
+        if self.skip(field) {
+            return;
+        }
+        if self.over_limit(field, Kind::String) {
+            return;
+        }
+        if self.redact(field) {
+            return;
+        }
+        self.begin_value(field, "error");
+        if self.config.error_chain {
+            self.writer.arr_start();
+            self.writer.val_display(value);
+            let mut source = value.source();
+            while let Some(err) = source {
+                self.writer.comma();
+                self.writer.val_display(err);
+                source = err.source();
+            }
+            self.writer.arr_end();
+        } else {
+            self.writer.val_display(value);
+        }
+        self.end_value(field);
+    }
+}
+
+/// A [`Visit`] implementation that copies the text of an event's `message`
+/// field into a string, ignoring all other fields.
+pub(crate) struct MessageVisitor<'a>(pub(crate) &'a mut String);
+
+impl Visit for MessageVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.0.clear();
+            self.0.push_str(value);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.0.clear();
+            let _ = write!(self.0, "{value:?}");
+        }
     }
 }
@@ -1,11 +1,43 @@
-use std::fmt::{self, Write as _};
+// Without `std` there is no layer, so its writer helpers go unused
+#![cfg_attr(not(feature = "std"), allow(dead_code))]
+
+#[cfg(feature = "std")]
+use crate::capture::{BytesEncoding, DurationFormat};
+use alloc::borrow::Cow;
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{self, Write as _};
+use core::ops::Range;
+
+/// Escape `s` for use inside a JSON string, without the surrounding quotes.
+///
+/// Uses the same escaping as [`JsonLayer`](crate::JsonLayer) output. Returns
+/// `s` itself, without allocating, when nothing in it needs escaping.
+///
+/// ```rust
+/// use std::borrow::Cow;
+/// use tracing_microjson::escape_json;
+///
+/// assert!(matches!(escape_json("plain"), Cow::Borrowed("plain")));
+/// assert_eq!(escape_json("say \"hi\"\n"), r#"say \"hi\"\n"#);
+/// ```
+pub fn escape_json(s: &str) -> Cow<'_, str> {
+    if !s.bytes().any(|b| b == b'"' || b == b'\\' || b < 0x20) {
+        return Cow::Borrowed(s);
+    }
+    let mut buf = Vec::with_capacity(s.len() + 8);
+    escape_json_into(s, &mut buf, Escape::default());
+    // Only ASCII bytes are replaced, by ASCII escapes
+    Cow::Owned(String::from_utf8(buf).expect("escaping preserves UTF-8"))
+}
 
 /// Write JSON-escaped content for `s` directly into `buf` per [RFC 8259](https://www.rfc-editor.org/rfc/rfc8259).
 ///
 /// Uses byte-level scanning: safe ranges are flushed in bulk with a single
 /// `extend_from_slice`, so the common case (no characters to escape) copies
-/// the entire input in one shot.
-fn escape_json_into(s: &str, buf: &mut Vec<u8>) {
+/// the entire input in one shot. `opts` adds escapes beyond those required.
+fn escape_json_into(s: &str, buf: &mut Vec<u8>, opts: Escape) {
     let bytes = s.as_bytes();
     let mut start = 0;
 
@@ -18,6 +50,7 @@ fn escape_json_into(s: &str, buf: &mut Vec<u8>) {
             b'\n' => &b"\\n"[..],
             b'\r' => &b"\\r"[..],
             b'\t' => &b"\\t"[..],
+            b'/' if opts.forward_slash => &b"\\/"[..],
             b if b < 0x20 => {
                 // Flush the safe range before this byte
                 buf.extend_from_slice(&bytes[start..i]);
@@ -29,6 +62,21 @@ fn escape_json_into(s: &str, buf: &mut Vec<u8>) {
                 start = i + 1;
                 continue;
             }
+            // U+2028 and U+2029 are E2 80 A8 and E2 80 A9 in UTF-8
+            0xE2 if opts.js_separators
+                && bytes.get(i + 1) == Some(&0x80)
+                && matches!(bytes.get(i + 2), Some(0xA8 | 0xA9)) =>
+            {
+                buf.extend_from_slice(&bytes[start..i]);
+                buf.extend_from_slice(if bytes[i + 2] == 0xA8 {
+                    b"\\u2028"
+                } else {
+                    b"\\u2029"
+                });
+                // The continuation bytes are skipped by the `_` arm
+                start = i + 3;
+                continue;
+            }
             _ => {
                 continue;
             }
@@ -43,74 +91,201 @@ fn escape_json_into(s: &str, buf: &mut Vec<u8>) {
     buf.extend_from_slice(&bytes[start..]);
 }
 
+/// Optional escapes applied by [`escape_json_into`] on top of those JSON
+/// requires.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct Escape {
+    /// Escape U+2028 and U+2029, which are not allowed unescaped in
+    /// JavaScript string literals before ES2019.
+    pub(crate) js_separators: bool,
+    /// Escape `/` as `\/`, so that `</script>` cannot appear in output
+    /// embedded in HTML.
+    pub(crate) forward_slash: bool,
+}
+
+/// The terminator written after each line.
+///
+/// Set with [`JsonLayer::with_line_ending`](crate::JsonLayer::with_line_ending).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LineEnding {
+    /// `\n`.
+    #[default]
+    Lf,
+    /// `\r\n`.
+    CrLf,
+    /// Nothing: lines are written back to back.
+    None,
+}
+
+impl LineEnding {
+    pub(crate) fn as_bytes(self) -> &'static [u8] {
+        match self {
+            Self::Lf => b"\n",
+            Self::CrLf => b"\r\n",
+            Self::None => b"",
+        }
+    }
+}
+
+/// Options controlling how values are rendered by a [`JsonWriter`].
+#[derive(Clone, Copy)]
+pub(crate) struct WriterConfig {
+    /// Write NaN and infinities as `"NaN"`, `"Infinity"` and `"-Infinity"`
+    /// strings instead of `null`.
+    pub(crate) non_finite_as_string: bool,
+    /// Truncate string values longer than this many bytes (before escaping),
+    /// appending `truncation_marker`.
+    pub(crate) max_str_len: Option<usize>,
+    /// Appended to string values cut short by `max_str_len`.
+    pub(crate) truncation_marker: &'static str,
+    /// Extra escapes for string values.
+    pub(crate) escape: Escape,
+}
+
+impl Default for WriterConfig {
+    fn default() -> Self {
+        Self {
+            non_finite_as_string: false,
+            max_str_len: None,
+            truncation_marker: "…",
+            escape: Escape::default(),
+        }
+    }
+}
+
+/// A user-configurable object key, rendered once (quoted, escaped and
+/// followed by `:`) so it can be written per event with a single copy.
+#[derive(Clone)]
+pub(crate) struct Key(Box<[u8]>);
+
+impl Key {
+    pub(crate) fn new(name: &str) -> Self {
+        let mut buf = Vec::with_capacity(name.len() + 3);
+        buf.push(b'"');
+        escape_json_into(name, &mut buf, Escape::default());
+        buf.extend_from_slice(b"\":");
+        Self(buf.into_boxed_slice())
+    }
+}
+
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Show the key as written, without the trailing `:`.
+        let quoted = &self.0[..self.0.len() - 1];
+        f.write_str(&String::from_utf8_lossy(quoted))
+    }
+}
+
 /// A minimal JSON string builder backed by a `Vec<u8>` buffer.
 ///
 /// Implements [`fmt::Write`] so it can be used as a sink for `write!` macros
 /// and with [`tracing_subscriber::fmt::format::Writer`].
 pub struct JsonWriter {
     buf: Vec<u8>,
+    config: WriterConfig,
 }
 
 impl JsonWriter {
     /// Create a new, empty writer.
     pub fn new() -> Self {
-        Self { buf: Vec::new() }
+        Self::from_vec(Vec::new())
     }
 
-    /// Create a writer that wraps an existing `Vec<u8>` (for buffer reuse).
-    pub fn from_vec(buf: Vec<u8>) -> Self {
-        Self { buf }
+    /// Create an empty writer with room for `capacity` bytes, so that
+    /// output up to that size is written without reallocating.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::from_vec(Vec::with_capacity(capacity))
     }
 
-    /// Create a writer that continues from existing content (e.g. span field fragments).
-    /// The existing content is treated as already-written key-value pairs.
-    pub fn continuing(existing: &[u8]) -> Self {
+    /// Create a writer that wraps an existing `Vec<u8>` (for buffer reuse).
+    pub fn from_vec(buf: Vec<u8>) -> Self {
         Self {
-            buf: existing.to_vec(),
+            buf,
+            config: WriterConfig::default(),
         }
     }
 
+    /// Apply rendering options to this writer.
+    pub(crate) fn with_config(mut self, config: WriterConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Open a JSON object: `{`.
     pub fn obj_start(&mut self) {
         self.buf.push(b'{');
     }
 
+    /// Close a JSON object: `}`.
     pub fn obj_end(&mut self) {
         self.buf.push(b'}');
     }
 
+    /// Open a JSON array: `[`.
     pub fn arr_start(&mut self) {
         self.buf.push(b'[');
     }
 
+    /// Close a JSON array: `]`.
     pub fn arr_end(&mut self) {
         self.buf.push(b']');
     }
 
-    /// Write a JSON object key (field names are Rust identifiers, safe without escaping).
+    /// Write a JSON object key and its `:`.
+    ///
+    /// The name is written unescaped: field names are Rust identifiers, which
+    /// never need escaping, but arbitrary text must not be passed here.
     pub fn key(&mut self, name: &str) {
         self.buf.push(b'"');
         self.buf.extend_from_slice(name.as_bytes());
         self.buf.extend_from_slice(b"\":");
     }
 
+    /// Write a pre-rendered, user-configured object key.
+    pub(crate) fn key_from(&mut self, key: &Key) {
+        self.buf.extend_from_slice(&key.0);
+    }
+
     /// Write a JSON string value with proper escaping.
     pub fn val_str(&mut self, s: &str) {
         self.buf.push(b'"');
-        escape_json_into(s, &mut self.buf);
+        match self.config.max_str_len {
+            Some(max) if s.len() > max => {
+                let escape = self.config.escape;
+                escape_json_into(&s[..floor_char_boundary(s, max)], &mut self.buf, escape);
+                escape_json_into(self.config.truncation_marker, &mut self.buf, escape);
+            }
+            _ => escape_json_into(s, &mut self.buf, self.config.escape),
+        }
         self.buf.push(b'"');
     }
 
+    /// Write `json` verbatim as a value, without quoting or escaping.
+    ///
+    /// The caller must ensure `json` is a single valid JSON value; anything
+    /// else, including an empty string, makes the output invalid.
+    pub fn val_json_raw(&mut self, json: &str) {
+        self.buf.extend_from_slice(json.as_bytes());
+    }
+
+    /// Write an integer as a JSON number.
     pub fn val_u64(&mut self, v: u64) {
         self.write_int(v)
     }
+
+    /// Write an integer as a JSON number.
     pub fn val_i64(&mut self, v: i64) {
         self.write_int(v)
     }
 
-    /// Write a u128/i128 as a quoted JSON string (to preserve full precision).
+    /// Write a u128 as a quoted decimal string, preserving precision that a
+    /// JSON number would lose.
     pub fn val_u128(&mut self, v: u128) {
         self.write_quoted_int(v)
     }
+
+    /// Write an i128 as a quoted decimal string, preserving precision that a
+    /// JSON number would lose.
     pub fn val_i128(&mut self, v: i128) {
         self.write_quoted_int(v)
     }
@@ -125,47 +300,211 @@ impl JsonWriter {
         self.buf.push(b'"');
     }
 
+    /// Write an `f64`. NaN and infinities have no JSON representation and are
+    /// written as `null`, or as strings if configured.
     pub fn val_f64(&mut self, v: f64) {
         if v.is_nan() || v.is_infinite() {
-            self.val_null();
+            if !self.config.non_finite_as_string {
+                self.val_null();
+            } else if v.is_nan() {
+                self.val_str("NaN");
+            } else if v.is_sign_positive() {
+                self.val_str("Infinity");
+            } else {
+                self.val_str("-Infinity");
+            }
+        } else {
+            self.write_f64(v);
+        }
+    }
+
+    /// Write a finite `f64` exactly as serde_json would.
+    ///
+    /// The digits are the shortest that round-trip (taken from Rust's `{:e}`
+    /// output); the layout matches serde_json's output: plain decimal for
+    /// magnitudes in `[1e-5, 1e16)`, scientific notation with a signed
+    /// exponent otherwise (`1e+16`, `1.5e-7`). Plain decimals always include
+    /// a fractional part (`1.0`, not `1`).
+    fn write_f64(&mut self, v: f64) {
+        let mut sci = SciBuf::default();
+        write!(sci, "{v:e}").unwrap();
+        let sci = sci.as_bytes();
+
+        let (negative, sci) = match sci.split_first() {
+            Some((b'-', rest)) => (true, rest),
+            _ => (false, sci),
+        };
+        let e = sci.iter().position(|&b| b == b'e').unwrap();
+        let (mantissa, exp) = (&sci[..e], &sci[e + 1..]);
+        let exp = match exp.split_first() {
+            Some((b'-', digits)) => -parse_digits(digits),
+            _ => parse_digits(exp),
+        };
+
+        // Significant digits with the decimal point removed
+        let mut digits = [0u8; 20];
+        let mut len = 0;
+        for &b in mantissa.iter().filter(|&&b| b != b'.') {
+            digits[len] = b;
+            len += 1;
+        }
+        let digits = &digits[..len];
+        let len = len as i32;
+
+        // 10^(kk-1) <= |v| < 10^kk
+        let kk = exp + 1;
+        let k = kk - len;
+
+        if negative {
+            self.buf.push(b'-');
+        }
+        if 0 <= k && kk <= 16 {
+            // 1234e7 -> 12340000000.0
+            self.buf.extend_from_slice(digits);
+            self.buf.resize(self.buf.len() + k as usize, b'0');
+            self.buf.extend_from_slice(b".0");
+        } else if 0 < kk && kk <= 16 {
+            // 1234e-2 -> 12.34
+            let (int, frac) = digits.split_at(kk as usize);
+            self.buf.extend_from_slice(int);
+            self.buf.push(b'.');
+            self.buf.extend_from_slice(frac);
+        } else if -5 < kk && kk <= 0 {
+            // 1234e-6 -> 0.001234
+            self.buf.extend_from_slice(b"0.");
+            self.buf.resize(self.buf.len() + (-kk) as usize, b'0');
+            self.buf.extend_from_slice(digits);
         } else {
-            // Format like serde_json: use Rust's default Display which gives
-            // enough precision and handles -0.0 correctly.
-            let start = self.buf.len();
-            write!(self, "{v}").unwrap();
-            // serde_json always includes a decimal point for floats
-            let written = &self.buf[start..];
-            if !written.contains(&b'.') && !written.contains(&b'e') && !written.contains(&b'E') {
-                self.buf.extend_from_slice(b".0");
+            // 1e30 -> 1e+30, 1234e30 -> 1.234e+33
+            self.buf.push(digits[0]);
+            if digits.len() > 1 {
+                self.buf.push(b'.');
+                self.buf.extend_from_slice(&digits[1..]);
+            }
+            self.buf.push(b'e');
+            if kk > 0 {
+                self.buf.push(b'+');
             }
+            self.write_int(kk - 1);
         }
     }
 
+    /// Write a JSON boolean.
     pub fn val_bool(&mut self, v: bool) {
         self.buf
             .extend_from_slice(if v { b"true" } else { b"false" });
     }
 
+    /// Write `null`.
     pub fn val_null(&mut self) {
         self.buf.extend_from_slice(b"null");
     }
 
+    /// Write a byte slice in `encoding`: a base64 or hex string, or an
+    /// array of numbers.
+    #[cfg(feature = "std")]
+    pub fn val_bytes(&mut self, bytes: &[u8], encoding: BytesEncoding) {
+        const HEX: &[u8; 16] = b"0123456789abcdef";
+        const BASE64: &[u8; 64] =
+            b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        match encoding {
+            BytesEncoding::Base64 => {
+                self.buf.reserve(bytes.len().div_ceil(3) * 4 + 2);
+                self.buf.push(b'"');
+                for chunk in bytes.chunks(3) {
+                    let n = match *chunk {
+                        [a] => u32::from(a) << 16,
+                        [a, b] => u32::from(a) << 16 | u32::from(b) << 8,
+                        [a, b, c, ..] => u32::from(a) << 16 | u32::from(b) << 8 | u32::from(c),
+                        [] => unreachable!("chunks are never empty"),
+                    };
+                    for i in 0..4 {
+                        if i <= chunk.len() {
+                            self.buf.push(BASE64[(n >> (18 - 6 * i) & 0x3f) as usize]);
+                        } else {
+                            self.buf.push(b'=');
+                        }
+                    }
+                }
+                self.buf.push(b'"');
+            }
+            BytesEncoding::Hex => {
+                self.buf.reserve(bytes.len() * 2 + 2);
+                self.buf.push(b'"');
+                for &b in bytes {
+                    self.buf.push(HEX[(b >> 4) as usize]);
+                    self.buf.push(HEX[(b & 0x0F) as usize]);
+                }
+                self.buf.push(b'"');
+            }
+            BytesEncoding::Array => {
+                self.arr_start();
+                for (i, &b) in bytes.iter().enumerate() {
+                    if i > 0 {
+                        self.comma();
+                    }
+                    self.write_int(b);
+                }
+                self.arr_end();
+            }
+        }
+    }
+
+    /// Write a [`Duration`](core::time::Duration) as a number of nanoseconds
+    /// or a `{"secs":…,"nanos":…}` object, per `format`.
+    #[cfg(feature = "std")]
+    pub fn val_duration(&mut self, duration: core::time::Duration, format: DurationFormat) {
+        match format {
+            DurationFormat::Nanos => match u64::try_from(duration.as_nanos()) {
+                Ok(nanos) => self.val_u64(nanos),
+                Err(_) => self.val_u128(duration.as_nanos()),
+            },
+            DurationFormat::SecsNanos => {
+                self.obj_start();
+                self.key("secs");
+                self.val_u64(duration.as_secs());
+                self.comma();
+                self.key("nanos");
+                self.val_u64(u64::from(duration.subsec_nanos()));
+                self.obj_end();
+            }
+        }
+    }
+
     /// Write a JSON string value from a `Debug` value, streaming the escape
     /// so no intermediate `String` is allocated.
     pub fn val_debug(&mut self, value: &dyn fmt::Debug) {
-        self.buf.push(b'"');
-        let _ = write!(JsonEscapingWriter { buf: &mut self.buf }, "{value:?}");
-        self.buf.push(b'"');
+        self.val_fmt(format_args!("{value:?}"));
     }
 
     /// Write a JSON string value from a `Display` value, streaming the escape
     /// so no intermediate `String` is allocated.
     pub fn val_display(&mut self, value: &dyn fmt::Display) {
+        self.val_fmt(format_args!("{value}"));
+    }
+
+    /// Write formatted text as a JSON string value, escaping it as it
+    /// streams in and truncating it to the configured maximum length.
+    fn val_fmt(&mut self, args: fmt::Arguments<'_>) {
         self.buf.push(b'"');
-        let _ = write!(JsonEscapingWriter { buf: &mut self.buf }, "{value}");
+        let mut w = JsonEscapingWriter {
+            buf: &mut self.buf,
+            escape: self.config.escape,
+            remaining: self.config.max_str_len.unwrap_or(usize::MAX),
+            truncated: false,
+        };
+        let _ = w.write_fmt(args);
+        if w.truncated {
+            escape_json_into(
+                self.config.truncation_marker,
+                &mut self.buf,
+                self.config.escape,
+            );
+        }
         self.buf.push(b'"');
     }
 
+    /// Write the `,` separating entries or elements.
     pub fn comma(&mut self) {
         self.buf.push(b',');
     }
@@ -175,8 +514,19 @@ impl JsonWriter {
         self.buf.extend_from_slice(s);
     }
 
+    /// End the line with `\n`.
     pub fn finish_line(&mut self) {
-        self.buf.push(b'\n');
+        self.finish_line_with(LineEnding::Lf);
+    }
+
+    /// End the line with `ending`.
+    pub fn finish_line_with(&mut self, ending: LineEnding) {
+        self.buf.extend_from_slice(ending.as_bytes());
+    }
+
+    /// Insert raw bytes before everything written so far.
+    pub(crate) fn prepend(&mut self, bytes: &[u8]) {
+        self.buf.splice(0..0, bytes.iter().copied());
     }
 
     /// Push a single raw byte.
@@ -194,6 +544,211 @@ impl JsonWriter {
         self.buf.truncate(len);
     }
 
+    /// Mark the current end of the output, so that everything written after
+    /// it can be undone with [`rollback_to`](Self::rollback_to).
+    ///
+    /// For entries that are only kept depending on what was written, e.g.
+    /// leaving out empty values:
+    ///
+    /// ```rust
+    /// use tracing_microjson::JsonWriter;
+    ///
+    /// let mut jw = JsonWriter::new();
+    /// jw.obj_start();
+    /// jw.key("id");
+    /// jw.val_u64(7);
+    /// for (name, value) in [("user", "alice"), ("team", "")] {
+    ///     let checkpoint = jw.checkpoint();
+    ///     jw.comma();
+    ///     jw.key(name);
+    ///     jw.val_display(&value);
+    ///     if jw.as_bytes().ends_with(b":\"\"") {
+    ///         jw.rollback_to(checkpoint);
+    ///     }
+    /// }
+    /// jw.obj_end();
+    /// assert_eq!(jw.as_bytes(), br#"{"id":7,"user":"alice"}"#);
+    /// ```
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint(self.buf.len())
+    }
+
+    /// Remove everything written since `checkpoint` was taken.
+    ///
+    /// Rolling back to a checkpoint that precedes the start of a value or
+    /// entry, as above, keeps the output valid. `checkpoint` must come from
+    /// this writer; one past the current end of the output is ignored.
+    pub fn rollback_to(&mut self, checkpoint: Checkpoint) {
+        self.buf.truncate(checkpoint.0);
+    }
+
+    /// Keep the first `prefix` bytes followed by the bytes in `tail`,
+    /// dropping everything in between and after.
+    pub(crate) fn keep_prefix_and(&mut self, prefix: usize, tail: Range<usize>) {
+        let len = tail.len();
+        self.buf[prefix..tail.end].rotate_right(len);
+        self.buf.truncate(prefix + len);
+    }
+
+    /// Repair raw `\n` and `\r` bytes in the buffer, which would break
+    /// line framing: inside strings they are escaped, elsewhere they are
+    /// dropped. A final `\n` or `\r\n` line terminator is kept.
+    ///
+    /// The buffer is rebuilt through `scratch` only if it contains either
+    /// byte.
+    pub(crate) fn escape_raw_newlines(&mut self, scratch: &mut Vec<u8>) {
+        let terminator = self.line_terminator();
+        let body = &self.buf[..self.buf.len() - terminator.len()];
+        if !body.iter().any(|&b| b == b'\n' || b == b'\r') {
+            return;
+        }
+        scratch.clear();
+        let mut in_string = false;
+        let mut escaped = false;
+        for &b in body {
+            if b == b'\n' || b == b'\r' {
+                if in_string {
+                    // A preceding backslash already started the escape
+                    if !escaped {
+                        scratch.push(b'\\');
+                    }
+                    scratch.push(if b == b'\n' { b'n' } else { b'r' });
+                }
+                escaped = false;
+                continue;
+            }
+            scratch.push(b);
+            match b {
+                _ if escaped => escaped = false,
+                b'\\' if in_string => escaped = true,
+                b'"' => in_string = !in_string,
+                _ => {}
+            }
+        }
+        scratch.extend_from_slice(terminator);
+        core::mem::swap(&mut self.buf, scratch);
+    }
+
+    /// Reformat the finished line as indented, multi-line JSON, keeping its
+    /// line ending. The rewritten line is built in `scratch`, which is
+    /// swapped in.
+    ///
+    /// Expects raw newlines only inside strings, where they are left as is.
+    pub(crate) fn pretty_print(&mut self, scratch: &mut Vec<u8>) {
+        fn newline(out: &mut Vec<u8>, depth: usize) {
+            out.push(b'\n');
+            out.resize(out.len() + 2 * depth, b' ');
+        }
+
+        let terminator = self.line_terminator();
+        let body = &self.buf[..self.buf.len() - terminator.len()];
+        scratch.clear();
+        let mut depth = 0usize;
+        let mut in_string = false;
+        let mut escaped = false;
+        let mut bytes = body.iter().copied().peekable();
+        while let Some(b) = bytes.next() {
+            if in_string {
+                scratch.push(b);
+                match b {
+                    _ if escaped => escaped = false,
+                    b'\\' => escaped = true,
+                    b'"' => in_string = false,
+                    _ => {}
+                }
+                continue;
+            }
+            match b {
+                b'"' => {
+                    in_string = true;
+                    scratch.push(b);
+                }
+                b'{' | b'[' => {
+                    scratch.push(b);
+                    let close = if b == b'{' { b'}' } else { b']' };
+                    if bytes.peek() == Some(&close) {
+                        // Keep empty containers on one line
+                        scratch.push(close);
+                        bytes.next();
+                    } else {
+                        depth += 1;
+                        newline(scratch, depth);
+                    }
+                }
+                b'}' | b']' => {
+                    depth = depth.saturating_sub(1);
+                    newline(scratch, depth);
+                    scratch.push(b);
+                }
+                b',' => {
+                    scratch.push(b);
+                    newline(scratch, depth);
+                }
+                b':' => scratch.extend_from_slice(b": "),
+                _ => scratch.push(b),
+            }
+        }
+        scratch.extend_from_slice(terminator);
+        core::mem::swap(&mut self.buf, scratch);
+    }
+
+    /// The line ending the buffer finishes with, if any.
+    fn line_terminator(&self) -> &'static [u8] {
+        if self.buf.ends_with(b"\r\n") {
+            b"\r\n"
+        } else if self.buf.ends_with(b"\n") {
+            b"\n"
+        } else {
+            b""
+        }
+    }
+
+    /// Rewrite the object entries written from `start` onwards so that only
+    /// the byte ranges in `keep` remain, comma-separated, and preceded by a
+    /// comma if `leading_comma`.
+    ///
+    /// `keep` must be ascending, non-overlapping and lie after `start`;
+    /// entries only move towards the front, so this is done in place.
+    pub(crate) fn retain_entries(
+        &mut self,
+        start: usize,
+        keep: impl Iterator<Item = Range<usize>>,
+        leading_comma: bool,
+    ) {
+        let mut pos = start;
+        for (i, range) in keep.enumerate() {
+            if i > 0 || leading_comma {
+                self.buf[pos] = b',';
+                pos += 1;
+            }
+            let len = range.len();
+            self.buf.copy_within(range, pos);
+            pos += len;
+        }
+        self.buf.truncate(pos);
+    }
+
+    /// Like [`retain_entries`](Self::retain_entries), but `keep` may be in
+    /// any order. The entries are copied out through `scratch` first.
+    pub(crate) fn reorder_entries(
+        &mut self,
+        start: usize,
+        keep: impl Iterator<Item = Range<usize>>,
+        leading_comma: bool,
+        scratch: &mut Vec<u8>,
+    ) {
+        scratch.clear();
+        scratch.extend_from_slice(&self.buf[start..]);
+        self.buf.truncate(start);
+        for (i, range) in keep.enumerate() {
+            if i > 0 || leading_comma {
+                self.buf.push(b',');
+            }
+            self.buf
+                .extend_from_slice(&scratch[range.start - start..range.end - start]);
+        }
+    }
+
     /// Return a byte slice of the buffer contents.
     pub fn as_bytes(&self) -> &[u8] {
         &self.buf
@@ -205,6 +760,11 @@ impl JsonWriter {
     }
 }
 
+/// A position in a [`JsonWriter`]'s output, from
+/// [`JsonWriter::checkpoint`].
+#[derive(Clone, Copy, Debug)]
+pub struct Checkpoint(usize);
+
 impl Default for JsonWriter {
     fn default() -> Self {
         Self::new()
@@ -222,13 +782,68 @@ impl fmt::Write for JsonWriter {
 ///
 /// Used by [`JsonWriter::val_debug`] and [`JsonWriter::val_display`] to
 /// stream-escape `Debug`/`Display` output without an intermediate `String`.
+/// Once `remaining` bytes have been written, the rest of the text is cut at
+/// a character boundary and formatting is stopped with an error.
 struct JsonEscapingWriter<'a> {
     buf: &'a mut Vec<u8>,
+    escape: Escape,
+    remaining: usize,
+    truncated: bool,
 }
 
 impl fmt::Write for JsonEscapingWriter<'_> {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        escape_json_into(s, self.buf);
+        if s.len() <= self.remaining {
+            escape_json_into(s, self.buf, self.escape);
+            self.remaining -= s.len();
+            return Ok(());
+        }
+        let s = &s[..floor_char_boundary(s, self.remaining)];
+        escape_json_into(s, self.buf, self.escape);
+        self.remaining = 0;
+        self.truncated = true;
+        Err(fmt::Error)
+    }
+}
+
+/// The largest index `<= index` that lies on a `char` boundary of `s`.
+fn floor_char_boundary(s: &str, index: usize) -> usize {
+    let mut index = index.min(s.len());
+    while !s.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+/// A fixed-size stack buffer for the `{:e}` rendering of an `f64`, which is
+/// at most 24 bytes (e.g. `-2.2250738585072014e-308`).
+#[derive(Default)]
+struct SciBuf {
+    buf: [u8; 32],
+    len: usize,
+}
+
+impl SciBuf {
+    fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+impl fmt::Write for SciBuf {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        self.buf
+            .get_mut(self.len..end)
+            .ok_or(fmt::Error)?
+            .copy_from_slice(s.as_bytes());
+        self.len = end;
         Ok(())
     }
 }
+
+/// Parse a run of ASCII decimal digits (an `{:e}` exponent).
+fn parse_digits(digits: &[u8]) -> i32 {
+    digits
+        .iter()
+        .fold(0, |acc, &d| acc * 10 + i32::from(d - b'0'))
+}
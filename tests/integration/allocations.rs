@@ -0,0 +1,108 @@
+//! Steady-state allocation checks, using the same counting allocator as the
+//! `alloc` benchmark. Only allocations made on a thread while counting is
+//! switched on are counted, so other tests running in parallel are
+//! unaffected.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use tracing_microjson::JsonLayer;
+use tracing_subscriber::prelude::*;
+
+thread_local! {
+    static COUNTING: Cell<bool> = const { Cell::new(false) };
+    static ALLOC_COUNT: Cell<u64> = const { Cell::new(0) };
+}
+
+struct CountingAlloc;
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        // `try_with`: the thread-locals may already be gone during thread
+        // teardown
+        let _ = COUNTING.try_with(|counting| {
+            if counting.get() {
+                ALLOC_COUNT.with(|count| count.set(count.get() + 1));
+            }
+        });
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOC: CountingAlloc = CountingAlloc;
+
+/// The number of allocations `f` makes on the current thread.
+fn count_allocs(f: impl FnOnce()) -> u64 {
+    ALLOC_COUNT.with(|count| count.set(0));
+    COUNTING.with(|counting| counting.set(true));
+    f();
+    COUNTING.with(|counting| counting.set(false));
+    ALLOC_COUNT.with(|count| count.get())
+}
+
+#[test]
+fn test_counting_allocator_counts() {
+    let allocs = count_allocs(|| {
+        std::hint::black_box(Vec::<u8>::with_capacity(16));
+    });
+    assert_eq!(allocs, 1);
+}
+
+#[test]
+fn test_event_simple_steady_state_is_allocation_free() {
+    // Matches the `event_simple` benchmark
+    let subscriber = tracing_subscriber::registry().with(
+        JsonLayer::new(std::io::sink)
+            .without_time()
+            .with_target(false),
+    );
+    tracing::subscriber::with_default(subscriber, || {
+        // The first event allocates the reusable per-thread buffer
+        tracing::info!("hello");
+
+        let allocs = count_allocs(|| {
+            for _ in 0..100 {
+                tracing::info!("hello");
+            }
+        });
+        assert_eq!(allocs, 0, "steady-state events should not allocate");
+    });
+}
+
+#[test]
+fn test_event_with_fields_and_spans_steady_state_is_allocation_free() {
+    let subscriber = tracing_subscriber::registry().with(JsonLayer::new(std::io::sink));
+    tracing::subscriber::with_default(subscriber, || {
+        let span = tracing::info_span!("request", id = 7);
+        let _guard = span.enter();
+        tracing::info!(user = "alice", count = 3, ok = true, "hello");
+
+        let allocs = count_allocs(|| {
+            for i in 0..100 {
+                tracing::info!(user = "alice", count = i, ok = true, "hello");
+            }
+        });
+        assert_eq!(allocs, 0, "steady-state events should not allocate");
+    });
+}
+
+#[test]
+fn test_level_is_written_without_allocating() {
+    let subscriber = tracing_subscriber::registry().with(JsonLayer::new(std::io::sink));
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::error!("warm up");
+
+        let allocs = count_allocs(|| {
+            tracing::trace!("hello");
+            tracing::debug!("hello");
+            tracing::info!("hello");
+            tracing::warn!("hello");
+            tracing::error!("hello");
+        });
+        assert_eq!(allocs, 0, "writing the level should not allocate");
+    });
+}
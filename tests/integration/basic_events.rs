@@ -100,3 +100,130 @@ fn test_on_record_span_fields_updated() {
     assert_eq!(v["span"]["initial"], "yes");
     assert_eq!(v["span"]["extra"], "value");
 }
+
+#[test]
+fn test_on_record_replaces_existing_span_field() {
+    let w = TestWriter::new();
+    let subscriber = tracing_subscriber::registry().with(JsonLayer::new(w.clone()));
+    tracing::subscriber::with_default(subscriber, || {
+        let span = tracing::info_span!("my_span", x = "a", y = 1);
+        let _g = span.enter();
+        span.record("x", "b");
+        tracing::info!("event");
+    });
+    let out = w.output();
+    assert_eq!(
+        out.matches(r#""x":"#).count(),
+        2,
+        "one per span object: {out}"
+    );
+    let v = parse_line(out.trim());
+    assert_eq!(v["span"]["x"], "b");
+    assert_eq!(v["spans"][0]["x"], "b");
+    // Replaced in place, so the field order is unchanged
+    assert!(out.contains(r#""x":"b","y":1"#), "got: {out}");
+}
+
+#[test]
+fn test_consecutive_events_with_different_scopes() {
+    let w = TestWriter::new();
+    let subscriber = tracing_subscriber::registry().with(JsonLayer::new(w.clone()));
+    tracing::subscriber::with_default(subscriber, || {
+        let outer = tracing::info_span!("outer");
+        let _og = outer.enter();
+        {
+            let inner = tracing::info_span!("inner");
+            let _ig = inner.enter();
+            tracing::info!("two deep");
+        }
+        tracing::info!("one deep");
+        drop(_og);
+        tracing::info!("no span");
+    });
+    let out = w.output();
+    let lines: Vec<_> = out.lines().map(parse_line).collect();
+    assert_eq!(lines.len(), 3);
+
+    let spans = lines[0]["spans"].as_array().expect("spans array");
+    assert_eq!(spans.len(), 2);
+    assert_eq!(spans[0]["name"], "outer");
+    assert_eq!(spans[1]["name"], "inner");
+    assert_eq!(lines[0]["span"]["name"], "inner");
+
+    let spans = lines[1]["spans"].as_array().expect("spans array");
+    assert_eq!(spans.len(), 1);
+    assert_eq!(spans[0]["name"], "outer");
+    assert_eq!(lines[1]["span"]["name"], "outer");
+
+    assert!(lines[2].get("span").is_none());
+    assert!(lines[2].get("spans").is_none());
+}
+
+#[test]
+fn test_record_fields_to_string() {
+    use std::sync::{Arc, Mutex};
+
+    /// Keeps only the fields of each event, as an audit log might.
+    struct FieldsOnly {
+        layer: JsonLayer<TestWriter>,
+        lines: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for FieldsOnly {
+        fn on_event(
+            &self,
+            event: &tracing::Event<'_>,
+            _: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            let fields = self.layer.record_fields_to_string(event);
+            self.lines.lock().unwrap().push(fields);
+        }
+    }
+
+    let lines = Arc::new(Mutex::new(Vec::new()));
+    let layer = JsonLayer::new(TestWriter::new()).with_redacted_fields(["password"]);
+    let subscriber = tracing_subscriber::registry().with(FieldsOnly {
+        layer,
+        lines: lines.clone(),
+    });
+    tracing::subscriber::with_default(subscriber, || {
+        let span = tracing::info_span!("req", id = 7);
+        let _enter = span.enter();
+        tracing::info!(user = "alice", password = "hunter2", "login");
+        tracing::warn!(attempts = 3);
+    });
+
+    let lines = lines.lock().unwrap();
+    assert_eq!(
+        lines[0],
+        r#"{"message":"login","user":"alice","password":"[REDACTED]"}"#
+    );
+    let v = parse_line(&lines[1]);
+    assert_eq!(v, serde_json::json!({"attempts": 3}));
+}
+
+#[test]
+fn test_display_vs_debug_fields() {
+    #[derive(Debug)]
+    struct Port(u16);
+
+    impl std::fmt::Display for Port {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, ":{}", self.0)
+        }
+    }
+
+    let w = TestWriter::new();
+    let subscriber = tracing_subscriber::registry().with(JsonLayer::new(w.clone()));
+    tracing::subscriber::with_default(subscriber, || {
+        let port = Port(8080);
+        let name = "a \"b\"";
+        tracing::info!(debug = ?port, display = %port, debug_str = ?name, display_str = %name);
+    });
+
+    let v = parse_line(w.output().trim());
+    assert_eq!(v["fields"]["debug"], "Port(8080)");
+    assert_eq!(v["fields"]["display"], ":8080");
+    assert_eq!(v["fields"]["debug_str"], r#""a \"b\"""#);
+    assert_eq!(v["fields"]["display_str"], r#"a "b""#);
+}
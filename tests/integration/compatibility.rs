@@ -52,6 +52,111 @@ fn test_compatibility_with_tracing_subscriber_json() {
     );
 }
 
+/// Blank out the timestamp value, the only part of a line expected to differ.
+fn without_timestamp(line: &str) -> String {
+    let v = parse_line(line);
+    let timestamp = v["timestamp"]
+        .as_str()
+        .expect("timestamp should be a string");
+    line.replacen(timestamp, "", 1)
+}
+
+#[test]
+fn test_field_order_matches_tracing_subscriber() {
+    use tracing_subscriber::fmt;
+
+    // Spans without fields, since span objects order their fields differently
+    fn emit() {
+        tracing::warn!(request_id = "abc-123", "outside");
+        let outer = tracing::info_span!("outer");
+        let _outer = outer.enter();
+        let inner = tracing::info_span!("inner");
+        let _inner = inner.enter();
+        tracing::info!(n = 1, ok = true, "inside");
+    }
+
+    let ts_writer = TestWriter::new();
+    {
+        let subscriber = tracing_subscriber::registry().with(
+            fmt::Layer::new()
+                .json()
+                .with_writer(ts_writer.clone())
+                .with_file(true)
+                .with_line_number(true)
+                .with_thread_ids(true)
+                .with_thread_names(true)
+                .with_current_span(true)
+                .with_span_list(true),
+        );
+        tracing::subscriber::with_default(subscriber, emit);
+    }
+
+    let our_writer = TestWriter::new();
+    {
+        let subscriber = tracing_subscriber::registry().with(
+            JsonLayer::new(our_writer.clone())
+                .with_file(true)
+                .with_line_number(true)
+                .with_thread_ids(true)
+                .with_thread_names(true),
+        );
+        tracing::subscriber::with_default(subscriber, emit);
+    }
+
+    let ts_out = ts_writer.output();
+    let our_out = our_writer.output();
+    assert_eq!(ts_out.lines().count(), 2);
+    for (ts_line, our_line) in ts_out.lines().zip(our_out.lines()) {
+        assert_eq!(without_timestamp(our_line), without_timestamp(ts_line));
+    }
+}
+
+#[test]
+fn test_span_object_order_differs_from_tracing_subscriber() {
+    use tracing_subscriber::fmt;
+
+    fn emit() {
+        let span = tracing::info_span!("req", z = 1, a = 2);
+        let _enter = span.enter();
+        tracing::info!("inside");
+    }
+
+    let ts_writer = TestWriter::new();
+    {
+        let subscriber = tracing_subscriber::registry().with(
+            fmt::Layer::new()
+                .json()
+                .without_time()
+                .with_writer(ts_writer.clone())
+                .with_span_list(false),
+        );
+        tracing::subscriber::with_default(subscriber, emit);
+    }
+
+    let our_writer = TestWriter::new();
+    {
+        let subscriber = tracing_subscriber::registry().with(
+            JsonLayer::new(our_writer.clone())
+                .without_time()
+                .with_span_list(false),
+        );
+        tracing::subscriber::with_default(subscriber, emit);
+    }
+
+    // The documented deviation: `name` first and fields in recording order,
+    // rather than sorted fields and `name` last
+    assert!(
+        ts_writer
+            .output()
+            .contains(r#""span":{"a":2,"z":1,"name":"req"}"#)
+    );
+    assert!(
+        our_writer
+            .output()
+            .contains(r#""span":{"name":"req","z":1,"a":2}"#)
+    );
+}
+
 #[test]
 fn test_thread_id_compat_with_tracing_subscriber() {
     use tracing_subscriber::fmt;
@@ -116,3 +221,88 @@ fn test_thread_id_compat_with_tracing_subscriber() {
         ts_val["threadName"], our_val["threadName"]
     );
 }
+
+#[test]
+fn test_non_finite_compat_with_tracing_subscriber() {
+    use tracing_subscriber::fmt;
+
+    fn emit() {
+        tracing::info!(
+            nan = f64::NAN,
+            pos = f64::INFINITY,
+            neg = f64::NEG_INFINITY,
+            "non-finite"
+        );
+    }
+
+    let ts_writer = TestWriter::new();
+    {
+        let subscriber = tracing_subscriber::registry()
+            .with(fmt::Layer::new().json().with_writer(ts_writer.clone()));
+        tracing::subscriber::with_default(subscriber, emit);
+    }
+
+    let our_writer = TestWriter::new();
+    {
+        let subscriber = tracing_subscriber::registry().with(JsonLayer::new(our_writer.clone()));
+        tracing::subscriber::with_default(subscriber, emit);
+    }
+
+    let ts_val = parse_line(ts_writer.output().trim());
+    let our_val = parse_line(our_writer.output().trim());
+
+    // serde_json has no representation for non-finite floats and writes null
+    for key in ["nan", "pos", "neg"] {
+        assert!(
+            our_val["fields"][key].is_null(),
+            "{key} should be null: {our_val}"
+        );
+        assert_eq!(
+            ts_val["fields"][key], our_val["fields"][key],
+            "field '{key}' should match: ts={ts_val} ours={our_val}"
+        );
+    }
+}
+
+#[test]
+fn test_interpolated_message_compat_with_tracing_subscriber() {
+    use tracing_subscriber::fmt;
+
+    fn emit() {
+        tracing::info!("x={}", 5);
+        tracing::info!("quoted {:?} and {}", "s", "plain \"q\"");
+        tracing::info!(k = 1, "multi\nline\ttab é {}", '✓');
+        tracing::info!(message = %format_args!("via {}", "field"));
+    }
+
+    let ts_writer = TestWriter::new();
+    {
+        let subscriber = tracing_subscriber::registry().with(
+            fmt::Layer::new()
+                .json()
+                .without_time()
+                .with_writer(ts_writer.clone()),
+        );
+        tracing::subscriber::with_default(subscriber, emit);
+    }
+
+    let our_writer = TestWriter::new();
+    {
+        let subscriber =
+            tracing_subscriber::registry().with(JsonLayer::new(our_writer.clone()).without_time());
+        tracing::subscriber::with_default(subscriber, emit);
+    }
+
+    // format_args! messages are recorded through Debug, which must not add
+    // quotes; the lines should be identical byte for byte.
+    let ts_out = ts_writer.output();
+    let our_out = our_writer.output();
+    assert_eq!(ts_out.lines().count(), 4);
+    for (ts_line, our_line) in ts_out.lines().zip(our_out.lines()) {
+        assert_eq!(our_line, ts_line);
+    }
+    assert_eq!(
+        parse_line(our_out.lines().next().unwrap())["fields"]["message"],
+        "x=5"
+    );
+}
@@ -1,5 +1,5 @@
 use super::common::{TestWriter, parse_line};
-use tracing_microjson::JsonLayer;
+use tracing_microjson::{JsonLayer, TargetTransform};
 use tracing_subscriber::prelude::*;
 
 #[test]
@@ -24,6 +24,71 @@ fn test_optional_fields_filename_line() {
     );
 }
 
+#[test]
+fn test_module_path() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone()).with_module_path(true);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!(target: "audit", "overridden target");
+    });
+    let out = w.output();
+    let v = parse_line(out.trim());
+    assert_eq!(v["target"], "audit");
+    assert_eq!(v["module_path"], module_path!());
+    assert!(
+        v["module_path"].as_str().unwrap().contains("::"),
+        "module_path should look like a module path, got: {}",
+        v["module_path"]
+    );
+}
+
+#[test]
+fn test_location() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone()).with_location(true);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!("located");
+    });
+    let out = w.output();
+    let v = parse_line(out.trim());
+    assert_eq!(v["module_path"], module_path!());
+    assert_eq!(v["filename"], file!());
+    assert!(v["line_number"].is_u64());
+}
+
+#[test]
+fn test_location_overridden() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone())
+        .with_location(true)
+        .with_line_number(false);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!("located");
+    });
+    let out = w.output();
+    let v = parse_line(out.trim());
+    assert_eq!(v["filename"], file!());
+    assert!(v.get("line_number").is_none());
+}
+
+#[test]
+fn test_code_location() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone()).with_code_location(true);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!("located");
+    });
+    let line = line!() - 2;
+    let v = parse_line(w.output().trim());
+    assert_eq!(v["location"], format!("{}:{line}", file!()));
+    assert!(v.get("filename").is_none());
+    assert!(v.get("line_number").is_none());
+}
+
 #[test]
 fn test_target_hidden() {
     let w = TestWriter::new();
@@ -36,3 +101,474 @@ fn test_target_hidden() {
     let v = parse_line(out.trim());
     assert!(v.get("target").is_none(), "target should be absent");
 }
+
+#[test]
+fn test_target_prefix_strip() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone()).with_target_prefix_strip("mycompany::");
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!(target: "mycompany::billing::invoice", "stripped");
+        tracing::info!(target: "other::billing", "kept");
+        tracing::info!(target: "mycompany::", "nothing left");
+    });
+    let out = w.output();
+    let targets: Vec<_> = out
+        .lines()
+        .map(|l| parse_line(l)["target"].clone())
+        .collect();
+    assert_eq!(
+        targets,
+        ["billing::invoice", "other::billing", "mycompany::"]
+    );
+}
+
+#[test]
+fn test_target_transform() {
+    for (transform, expected) in [
+        (TargetTransform::AsIs, "A::b::C"),
+        (TargetTransform::Lowercase, "a::b::c"),
+        (TargetTransform::LastSegment, "C"),
+    ] {
+        let w = TestWriter::new();
+        let layer = JsonLayer::new(w.clone()).with_target_transform(transform);
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(target: "A::b::C", "transformed");
+        });
+        let v = parse_line(w.output().trim());
+        assert_eq!(v["target"], expected, "{transform:?}");
+    }
+}
+
+#[test]
+fn test_skip_false_bools() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone()).skip_false_bools(true);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!(a = true, b = false, "flags");
+        tracing::info!(b = false, a = true);
+    });
+    let out = w.output();
+    let mut lines = out.lines();
+
+    let v = parse_line(lines.next().unwrap());
+    assert_eq!(v["fields"]["a"], true);
+    assert!(
+        v["fields"].get("b").is_none(),
+        "false bool should be omitted"
+    );
+    assert_eq!(v["fields"]["message"], "flags");
+
+    // A skipped first field must not leave a dangling comma
+    let v = parse_line(lines.next().unwrap());
+    let fields = v["fields"].as_object().expect("fields object");
+    assert_eq!(fields.len(), 1);
+    assert_eq!(fields["a"], true);
+}
+
+#[test]
+fn test_pid_present() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone()).with_pid(true);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!("with pid");
+    });
+    let v = parse_line(w.output().trim());
+    assert_eq!(v["pid"], std::process::id());
+    assert!(v.get("ppid").is_none(), "ppid should be absent by default");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_ppid_present() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone()).with_ppid(true);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!("with ppid");
+    });
+    let v = parse_line(w.output().trim());
+    assert!(
+        v["ppid"].is_u64(),
+        "ppid should be a number, got: {}",
+        v["ppid"]
+    );
+    assert!(v.get("pid").is_none(), "pid should be absent by default");
+}
+
+#[test]
+fn test_level_handle_changes_level_at_runtime() {
+    use tracing_microjson::LevelFilter;
+
+    let w = TestWriter::new();
+    let (layer, handle) = JsonLayer::new(w.clone()).with_level_handle();
+    handle.set_level(LevelFilter::WARN);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::debug!("suppressed");
+        tracing::warn!("kept");
+        handle.set_level(LevelFilter::DEBUG);
+        tracing::debug!("now visible");
+        tracing::trace!("still suppressed");
+    });
+    assert_eq!(handle.level(), LevelFilter::DEBUG);
+
+    let out = w.output();
+    let messages: Vec<_> = out
+        .lines()
+        .map(|l| {
+            parse_line(l)["fields"]["message"]
+                .as_str()
+                .unwrap()
+                .to_owned()
+        })
+        .collect();
+    assert_eq!(messages, ["kept", "now visible"]);
+}
+
+#[test]
+fn test_custom_timestamp_and_level_keys() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone())
+        .with_timestamp_field_name("ts")
+        .with_level_field_name("lvl");
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!("renamed");
+    });
+    let v = parse_line(w.output().trim());
+    assert!(v["ts"].is_string());
+    assert_eq!(v["lvl"], "INFO");
+    assert!(v.get("timestamp").is_none());
+    assert!(v.get("level").is_none());
+}
+
+#[test]
+fn test_line_hash() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone())
+        .with_line_hash(true)
+        .without_time();
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        for _ in 0..2 {
+            tracing::info!(user = "alice", "same");
+        }
+        tracing::info!(user = "bob", "same");
+    });
+    let out = w.output();
+    let lines: Vec<_> = out.lines().collect();
+    let hashes: Vec<_> = lines
+        .iter()
+        .map(|l| parse_line(l)["hash"].as_str().unwrap().to_owned())
+        .collect();
+    assert_eq!(hashes[0].len(), 16);
+    assert_eq!(hashes[0], hashes[1], "identical content must hash the same");
+    assert_ne!(hashes[0], hashes[2]);
+
+    // The hash covers everything before the `,"hash":` entry
+    let (content, rest) = lines[0].split_once(r#","hash":"#).unwrap();
+    assert!(rest.ends_with("\"}"));
+    let expected = content.bytes().fold(0xcbf2_9ce4_8422_2325u64, |h, b| {
+        (h ^ u64::from(b)).wrapping_mul(0x0000_0100_0000_01b3)
+    });
+    assert_eq!(hashes[0], format!("{expected:016x}"));
+}
+
+#[test]
+fn test_line_hash_after_other_rewrites() {
+    // A short field limit must not cut the hash, and newline repair of a
+    // raw JSON field must happen before hashing
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone())
+        .with_line_hash(true)
+        .with_max_field_len(4)
+        .with_raw_json_fields(["payload"])
+        .with_newline_safety(true)
+        .without_time();
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!(payload = "{\"a\":\n1}", "hashed");
+    });
+    let out = w.output();
+    assert_eq!(out.lines().count(), 1);
+    let line = out.trim_end();
+    let v = parse_line(line);
+    assert_eq!(v["fields"]["payload"], serde_json::json!({"a": 1}));
+
+    let hash = v["hash"].as_str().unwrap();
+    assert_eq!(hash.len(), 16);
+    let (content, _) = line.split_once(r#","hash":"#).unwrap();
+    let expected = content.bytes().fold(0xcbf2_9ce4_8422_2325u64, |h, b| {
+        (h ^ u64::from(b)).wrapping_mul(0x0000_0100_0000_01b3)
+    });
+    assert_eq!(hash, format!("{expected:016x}"));
+}
+
+#[test]
+fn test_thread_ids_numeric() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone()).with_thread_ids_numeric(true);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!("numeric thread id");
+    });
+    let v = parse_line(w.output().trim());
+    let expected = format!("{:?}", std::thread::current().id());
+    let n = v["threadId"].as_u64().expect("threadId is a number");
+    assert_eq!(expected, format!("ThreadId({n})"));
+}
+
+#[test]
+fn test_host_ip_value() {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    for (ip, expected) in [
+        (IpAddr::from(Ipv4Addr::new(10, 1, 2, 3)), "10.1.2.3"),
+        (IpAddr::from(Ipv6Addr::LOCALHOST), "::1"),
+    ] {
+        let w = TestWriter::new();
+        let layer = JsonLayer::new(w.clone()).with_host_ip_value(ip);
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!("with ip");
+        });
+        let v = parse_line(w.output().trim());
+        assert_eq!(v["host_ip"], expected);
+    }
+}
+
+#[cfg(feature = "host-ip")]
+#[test]
+fn test_host_ip_detected() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone()).with_host_ip(true);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!("detected ip");
+    });
+    let v = parse_line(w.output().trim());
+    // Sandboxed hosts may have no route; then the field is omitted
+    if let Some(ip) = v.get("host_ip") {
+        let ip: std::net::IpAddr = ip.as_str().unwrap().parse().expect("valid IP");
+        assert!(!ip.is_loopback());
+    }
+}
+
+#[test]
+fn test_context_stack() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone()).with_context_stack(true);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        let _request = tracing_microjson::push_context("request_id", 42);
+        {
+            let _user = tracing_microjson::push_context("user", "al\"ice");
+            tracing::info!("inside");
+        }
+        tracing::info!("request only");
+        drop(_request);
+        tracing::info!("outside");
+    });
+    let out = w.output();
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(lines.len(), 3);
+
+    let v = parse_line(lines[0]);
+    assert_eq!(v["request_id"], "42");
+    assert_eq!(v["user"], "al\"ice");
+    assert_eq!(v["fields"]["message"], "inside");
+
+    let v = parse_line(lines[1]);
+    assert_eq!(v["request_id"], "42");
+    assert!(v.get("user").is_none());
+
+    let v = parse_line(lines[2]);
+    assert!(v.get("request_id").is_none());
+    assert!(v.get("user").is_none());
+}
+
+#[test]
+fn test_field_hook() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone())
+        .without_time()
+        .with_target(false)
+        .with_field_hook(|jw| {
+            jw.key("req");
+            jw.val_str("abc");
+        });
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!("hooked");
+    });
+    assert_eq!(
+        w.output().trim(),
+        r#"{"level":"INFO","req":"abc","fields":{"message":"hooked"}}"#
+    );
+}
+
+#[test]
+fn test_field_hook_writing_nothing() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone())
+        .without_time()
+        .with_target(false)
+        .flatten_event(true)
+        .with_field_hook(|_| {});
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!("unhooked");
+    });
+    assert_eq!(
+        w.output().trim(),
+        r#"{"level":"INFO","message":"unhooked"}"#
+    );
+}
+
+#[test]
+fn test_message_top_level() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone()).with_message_top_level(true);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!(user = "alice", n = 2, "hello");
+        tracing::info!(user = "bob");
+    });
+    let out = w.output();
+    let lines: Vec<&str> = out.lines().collect();
+
+    let v = parse_line(lines[0]);
+    assert_eq!(v["message"], "hello");
+    assert!(v["fields"].get("message").is_none());
+    assert_eq!(v["fields"]["user"], "alice");
+    assert_eq!(v["fields"]["n"], 2);
+
+    let v = parse_line(lines[1]);
+    assert!(v.get("message").is_none());
+    assert_eq!(v["fields"]["user"], "bob");
+}
+
+#[test]
+fn test_hoisted_fields() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone())
+        .without_time()
+        .with_target(false)
+        .with_hoisted_fields(["request_id"]);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!(other = 1, request_id = "abc", "hello");
+        tracing::info!(other = 2);
+    });
+    let out = w.output();
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(
+        lines[0],
+        r#"{"level":"INFO","request_id":"abc","fields":{"message":"hello","other":1}}"#
+    );
+    assert_eq!(lines[1], r#"{"level":"INFO","fields":{"other":2}}"#);
+}
+
+#[test]
+fn test_hoisted_fields_with_message_top_level() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone())
+        .with_message_top_level(true)
+        .with_hoisted_fields(["request_id"]);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!(request_id = "abc", other = true, "hello");
+    });
+    let v = parse_line(w.output().trim());
+    assert_eq!(v["message"], "hello");
+    assert_eq!(v["request_id"], "abc");
+    assert_eq!(v["fields"], serde_json::json!({"other": true}));
+}
+
+#[test]
+fn test_fields_field_name() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone()).with_fields_field_name("attributes");
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!(user = "alice", n = 2, "hello");
+    });
+    let v = parse_line(w.output().trim());
+    assert!(v.get("fields").is_none());
+    assert_eq!(v["attributes"]["message"], "hello");
+    assert_eq!(v["attributes"]["user"], "alice");
+    assert_eq!(v["attributes"]["n"], 2);
+}
+
+#[test]
+fn test_layer_clone() {
+    let w = TestWriter::new();
+    let base = JsonLayer::new(w.clone())
+        .without_time()
+        .with_target(false)
+        .with_level_ordinal(true)
+        .flatten_event(true);
+
+    for layer in [base.clone(), base] {
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::warn!(n = 1, "cloned");
+        });
+    }
+    let out = w.output();
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0], lines[1]);
+    assert_eq!(
+        lines[0],
+        r#"{"level":"WARN","level_ord":3,"message":"cloned","n":1}"#
+    );
+}
+
+#[test]
+fn test_layer_debug() {
+    let layer = JsonLayer::new(TestWriter::new()).with_target(false);
+    let debug = format!("{layer:?}");
+    assert!(debug.starts_with("JsonLayer {"), "{debug}");
+    assert!(debug.contains("flatten_event: false"), "{debug}");
+    assert!(debug.contains("display_target: false"), "{debug}");
+    assert!(debug.contains(r#"timestamp_key: "timestamp""#), "{debug}");
+}
+
+#[test]
+fn test_pretty() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone())
+        .without_time()
+        .with_target(false)
+        .with_pretty(true);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        let span = tracing::info_span!("req");
+        let _g = span.enter();
+        tracing::info!(text = "a, {b}: [c] \"d\"", "hi");
+    });
+    assert_eq!(
+        w.output(),
+        r#"{
+  "level": "INFO",
+  "fields": {
+    "message": "hi",
+    "text": "a, {b}: [c] \"d\""
+  },
+  "span": {
+    "name": "req"
+  },
+  "spans": [
+    {
+      "name": "req"
+    }
+  ]
+}
+"#
+    );
+}
@@ -1,5 +1,8 @@
 use super::common::{TestWriter, parse_line};
-use tracing_microjson::JsonLayer;
+use std::time::Duration;
+use tracing_microjson::{
+    Bytes, BytesEncoding, DurationField, DurationFormat, JsonLayer, Level, LevelStyle,
+};
 use tracing_subscriber::prelude::*;
 
 #[test]
@@ -117,6 +120,45 @@ fn test_record_error_field() {
     assert_eq!(v["level"], "ERROR");
 }
 
+#[test]
+fn test_record_error_chain() {
+    #[derive(Debug)]
+    struct Inner;
+    impl std::fmt::Display for Inner {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str("disk full")
+        }
+    }
+    impl std::error::Error for Inner {}
+
+    #[derive(Debug)]
+    struct Outer(Inner);
+    impl std::fmt::Display for Outer {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str("failed to save")
+        }
+    }
+    impl std::error::Error for Outer {
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+            Some(&self.0)
+        }
+    }
+
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone()).with_error_chain(true);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        let err = Outer(Inner);
+        tracing::error!(err = &err as &dyn std::error::Error, "failure");
+    });
+    let v = parse_line(w.output().trim());
+    assert_eq!(
+        v["fields"]["err"],
+        serde_json::json!(["failed to save", "disk full"])
+    );
+    assert_eq!(v["fields"]["message"], "failure");
+}
+
 #[test]
 fn test_event_outside_span_has_no_span_fields() {
     let w = TestWriter::new();
@@ -199,3 +241,928 @@ fn test_span_with_no_fields() {
     assert_eq!(span0_obj.len(), 1, "spans[0] must have only 'name'");
     assert_eq!(spans[0]["name"], "empty_span");
 }
+
+#[test]
+fn test_non_finite_floats() {
+    for (as_string, expected) in [
+        (
+            false,
+            [
+                serde_json::Value::Null,
+                serde_json::Value::Null,
+                serde_json::Value::Null,
+            ],
+        ),
+        (
+            true,
+            [
+                serde_json::json!("NaN"),
+                serde_json::json!("Infinity"),
+                serde_json::json!("-Infinity"),
+            ],
+        ),
+    ] {
+        let w = TestWriter::new();
+        let layer = JsonLayer::new(w.clone()).with_non_finite_as_string(as_string);
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(
+                nan = f64::NAN,
+                pos = f64::INFINITY,
+                neg = f64::NEG_INFINITY,
+                "non-finite"
+            );
+        });
+        let v = parse_line(w.output().trim());
+        assert_eq!(v["fields"]["nan"], expected[0], "as_string={as_string}");
+        assert_eq!(v["fields"]["pos"], expected[1], "as_string={as_string}");
+        assert_eq!(v["fields"]["neg"], expected[2], "as_string={as_string}");
+    }
+}
+
+#[test]
+fn test_level_ordinal() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone()).with_level_ordinal(true);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::error!("error");
+        tracing::warn!("warn");
+        tracing::info!("info");
+        tracing::debug!("debug");
+        tracing::trace!("trace");
+    });
+    let out = w.output();
+    let ords: Vec<_> = out
+        .lines()
+        .map(|l| parse_line(l)["level_ord"].clone())
+        .collect();
+    assert_eq!(ords, [4, 3, 2, 1, 0]);
+}
+
+#[test]
+fn test_level_style() {
+    for (style, expected) in [
+        (LevelStyle::Name, serde_json::json!("INFO")),
+        (LevelStyle::Lowercase, serde_json::json!("info")),
+        (LevelStyle::Numeric, serde_json::json!(2)),
+        (LevelStyle::ShortUpper, serde_json::json!("I")),
+    ] {
+        let w = TestWriter::new();
+        let layer = JsonLayer::new(w.clone()).with_level_style(style);
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!("styled");
+        });
+        let v = parse_line(w.output().trim());
+        assert_eq!(v["level"], expected, "{style:?}");
+    }
+}
+
+#[test]
+fn test_level_map() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone()).with_level_map(|level| match level {
+        Level::WARN => "warning",
+        Level::ERROR => "critical",
+        _ => level.as_str(),
+    });
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::error!("error");
+        tracing::warn!("warn");
+        tracing::info!("info");
+    });
+    let out = w.output();
+    let levels: Vec<_> = out
+        .lines()
+        .map(|l| parse_line(l)["level"].clone())
+        .collect();
+    assert_eq!(levels, ["critical", "warning", "INFO"]);
+}
+
+#[test]
+fn test_level_number_field() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone())
+        .without_time()
+        .with_level_style(LevelStyle::Lowercase)
+        .with_level_number_field("level_num");
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::error!("error");
+        tracing::warn!("warn");
+        tracing::info!("info");
+        tracing::debug!("debug");
+        tracing::trace!("trace");
+    });
+    let out = w.output();
+    let levels: Vec<_> = out
+        .lines()
+        .map(|l| {
+            let v = parse_line(l);
+            (v["level"].clone(), v["level_num"].clone())
+        })
+        .collect();
+    assert_eq!(
+        levels,
+        [
+            ("error".into(), 3.into()),
+            ("warn".into(), 4.into()),
+            ("info".into(), 6.into()),
+            ("debug".into(), 7.into()),
+            ("trace".into(), 7.into()),
+        ]
+    );
+    assert!(out.starts_with(r#"{"level":"error","level_num":3,"fields""#));
+}
+
+#[test]
+fn test_level_numbers_custom_scale() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone())
+        .with_level_number_field("severity_number")
+        .with_level_numbers(|level| match level {
+            Level::TRACE => 1,
+            Level::DEBUG => 5,
+            Level::INFO => 9,
+            Level::WARN => 13,
+            Level::ERROR => 17,
+        });
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::warn!("warn");
+    });
+    let v = parse_line(w.output().trim());
+    assert_eq!(v["level"], "WARN");
+    assert_eq!(v["severity_number"], 13);
+}
+
+#[test]
+fn test_level_style_short_upper_all_levels() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone()).with_level_style(LevelStyle::ShortUpper);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::error!("error");
+        tracing::warn!("warn");
+        tracing::info!("info");
+        tracing::debug!("debug");
+        tracing::trace!("trace");
+    });
+    let out = w.output();
+    let levels: Vec<_> = out
+        .lines()
+        .map(|l| parse_line(l)["level"].clone())
+        .collect();
+    assert_eq!(levels, ["E", "W", "I", "D", "V"]);
+}
+
+#[test]
+fn test_dedup_fields() {
+    for flatten in [false, true] {
+        let w = TestWriter::new();
+        let layer = JsonLayer::new(w.clone())
+            .with_dedup_fields(true)
+            .flatten_event(flatten);
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(x = 1, y = "keep", x = 2, "dup");
+        });
+        let out = w.output();
+        assert_eq!(out.matches(r#""x":"#).count(), 1, "got: {out}");
+        let v = parse_line(out.trim());
+        let fields = if flatten { &v } else { &v["fields"] };
+        assert_eq!(fields["x"], 2, "flatten={flatten}");
+        assert_eq!(fields["y"], "keep", "flatten={flatten}");
+        assert_eq!(fields["message"], "dup", "flatten={flatten}");
+        if !flatten {
+            assert!(
+                out.contains(r#""fields":{"message":"dup","y":"keep","x":2}"#),
+                "got: {out}"
+            );
+        }
+    }
+}
+
+#[test]
+fn test_duplicate_fields_kept_by_default() {
+    let w = TestWriter::new();
+    let subscriber = tracing_subscriber::registry().with(JsonLayer::new(w.clone()));
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!(x = 1, x = 2, "dup");
+    });
+    assert_eq!(w.output().matches(r#""x":"#).count(), 2);
+}
+
+#[test]
+fn test_typed_fields() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone()).with_typed_fields(true);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        let span = tracing::info_span!("req", id = 7u64);
+        let _g = span.enter();
+        tracing::info!(
+            name = "alice",
+            count = 42u64,
+            delta = -3i64,
+            ratio = 0.5,
+            ok = true,
+            "typed"
+        );
+    });
+    let v = parse_line(w.output().trim());
+    let fields = &v["fields"];
+    assert_eq!(
+        fields["name"],
+        serde_json::json!({"type": "str", "value": "alice"})
+    );
+    assert_eq!(
+        fields["count"],
+        serde_json::json!({"type": "u64", "value": 42})
+    );
+    assert_eq!(
+        fields["delta"],
+        serde_json::json!({"type": "i64", "value": -3})
+    );
+    assert_eq!(
+        fields["ratio"],
+        serde_json::json!({"type": "f64", "value": 0.5})
+    );
+    assert_eq!(
+        fields["ok"],
+        serde_json::json!({"type": "bool", "value": true})
+    );
+    // The message stays a bare string
+    assert_eq!(fields["message"], "typed");
+    // Span fields go through the same visitor
+    assert_eq!(
+        v["span"]["id"],
+        serde_json::json!({"type": "u64", "value": 7})
+    );
+}
+
+#[test]
+fn test_sorted_fields() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone()).with_sorted_fields(true);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!(c = 1, a = 2, b = 3);
+        tracing::info!(z = "last", m = true, "with message");
+    });
+    let out = w.output();
+    let mut lines = out.lines();
+    assert!(
+        lines
+            .next()
+            .unwrap()
+            .contains(r#""fields":{"a":2,"b":3,"c":1}"#),
+        "got: {out}"
+    );
+    assert!(
+        lines
+            .next()
+            .unwrap()
+            .contains(r#""fields":{"m":true,"message":"with message","z":"last"}"#),
+        "got: {out}"
+    );
+}
+
+#[test]
+fn test_sorted_and_dedup_fields_flattened() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone())
+        .with_sorted_fields(true)
+        .with_dedup_fields(true)
+        .flatten_event(true)
+        .without_time();
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!(b = 1, a = 2, b = 3);
+    });
+    let out = w.output();
+    assert!(
+        out.starts_with(r#"{"level":"INFO","a":2,"b":3,"target":"#),
+        "got: {out}"
+    );
+}
+
+#[test]
+fn test_fields_object_present_when_event_has_no_fields() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone()).skip_false_bools(true);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        // The only field is skipped, so the event records nothing
+        tracing::info!(flag = false);
+    });
+    let out = w.output();
+    assert!(out.contains(r#""fields":{}"#), "got: {out}");
+    let v = parse_line(out.trim());
+    assert!(v["fields"].as_object().unwrap().is_empty());
+}
+
+#[test]
+fn test_per_type_field_limits() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone()).with_per_type_field_limits(2, usize::MAX);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!(
+            s1 = "a",
+            n1 = 1,
+            s2 = "b",
+            s3 = "c",
+            n2 = 2.5,
+            s4 = ?"d",
+            n3 = -3,
+            ok = true,
+            "many strings"
+        );
+    });
+    let v = parse_line(w.output().trim());
+    let fields = &v["fields"];
+    assert_eq!(fields["message"], "many strings");
+    assert_eq!(fields["s1"], "a");
+    assert_eq!(fields["s2"], "b");
+    assert!(fields.get("s3").is_none());
+    assert!(fields.get("s4").is_none());
+    assert_eq!(fields["n1"], 1);
+    assert_eq!(fields["n2"], 2.5);
+    assert_eq!(fields["n3"], -3);
+    assert_eq!(fields["ok"], true);
+    assert_eq!(fields["dropped_string_fields"], 2);
+    assert!(fields.get("dropped_number_fields").is_none());
+}
+
+#[test]
+fn test_max_field_len() {
+    #[derive(Debug)]
+    #[allow(dead_code)]
+    struct Blob(&'static str);
+
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone()).with_max_field_len(8);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!(
+            ascii = "abcdefghijklmnop",
+            short = "abc",
+            // "é" is 2 bytes and straddles the 8-byte limit
+            multibyte = "abcdefgé",
+            debug = ?Blob("abcdefghijklmnop"),
+            display = %"quote\"quote\"quote",
+            "msg"
+        );
+    });
+    let out = w.output();
+    let v = parse_line(out.trim());
+    let fields = &v["fields"];
+    assert_eq!(fields["ascii"], "abcdefgh…");
+    assert_eq!(fields["short"], "abc");
+    assert_eq!(fields["multibyte"], "abcdefg…");
+    assert_eq!(fields["debug"], "Blob(\"ab…");
+    assert_eq!(fields["display"], "quote\"qu…");
+    assert_eq!(fields["message"], "msg");
+}
+
+#[test]
+fn test_max_line_len() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone()).with_max_line_len(200);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    let huge = "x".repeat(1000);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::warn!(huge = huge.as_str(), "too long");
+        tracing::info!("short");
+    });
+    let out = w.output();
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(lines.len(), 2);
+
+    let v = parse_line(lines[0]);
+    assert!(lines[0].len() < 200, "got: {}", lines[0]);
+    assert_eq!(v["truncated"], true);
+    assert_eq!(v["level"], "WARN");
+    assert!(v["timestamp"].is_string());
+    assert!(v.get("fields").is_none());
+
+    let v = parse_line(lines[1]);
+    assert!(v.get("truncated").is_none());
+    assert_eq!(v["fields"]["message"], "short");
+}
+
+#[test]
+fn test_max_line_len_timestamp_last() {
+    use tracing_microjson::FormatTime;
+
+    struct FixedTime;
+
+    impl FormatTime for FixedTime {
+        fn format_time(
+            &self,
+            w: &mut tracing_subscriber::fmt::format::Writer<'_>,
+        ) -> std::fmt::Result {
+            w.write_str("FIXED")
+        }
+    }
+
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone())
+        .with_timer(FixedTime)
+        .with_timestamp_last(true)
+        .with_line_hash(true)
+        .with_max_line_len(40);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!("this line is over forty bytes long");
+    });
+    assert_eq!(
+        w.output(),
+        "{\"level\":\"INFO\",\"timestamp\":\"FIXED\",\"truncated\":true}\n"
+    );
+}
+
+/// A timer that writes an unescaped quote into the timestamp string.
+struct QuotingTimer;
+
+impl tracing_microjson::FormatTime for QuotingTimer {
+    fn format_time(&self, w: &mut tracing_subscriber::fmt::format::Writer<'_>) -> std::fmt::Result {
+        w.write_str("12\"34")
+    }
+}
+
+#[test]
+#[should_panic(expected = "unbalanced line")]
+fn test_output_validation_catches_bad_timer() {
+    let layer = JsonLayer::new(TestWriter::new())
+        .with_timer(QuotingTimer)
+        .with_output_validation(true);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!("msg");
+    });
+}
+
+#[test]
+fn test_output_validation_passes_valid_lines() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone()).with_output_validation(true);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        let span = tracing::info_span!("outer", quote = "\"{[");
+        let _g = span.enter();
+        tracing::info!(braces = "}]", "msg \"quoted\"");
+    });
+    let v = parse_line(w.output().trim());
+    assert_eq!(v["fields"]["braces"], "}]");
+}
+
+#[test]
+fn test_truncation_marker() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone())
+        .with_max_field_len(4)
+        .with_truncation_marker("...(truncated)");
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!(s = "abcdefgh", d = ?"abcdefgh", "msg");
+    });
+    let v = parse_line(w.output().trim());
+    assert_eq!(v["fields"]["s"], "abcd...(truncated)");
+    assert_eq!(v["fields"]["d"], "\"abc...(truncated)");
+}
+
+/// A timer that writes a raw newline into the timestamp string.
+struct NewlineTimer;
+
+impl tracing_microjson::FormatTime for NewlineTimer {
+    fn format_time(&self, w: &mut tracing_subscriber::fmt::format::Writer<'_>) -> std::fmt::Result {
+        w.write_str("12\n34\r")
+    }
+}
+
+#[test]
+fn test_newline_safety() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone())
+        .with_timer(NewlineTimer)
+        .with_newline_safety(true);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!(text = "a\nb", "msg");
+    });
+    let out = w.output();
+    assert_eq!(out.lines().count(), 1, "got: {out}");
+    assert!(out.ends_with("}\n"));
+    let v = parse_line(out.trim());
+    assert_eq!(v["timestamp"], "12\n34\r");
+    assert_eq!(v["fields"]["text"], "a\nb");
+}
+
+#[test]
+fn test_raw_newline_splits_line_without_newline_safety() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone()).with_timer(NewlineTimer);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!("msg");
+    });
+    assert_eq!(w.output().lines().count(), 2);
+}
+
+#[test]
+fn test_raw_json_fields() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone()).with_raw_json_fields(["payload", "list"]);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        let payload = r#"{"a":1}"#;
+        let list = String::from("[1,\"two\"]");
+        tracing::info!(payload, list = %list, other = r#"{"a":1}"#, "received");
+    });
+    let out = w.output();
+    assert!(out.contains(r#""payload":{"a":1}"#), "got: {out}");
+    let v = parse_line(out.trim());
+    assert_eq!(v["fields"]["payload"]["a"], 1);
+    assert_eq!(v["fields"]["list"][1], "two");
+    assert_eq!(v["fields"]["other"], r#"{"a":1}"#);
+}
+
+#[test]
+fn test_raw_json_fields_recorded_with_debug() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone()).with_raw_json_fields(["payload"]);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        let payload = String::from(r#"{"a":1}"#);
+        tracing::info!(payload = ?payload, "received");
+    });
+    // The `Debug` quotes are written too, so this is a string, not an object
+    let v = parse_line(w.output().trim());
+    assert_eq!(v["fields"]["payload"], r#"{"a":1}"#);
+}
+
+fn bytes_output(encoding: BytesEncoding) -> serde_json::Value {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone()).with_bytes_encoding(encoding);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        let span = tracing::info_span!("req", key = ?Bytes(b"span"));
+        let _enter = span.enter();
+        tracing::info!(
+            one = ?Bytes(b"h"),
+            two = ?Bytes(b"hi"),
+            three = ?Bytes(b"hi!"),
+            empty = ?Bytes(b""),
+            nested = ?Some(Bytes(b"hi")),
+            plain = ?b"hi",
+            "bytes"
+        );
+    });
+    parse_line(w.output().trim())
+}
+
+#[test]
+fn test_bytes_base64() {
+    let v = bytes_output(BytesEncoding::Base64);
+    assert_eq!(v["fields"]["one"], "aA==");
+    assert_eq!(v["fields"]["two"], "aGk=");
+    assert_eq!(v["fields"]["three"], "aGkh");
+    assert_eq!(v["fields"]["empty"], "");
+    assert_eq!(v["fields"]["nested"], "Some([104, 105])");
+    assert_eq!(v["fields"]["plain"], "[104, 105]");
+    assert_eq!(v["span"]["key"], "c3Bhbg==");
+}
+
+#[test]
+fn test_bytes_hex() {
+    let v = bytes_output(BytesEncoding::Hex);
+    assert_eq!(v["fields"]["two"], "6869");
+    assert_eq!(v["fields"]["three"], "686921");
+    assert_eq!(v["fields"]["empty"], "");
+    assert_eq!(v["fields"]["nested"], "Some([104, 105])");
+    assert_eq!(v["span"]["key"], "7370616e");
+}
+
+#[test]
+fn test_bytes_array() {
+    let v = bytes_output(BytesEncoding::Array);
+    assert_eq!(v["fields"]["two"], serde_json::json!([104, 105]));
+    assert_eq!(v["fields"]["empty"], serde_json::json!([]));
+    assert_eq!(v["fields"]["nested"], "Some([104, 105])");
+    assert_eq!(v["span"]["key"], serde_json::json!([115, 112, 97, 110]));
+}
+
+#[test]
+fn test_bytes_typed() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone())
+        .with_typed_fields(true)
+        .with_bytes_encoding(BytesEncoding::Hex);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!(digest = ?Bytes(&[0xca, 0xfe]), n = 1, "hashed");
+    });
+    let out = w.output();
+    assert!(
+        out.contains(r#""digest":{"type":"bytes","value":"cafe"},"n":"#),
+        "got: {out}"
+    );
+}
+
+fn duration_output(format: DurationFormat) -> serde_json::Value {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone()).with_duration_format(format);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        let span = tracing::info_span!("req", timeout = ?DurationField(Duration::from_secs(30)));
+        let _enter = span.enter();
+        tracing::info!(
+            elapsed = ?DurationField(Duration::from_millis(1500)),
+            max = ?DurationField(Duration::MAX),
+            plain = ?Duration::from_millis(1500),
+            "timed"
+        );
+    });
+    parse_line(w.output().trim())
+}
+
+#[test]
+fn test_duration_nanos() {
+    let v = duration_output(DurationFormat::Nanos);
+    assert_eq!(v["fields"]["elapsed"], 1_500_000_000u64);
+    assert_eq!(v["fields"]["max"], Duration::MAX.as_nanos().to_string());
+    assert_eq!(v["fields"]["plain"], "1.5s");
+    assert_eq!(v["span"]["timeout"], 30_000_000_000u64);
+}
+
+#[test]
+fn test_duration_secs_nanos() {
+    let v = duration_output(DurationFormat::SecsNanos);
+    assert_eq!(
+        v["fields"]["elapsed"],
+        serde_json::json!({"secs": 1, "nanos": 500_000_000})
+    );
+    assert_eq!(
+        v["span"]["timeout"],
+        serde_json::json!({"secs": 30, "nanos": 0})
+    );
+}
+
+#[test]
+fn test_addresses_are_canonical_strings() {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+    let w = TestWriter::new();
+    let subscriber = tracing_subscriber::registry().with(JsonLayer::new(w.clone()));
+    tracing::subscriber::with_default(subscriber, || {
+        let v4 = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+        let v6 = IpAddr::V6(Ipv6Addr::LOCALHOST);
+        let socket = SocketAddr::new(v6, 8080);
+        tracing::info!(v4 = %v4, v6 = ?v6, socket = ?socket, "addresses");
+    });
+    let v = parse_line(w.output().trim());
+    assert_eq!(v["fields"]["v4"], "192.0.2.1");
+    assert_eq!(v["fields"]["v6"], "::1");
+    assert_eq!(v["fields"]["socket"], "[::1]:8080");
+}
+
+#[test]
+fn test_message_kv_parsing() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone()).with_message_kv_parsing(true);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        let user = 5;
+        tracing::info!("user={user} action=login a=b=c =x y= \"q\"=1 done");
+        tracing::info!("no pairs here");
+    });
+    let out = w.output();
+    let lines: Vec<&str> = out.lines().collect();
+
+    let v = parse_line(lines[0]);
+    assert_eq!(
+        v["fields"]["message"],
+        "user=5 action=login a=b=c =x y= \"q\"=1 done"
+    );
+    assert_eq!(
+        v["message_fields"],
+        serde_json::json!({"user": "5", "action": "login"})
+    );
+
+    let v = parse_line(lines[1]);
+    assert!(v.get("message_fields").is_none());
+}
+
+#[test]
+fn test_js_safe_escape() {
+    let emit = |layer: JsonLayer<TestWriter>, w: &TestWriter| {
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(s = "a\u{2028}b\u{2029}c", d = %"x\u{2028}", "\u{2029}");
+        });
+        w.output()
+    };
+
+    let w = TestWriter::new();
+    let out = emit(JsonLayer::new(w.clone()).with_js_safe_escape(true), &w);
+    assert!(
+        !out.contains('\u{2028}') && !out.contains('\u{2029}'),
+        "got: {out}"
+    );
+    assert!(out.contains(r#""s":"a\u2028b\u2029c""#), "got: {out}");
+    assert!(out.contains(r#""d":"x\u2028""#), "got: {out}");
+    let v = parse_line(out.trim());
+    assert_eq!(v["fields"]["s"], "a\u{2028}b\u{2029}c");
+    assert_eq!(v["fields"]["message"], "\u{2029}");
+
+    let w = TestWriter::new();
+    let out = emit(JsonLayer::new(w.clone()), &w);
+    assert!(out.contains('\u{2028}') && out.contains('\u{2029}'));
+}
+
+#[test]
+fn test_escape_forward_slash() {
+    let emit = |layer: JsonLayer<TestWriter>, w: &TestWriter| {
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(html = "</script>", "a/b");
+        });
+        w.output()
+    };
+
+    let w = TestWriter::new();
+    let out = emit(
+        JsonLayer::new(w.clone()).with_escape_forward_slash(true),
+        &w,
+    );
+    assert!(out.contains(r#""html":"<\/script>""#), "got: {out}");
+    assert!(out.contains(r#""message":"a\/b""#), "got: {out}");
+    assert_eq!(parse_line(out.trim())["fields"]["html"], "</script>");
+
+    let w = TestWriter::new();
+    let out = emit(JsonLayer::new(w.clone()), &w);
+    assert!(out.contains(r#""html":"</script>""#), "got: {out}");
+}
+
+#[test]
+fn test_require_message() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone()).with_require_message(true);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!(x = 1);
+        tracing::info!(x = 2, "kept");
+    });
+    let out = w.output();
+    assert_eq!(out.lines().count(), 1, "got: {out}");
+    let v = parse_line(out.trim());
+    assert_eq!(v["fields"]["message"], "kept");
+    assert_eq!(v["fields"]["x"], 2);
+}
+
+#[test]
+fn test_default_message() {
+    for flatten in [false, true] {
+        let w = TestWriter::new();
+        let layer = JsonLayer::new(w.clone())
+            .without_time()
+            .with_target(false)
+            .flatten_event(flatten)
+            .with_default_message("(no message)");
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::event!(target: "empty", tracing::Level::INFO, {});
+            tracing::info!(n = 1);
+            tracing::info!(n = 2, "own message");
+        });
+        let out = w.output();
+        let lines: Vec<&str> = out.lines().collect();
+        if flatten {
+            assert_eq!(lines[0], r#"{"level":"INFO","message":"(no message)"}"#);
+            assert_eq!(
+                lines[1],
+                r#"{"level":"INFO","message":"(no message)","n":1}"#
+            );
+            assert_eq!(
+                lines[2],
+                r#"{"level":"INFO","message":"own message","n":2}"#
+            );
+        } else {
+            assert_eq!(
+                lines[0],
+                r#"{"level":"INFO","fields":{"message":"(no message)"}}"#
+            );
+            assert_eq!(
+                lines[1],
+                r#"{"level":"INFO","fields":{"message":"(no message)","n":1}}"#
+            );
+            assert_eq!(
+                lines[2],
+                r#"{"level":"INFO","fields":{"message":"own message","n":2}}"#
+            );
+        }
+    }
+}
+
+#[test]
+fn test_default_message_with_require_message() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone())
+        .with_require_message(true)
+        .with_default_message("(no message)");
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!(n = 1);
+    });
+    assert!(w.output().is_empty());
+}
+
+#[test]
+fn test_smart_debug() {
+    #[derive(Debug)]
+    #[allow(dead_code)]
+    struct Point {
+        x: i32,
+        y: i32,
+    }
+
+    let mut map = std::collections::BTreeMap::new();
+    map.insert("a", vec![Some(1.5), None]);
+
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone()).with_smart_debug(true);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!(
+            some = ?Some(5),
+            none = ?None::<u8>,
+            list = ?vec![1, -2, 3],
+            map = ?map,
+            quoted = ?"say \"hi\"\n\u{1f}",
+            point = ?Point { x: 1, y: 2 },
+            tuple = ?(1, 2),
+            nan = ?Some(f64::NAN),
+            "[1, 2]"
+        );
+    });
+    let v = parse_line(w.output().trim());
+    let fields = &v["fields"];
+    assert_eq!(fields["some"], 5);
+    assert!(fields["none"].is_null());
+    assert_eq!(fields["list"], serde_json::json!([1, -2, 3]));
+    assert_eq!(fields["map"], serde_json::json!({"a": [1.5, null]}));
+    assert_eq!(fields["quoted"], "say \"hi\"\n\u{1f}");
+    assert_eq!(fields["point"], "Point { x: 1, y: 2 }");
+    assert_eq!(fields["tuple"], "(1, 2)");
+    assert_eq!(fields["nan"], "Some(NaN)");
+    assert_eq!(fields["message"], "[1, 2]");
+}
+
+#[test]
+fn test_smart_debug_disabled() {
+    let w = TestWriter::new();
+    let subscriber = tracing_subscriber::registry().with(JsonLayer::new(w.clone()));
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!(some = ?Some(5), none = ?None::<u8>);
+    });
+    let v = parse_line(w.output().trim());
+    assert_eq!(v["fields"]["some"], "Some(5)");
+    assert_eq!(v["fields"]["none"], "None");
+}
+
+#[test]
+fn test_smart_debug_chars() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone()).with_smart_debug(true);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!(
+            c = ?'a',
+            quote = ?'\'',
+            newline = ?'\n',
+            accent = ?'é',
+            list = ?['x', '"'],
+            display = %'a',
+        );
+    });
+    let v = parse_line(w.output().trim());
+    let fields = &v["fields"];
+    assert_eq!(fields["c"], "a");
+    assert_eq!(fields["quote"], "'");
+    assert_eq!(fields["newline"], "\n");
+    assert_eq!(fields["accent"], "é");
+    assert_eq!(fields["list"], serde_json::json!(["x", "\""]));
+    assert_eq!(fields["display"], "a");
+
+    // Without it, the Debug quotes are kept
+    let w = TestWriter::new();
+    let subscriber = tracing_subscriber::registry().with(JsonLayer::new(w.clone()));
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!(c = ?'a');
+    });
+    assert_eq!(parse_line(w.output().trim())["fields"]["c"], "'a'");
+}
@@ -1,8 +1,20 @@
+#![cfg(feature = "std")]
+
 mod common;
 
+mod allocations;
 mod basic_events;
 mod compatibility;
 mod configuration;
 mod edge_cases;
+#[cfg(feature = "msgpack")]
+mod msgpack;
+#[cfg(feature = "opentelemetry")]
+mod otel;
+mod presets;
+mod spans;
 mod threads;
 mod timestamps;
+#[cfg(all(tracing_unstable, feature = "valuable"))]
+mod valuable;
+mod writers;
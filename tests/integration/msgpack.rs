@@ -0,0 +1,105 @@
+use super::common::TestWriter;
+use tracing_microjson::{JsonLayer, MsgPackLayer};
+use tracing_subscriber::prelude::*;
+
+/// Decode a sequence of back-to-back MessagePack values.
+fn decode_all(bytes: &[u8]) -> Vec<serde_json::Value> {
+    let mut rest = bytes;
+    let mut values = Vec::new();
+    while !rest.is_empty() {
+        values.push(rmp_serde::from_read(&mut rest).expect("valid MessagePack"));
+    }
+    values
+}
+
+#[test]
+fn test_msgpack_event_fields() {
+    let w = TestWriter::new();
+    let subscriber = tracing_subscriber::registry().with(MsgPackLayer::new(w.clone()));
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!(
+            small = 5u64,
+            medium = 300u64,
+            large = 5_000_000_000u64,
+            neg = -200i64,
+            tiny_neg = -3i64,
+            ratio = 0.25,
+            ok = true,
+            name = "alice",
+            long = "x".repeat(300).as_str(),
+            big = u128::MAX,
+            "hello {}",
+            "world"
+        );
+    });
+    let events = decode_all(&w.bytes());
+    assert_eq!(events.len(), 1);
+    let v = &events[0];
+    assert!(v["timestamp"].is_string());
+    assert_eq!(v["level"], "INFO");
+    assert_eq!(v["target"], module_path!());
+    let fields = &v["fields"];
+    assert_eq!(fields["message"], "hello world");
+    assert_eq!(fields["small"], 5);
+    assert_eq!(fields["medium"], 300);
+    assert_eq!(fields["large"], 5_000_000_000u64);
+    assert_eq!(fields["neg"], -200);
+    assert_eq!(fields["tiny_neg"], -3);
+    assert_eq!(fields["ratio"], 0.25);
+    assert_eq!(fields["ok"], true);
+    assert_eq!(fields["name"], "alice");
+    assert_eq!(fields["long"].as_str().unwrap().len(), 300);
+    assert_eq!(fields["big"], u128::MAX.to_string());
+    assert!(v.get("span").is_none());
+}
+
+#[test]
+fn test_msgpack_spans_and_options() {
+    let w = TestWriter::new();
+    let layer = MsgPackLayer::new(w.clone())
+        .with_target(false)
+        .without_time();
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        let outer = tracing::info_span!("outer", id = 1);
+        let _o = outer.enter();
+        let inner = tracing::info_span!("inner", user = tracing::field::Empty);
+        inner.record("user", "bob");
+        let _i = inner.enter();
+        tracing::warn!("one");
+        tracing::warn!("two");
+    });
+    let events = decode_all(&w.bytes());
+    assert_eq!(events.len(), 2);
+    let v = &events[1];
+    assert!(v.get("timestamp").is_none());
+    assert!(v.get("target").is_none());
+    assert_eq!(v["fields"]["message"], "two");
+    assert_eq!(
+        v["span"],
+        serde_json::json!({"name": "inner", "user": "bob"})
+    );
+    assert_eq!(
+        v["spans"],
+        serde_json::json!([{"name": "outer", "id": 1}, {"name": "inner", "user": "bob"}])
+    );
+}
+
+#[test]
+fn test_msgpack_alongside_json_layer() {
+    let json = TestWriter::new();
+    let msgpack = TestWriter::new();
+    let subscriber = tracing_subscriber::registry()
+        .with(JsonLayer::new(json.clone()))
+        .with(MsgPackLayer::new(msgpack.clone()));
+    tracing::subscriber::with_default(subscriber, || {
+        let span = tracing::info_span!("req", n = 1);
+        let _g = span.enter();
+        tracing::info!("both");
+    });
+    let from_json = super::common::parse_line(json.output().trim());
+    let from_msgpack = &decode_all(&msgpack.bytes())[0];
+    for key in ["level", "fields", "target", "span", "spans"] {
+        assert_eq!(from_json[key], from_msgpack[key], "key {key}");
+    }
+}
@@ -0,0 +1,98 @@
+use super::common::{TestWriter, parse_line};
+use opentelemetry::trace::{TraceContextExt, TracerProvider};
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use tracing_microjson::JsonLayer;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::prelude::*;
+
+#[test]
+fn test_otel_ids() {
+    let provider = SdkTracerProvider::default();
+    let w = TestWriter::new();
+    let subscriber = tracing_subscriber::registry()
+        .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")))
+        .with(JsonLayer::new(w.clone()).with_otel_ids(true));
+
+    let mut expected = Vec::new();
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!("outside");
+        let outer = tracing::info_span!("outer");
+        let _o = outer.enter();
+        tracing::info!("in outer");
+        let inner = tracing::info_span!("inner");
+        let _i = inner.enter();
+        tracing::info!("in inner");
+        for span in [&outer, &inner] {
+            let cx = span.context();
+            let span_cx = cx.span().span_context().clone();
+            expected.push((
+                format!("{:032x}", span_cx.trace_id()),
+                format!("{:016x}", span_cx.span_id()),
+            ));
+        }
+    });
+
+    let out = w.output();
+    let lines: Vec<_> = out.lines().map(parse_line).collect();
+    assert!(lines[0].get("trace_id").is_none());
+    assert!(lines[0].get("span_id").is_none());
+
+    let (trace_id, outer_span_id) = &expected[0];
+    let (_, inner_span_id) = &expected[1];
+    assert_eq!(trace_id.len(), 32);
+    assert_eq!(lines[1]["trace_id"], *trace_id);
+    assert_eq!(lines[1]["span_id"], *outer_span_id);
+    // The innermost span's context wins; both share the trace
+    assert_eq!(lines[2]["trace_id"], *trace_id);
+    assert_eq!(lines[2]["span_id"], *inner_span_id);
+}
+
+#[test]
+fn test_otel_ids_off_by_default() {
+    let provider = SdkTracerProvider::default();
+    let w = TestWriter::new();
+    let subscriber = tracing_subscriber::registry()
+        .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")))
+        .with(JsonLayer::new(w.clone()));
+    tracing::subscriber::with_default(subscriber, || {
+        let span = tracing::info_span!("req");
+        let _g = span.enter();
+        tracing::info!("inside");
+    });
+    let v = parse_line(w.output().trim());
+    assert!(v.get("trace_id").is_none());
+    assert!(v.get("span_id").is_none());
+}
+
+#[test]
+fn test_datadog_ids() {
+    let provider = SdkTracerProvider::default();
+    let w = TestWriter::new();
+    let subscriber = tracing_subscriber::registry()
+        .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")))
+        .with(JsonLayer::datadog(w.clone()));
+
+    let mut expected = None;
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!("outside");
+        let span = tracing::info_span!("req");
+        let _g = span.enter();
+        tracing::info!("inside");
+        let cx = span.context();
+        let span_cx = cx.span().span_context().clone();
+        let trace_id = u128::from_be_bytes(span_cx.trace_id().to_bytes()) as u64;
+        let span_id = u64::from_be_bytes(span_cx.span_id().to_bytes());
+        expected = Some((trace_id.to_string(), span_id.to_string()));
+    });
+
+    let out = w.output();
+    let lines: Vec<_> = out.lines().map(parse_line).collect();
+    assert!(lines[0].get("dd.trace_id").is_none());
+    assert!(lines[0].get("dd.span_id").is_none());
+
+    let (trace_id, span_id) = expected.unwrap();
+    assert_eq!(lines[1]["dd.trace_id"], trace_id);
+    assert_eq!(lines[1]["dd.span_id"], span_id);
+    assert_eq!(lines[1]["status"], "info");
+    assert!(lines[1].get("trace_id").is_none());
+}
@@ -0,0 +1,155 @@
+use super::common::{TestWriter, parse_line};
+use tracing_microjson::JsonLayer;
+use tracing_subscriber::prelude::*;
+
+#[test]
+fn test_gcp_preset() {
+    let w = TestWriter::new();
+    let subscriber = tracing_subscriber::registry().with(JsonLayer::gcp(w.clone()));
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::warn!(user = "alice", "disk almost full");
+        tracing::trace!("detail");
+    });
+    let out = w.output();
+    let mut lines = out.lines();
+
+    let line = lines.next().unwrap();
+    assert!(line.starts_with(r#"{"time":""#), "got: {line}");
+    let v = parse_line(line);
+    assert!(v["time"].is_string());
+    assert_eq!(v["severity"], "WARNING");
+    assert_eq!(v["message"], "disk almost full");
+    assert_eq!(v["user"], "alice");
+    assert!(v.get("timestamp").is_none());
+    assert!(v.get("level").is_none());
+    assert!(v.get("fields").is_none());
+
+    // TRACE has no Cloud Logging equivalent and maps to DEBUG
+    let v = parse_line(lines.next().unwrap());
+    assert_eq!(v["severity"], "DEBUG");
+}
+
+#[test]
+fn test_ecs_preset() {
+    let w = TestWriter::new();
+    let subscriber = tracing_subscriber::registry().with(JsonLayer::ecs(w.clone()));
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!(user = "alice", "signed in");
+    });
+    let line = w.output();
+    assert!(line.starts_with(r#"{"@timestamp":""#), "got: {line}");
+    let v = parse_line(line.trim());
+    assert!(v["@timestamp"].is_string());
+    // Dotted ECS names are literal keys, not nested objects
+    assert_eq!(v["log.level"], "info");
+    assert_eq!(v["ecs.version"], "1.6.0");
+    assert!(v.get("log").is_none());
+    assert_eq!(v["message"], "signed in");
+    assert_eq!(v["user"], "alice");
+    assert!(v.get("timestamp").is_none());
+    assert!(v.get("level").is_none());
+    assert!(v.get("fields").is_none());
+}
+
+#[test]
+fn test_compact_preset() {
+    let w = TestWriter::new();
+    let subscriber = tracing_subscriber::registry().with(JsonLayer::compact(w.clone()));
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!(user = "alice", "hello");
+    });
+    let v = parse_line(w.output().trim());
+    let keys: Vec<&String> = v.as_object().unwrap().keys().collect();
+    assert_eq!(keys.len(), 2, "got: {keys:?}");
+    assert_eq!(v["level"], "INFO");
+    assert_eq!(v["fields"]["message"], "hello");
+    assert_eq!(v["fields"]["user"], "alice");
+}
+
+#[test]
+fn test_datadog_status() {
+    let w = TestWriter::new();
+    let subscriber = tracing_subscriber::registry().with(JsonLayer::datadog(w.clone()));
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::trace!("trace");
+        tracing::debug!("debug");
+        tracing::info!(user = "alice", "info");
+        tracing::warn!("warn");
+        tracing::error!("error");
+    });
+    let out = w.output();
+    let lines: Vec<_> = out.lines().map(parse_line).collect();
+    let statuses: Vec<_> = lines.iter().map(|v| v["status"].clone()).collect();
+    assert_eq!(statuses, ["debug", "debug", "info", "warning", "error"]);
+    assert!(lines[2].get("level").is_none());
+    assert_eq!(lines[2]["message"], "info");
+    assert_eq!(lines[2]["user"], "alice");
+    // No span, so no trace context
+    assert!(lines[2].get("dd.trace_id").is_none());
+}
+
+#[test]
+fn test_dev_preset() {
+    let w = TestWriter::new();
+    let subscriber = tracing_subscriber::registry().with(JsonLayer::dev(w.clone()));
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!(user = "alice", "hello");
+    });
+    let out = w.output();
+    assert!(out.lines().count() > 1, "got: {out}");
+    let v: serde_json::Value = serde_json::from_str(&out).expect("valid JSON");
+    assert_eq!(v["level"], "info");
+    assert_eq!(v["message"], "hello");
+    assert_eq!(v["user"], "alice");
+    assert!(v["timestamp"].is_string());
+    assert!(v["filename"].as_str().unwrap().ends_with("presets.rs"));
+    assert!(v["line_number"].is_u64());
+}
+
+#[test]
+fn test_from_env() {
+    // The only test that touches MICROJSON_* variables, so nothing else
+    // reads them while they are set
+    let emit = || {
+        let w = TestWriter::new();
+        let subscriber = tracing_subscriber::registry().with(JsonLayer::from_env(w.clone()));
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(user = "alice", "configured");
+        });
+        parse_line(w.output().trim())
+    };
+
+    // Unset: the defaults
+    let v = emit();
+    assert!(v["timestamp"].is_string());
+    assert!(v["target"].is_string());
+    assert_eq!(v["fields"]["user"], "alice");
+
+    // SAFETY: no other thread reads or writes these variables
+    unsafe {
+        std::env::set_var("MICROJSON_TIME", "0");
+        std::env::set_var("MICROJSON_TARGET", "false");
+        std::env::set_var("MICROJSON_THREAD_IDS", "1");
+        std::env::set_var("MICROJSON_FLATTEN", "true");
+        std::env::set_var("MICROJSON_FILE", "yes");
+    }
+    let v = emit();
+    unsafe {
+        for name in [
+            "MICROJSON_TIME",
+            "MICROJSON_TARGET",
+            "MICROJSON_THREAD_IDS",
+            "MICROJSON_FLATTEN",
+            "MICROJSON_FILE",
+        ] {
+            std::env::remove_var(name);
+        }
+    }
+    assert!(v.get("timestamp").is_none());
+    assert!(v.get("target").is_none());
+    assert!(v["threadId"].is_string());
+    assert_eq!(v["user"], "alice");
+    assert!(v.get("fields").is_none());
+    // Not an accepted value, so the default is kept
+    assert!(v.get("filename").is_none());
+}
@@ -0,0 +1,370 @@
+use super::common::{TestWriter, parse_line};
+use tracing_microjson::{JsonLayer, SpanShape};
+use tracing_subscriber::prelude::*;
+
+#[test]
+fn test_span_ids() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone()).with_span_ids(true);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    let (outer_id, inner_id) = tracing::subscriber::with_default(subscriber, || {
+        let outer = tracing::info_span!("outer", req = "r1");
+        let _og = outer.enter();
+        let inner = tracing::info_span!("inner");
+        let _ig = inner.enter();
+        tracing::info!("with ids");
+        (outer.id().unwrap(), inner.id().unwrap())
+    });
+    let v = parse_line(w.output().trim());
+    let id = v["span"]["id"]
+        .as_u64()
+        .expect("span id should be an integer");
+    assert!(id > 0);
+    assert_eq!(id, inner_id.into_u64());
+
+    let spans = v["spans"].as_array().expect("spans array");
+    assert_eq!(spans[0]["id"], outer_id.into_u64());
+    assert_eq!(spans[0]["req"], "r1");
+    assert_eq!(spans[1]["id"], inner_id.into_u64());
+}
+
+#[test]
+fn test_span_target() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone()).with_span_target(true);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        let outer = tracing::info_span!(target: "app::http", "request", id = 7);
+        let _og = outer.enter();
+        let inner = tracing::info_span!(target: "lib::db", "query");
+        let _ig = inner.enter();
+        tracing::info!(target: "app::handler", "inside");
+    });
+    let line = w.output();
+    assert!(
+        line.contains(r#""span":{"name":"query","target":"lib::db"}"#),
+        "got: {line}"
+    );
+    let v = parse_line(line.trim());
+    assert_eq!(v["target"], "app::handler");
+    assert_eq!(v["spans"][0]["target"], "app::http");
+    assert_eq!(v["spans"][0]["id"], 7);
+    assert_eq!(v["spans"][1]["target"], "lib::db");
+}
+
+#[test]
+fn test_span_target_in_tuple_shape() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone())
+        .with_span_target(true)
+        .with_span_element_shape(SpanShape::Tuple);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        let span = tracing::info_span!(target: "lib::db", "query");
+        let _g = span.enter();
+        tracing::info!("inside");
+    });
+    let line = w.output();
+    assert!(
+        line.contains(r#""spans":[["query",{"target":"lib::db"}]]"#),
+        "got: {line}"
+    );
+}
+
+#[test]
+fn test_span_ids_absent_by_default() {
+    let w = TestWriter::new();
+    let subscriber = tracing_subscriber::registry().with(JsonLayer::new(w.clone()));
+    tracing::subscriber::with_default(subscriber, || {
+        let span = tracing::info_span!("my_span");
+        let _g = span.enter();
+        tracing::info!("no ids");
+    });
+    let v = parse_line(w.output().trim());
+    assert!(v["span"].get("id").is_none());
+}
+
+#[test]
+fn test_span_tree_ids() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone()).with_span_tree_ids(true);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    let (outer_id, inner_id) = tracing::subscriber::with_default(subscriber, || {
+        let outer = tracing::info_span!("outer");
+        let _og = outer.enter();
+        tracing::info!("in outer");
+        let inner = tracing::info_span!("inner");
+        let _ig = inner.enter();
+        tracing::info!("in inner");
+        (outer.id().unwrap(), inner.id().unwrap())
+    });
+    let out = w.output();
+    let lines: Vec<&str> = out.lines().collect();
+
+    let v = parse_line(lines[0]);
+    assert_eq!(v["span_id"], outer_id.into_u64());
+    assert!(v.get("parent_span_id").is_none());
+
+    let v = parse_line(lines[1]);
+    assert_eq!(v["span_id"], inner_id.into_u64());
+    assert_eq!(v["parent_span_id"], outer_id.into_u64());
+}
+
+#[test]
+fn test_parent_span() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone())
+        .with_parent_span(true)
+        .with_span_list(false);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        let outer = tracing::info_span!("outer");
+        let _og = outer.enter();
+        tracing::info!("in outer");
+        let inner = tracing::info_span!("inner");
+        let _ig = inner.enter();
+        tracing::info!("in inner");
+    });
+    let out = w.output();
+    let lines: Vec<&str> = out.lines().collect();
+
+    let v = parse_line(lines[0]);
+    assert!(v.get("parent_span").is_none());
+
+    let v = parse_line(lines[1]);
+    assert_eq!(v["parent_span"], "outer");
+    assert_eq!(v["span"]["name"], "inner");
+}
+
+#[test]
+fn test_span_field_names() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone())
+        .with_span_field_name("currentSpan")
+        .with_spans_field_name("spanStack");
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        let span = tracing::info_span!("req");
+        let _guard = span.enter();
+        tracing::info!("renamed");
+    });
+    let v = parse_line(w.output().trim());
+    assert_eq!(v["currentSpan"]["name"], "req");
+    assert_eq!(v["spanStack"][0]["name"], "req");
+    assert!(v.get("span").is_none());
+    assert!(v.get("spans").is_none());
+}
+
+#[test]
+fn test_span_path() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone()).with_span_path(true);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!("outside");
+        let outer = tracing::info_span!("outer");
+        let _og = outer.enter();
+        let inner = tracing::info_span!("inner");
+        let _ig = inner.enter();
+        tracing::info!("inside");
+    });
+    let out = w.output();
+    let lines: Vec<&str> = out.lines().collect();
+    assert!(parse_line(lines[0]).get("span_path").is_none());
+    assert_eq!(parse_line(lines[1])["span_path"], "outer>inner");
+}
+
+#[test]
+fn test_span_path_escaping() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone())
+        .with_span_path(true)
+        .with_span_path_separator("/")
+        .with_current_span(false)
+        .with_span_list(false);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        let outer = tracing::info_span!("GET /users");
+        let _og = outer.enter();
+        let inner = tracing::info_span!("a\\\"b");
+        let _ig = inner.enter();
+        tracing::info!("inside");
+    });
+    let v = parse_line(w.output().trim());
+    assert_eq!(v["span_path"], r#"GET \/users/a\\"b"#);
+    assert!(v.get("span").is_none());
+}
+
+#[test]
+fn test_current_span_and_span_list_toggles() {
+    for (current, list) in [(true, true), (true, false), (false, true), (false, false)] {
+        let w = TestWriter::new();
+        let layer = JsonLayer::new(w.clone())
+            .with_current_span(current)
+            .with_span_list(list);
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            let outer = tracing::info_span!("outer");
+            let _og = outer.enter();
+            let inner = tracing::info_span!("inner", k = "v");
+            let _ig = inner.enter();
+            tracing::info!("toggled");
+        });
+        let v = parse_line(w.output().trim());
+        let ctx = format!("current={current} list={list}: {v}");
+        if current {
+            assert_eq!(v["span"]["name"], "inner", "{ctx}");
+            assert_eq!(v["span"]["k"], "v", "{ctx}");
+        } else {
+            assert!(v.get("span").is_none(), "{ctx}");
+        }
+        if list {
+            let spans = v["spans"].as_array().expect("spans array");
+            assert_eq!(spans.len(), 2, "{ctx}");
+            assert_eq!(spans[0]["name"], "outer", "{ctx}");
+        } else {
+            assert!(v.get("spans").is_none(), "{ctx}");
+        }
+        assert_eq!(v["fields"]["message"], "toggled", "{ctx}");
+    }
+}
+
+#[test]
+fn test_redacted_fields_apply_to_spans_and_events() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone()).with_redacted_fields(["password", "token"]);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        let span = tracing::info_span!(
+            "login",
+            user = "alice",
+            password = "hunter2",
+            token = tracing::field::Empty
+        );
+        let _g = span.enter();
+        span.record("token", "abc123");
+        tracing::info!(token = "xyz", attempt = 1u64, "logged in");
+    });
+    let v = parse_line(w.output().trim());
+    assert_eq!(v["span"]["user"], "alice");
+    assert_eq!(v["span"]["password"], "[REDACTED]");
+    assert_eq!(v["span"]["token"], "[REDACTED]");
+    assert_eq!(v["spans"][0]["password"], "[REDACTED]");
+    assert_eq!(v["fields"]["token"], "[REDACTED]");
+    assert_eq!(v["fields"]["attempt"], 1);
+    assert_eq!(v["fields"]["message"], "logged in");
+    assert!(!w.output().contains("hunter2"));
+}
+
+#[test]
+fn test_span_field_types_round_trip() {
+    #[derive(Debug)]
+    #[allow(dead_code)]
+    struct Point {
+        x: i32,
+    }
+
+    let w = TestWriter::new();
+    let subscriber = tracing_subscriber::registry().with(JsonLayer::new(w.clone()));
+    tracing::subscriber::with_default(subscriber, || {
+        let span = tracing::info_span!(
+            "typed",
+            s = "text \"quoted\"",
+            u = 1u64,
+            i = -2i64,
+            f = 1.5f64,
+            b = true,
+            big = u128::MAX,
+            p = ?Point { x: 3 },
+        );
+        let _g = span.enter();
+        tracing::info!("event");
+    });
+    let out = w.output();
+    let v = parse_line(out.trim());
+    let span = &v["span"];
+    assert_eq!(span["s"], "text \"quoted\"");
+    assert_eq!(span["u"], 1);
+    assert_eq!(span["i"], -2);
+    assert_eq!(span["f"], 1.5);
+    assert_eq!(span["b"], true);
+    assert_eq!(span["big"], u128::MAX.to_string());
+    assert_eq!(span["p"], "Point { x: 3 }");
+    // Span fields are written in recording order after the name
+    assert!(
+        out.contains(r#""span":{"name":"typed","s":"text \"quoted\"","u":1,"i":-2,"#),
+        "got: {out}"
+    );
+}
+
+#[test]
+fn test_span_element_shapes() {
+    for (shape, expected) in [
+        (
+            SpanShape::Object,
+            serde_json::json!([{"name": "outer", "a": 1}, {"name": "inner", "b": "x"}]),
+        ),
+        (SpanShape::Name, serde_json::json!(["outer", "inner"])),
+        (
+            SpanShape::Tuple,
+            serde_json::json!([["outer", {"a": 1}], ["inner", {"b": "x"}]]),
+        ),
+    ] {
+        let w = TestWriter::new();
+        let layer = JsonLayer::new(w.clone()).with_span_element_shape(shape);
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            let outer = tracing::info_span!("outer", a = 1);
+            let _o = outer.enter();
+            let inner = tracing::info_span!("inner", b = "x");
+            let _i = inner.enter();
+            tracing::info!("nested");
+        });
+        let v = parse_line(w.output().trim());
+        assert_eq!(v["spans"], expected, "shape={shape:?}");
+        // The current span is always written as an object
+        assert_eq!(v["span"]["name"], "inner", "shape={shape:?}");
+    }
+}
+
+#[test]
+fn test_tuple_span_shape_with_ids() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone())
+        .with_span_element_shape(SpanShape::Tuple)
+        .with_span_ids(true);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        let span = tracing::info_span!("req", user = "alice");
+        let _g = span.enter();
+        tracing::info!("inside");
+    });
+    let v = parse_line(w.output().trim());
+    let element = &v["spans"][0];
+    assert_eq!(element[0], "req");
+    assert!(element[1]["id"].is_u64());
+    assert_eq!(element[1]["user"], "alice");
+}
+
+#[test]
+fn test_max_span_depth() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone()).with_max_span_depth(3);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        let _entered: Vec<_> = (0..10)
+            .map(|depth| tracing::info_span!("nested", depth).entered())
+            .collect();
+        tracing::info!("deep");
+    });
+
+    let v = parse_line(w.output().trim());
+    let depths: Vec<_> = v["spans"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|span| span["depth"].clone())
+        .collect();
+    assert_eq!(depths, [7, 8, 9]);
+    assert_eq!(v["span"]["depth"], 9);
+}
@@ -71,3 +71,26 @@ fn test_thread_id_and_name_together() {
     assert!(v["threadId"].is_string());
     assert!(v["threadName"].is_string());
 }
+
+#[test]
+fn test_delta_time() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone()).with_delta_time(true);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    // A fresh thread, so the first event has no predecessor
+    std::thread::spawn(move || {
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!("first");
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            tracing::info!("second");
+        });
+    })
+    .join()
+    .unwrap();
+    let out = w.output();
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(parse_line(lines[0])["delta_ms"], 0.0);
+    let delta = parse_line(lines[1])["delta_ms"].as_f64().unwrap();
+    assert!(delta >= 20.0, "got: {delta}");
+}
@@ -111,6 +111,45 @@ fn test_default_timer_produces_rfc3339() {
     assert_eq!(&ts[10..11], "T", "timestamp should have T separator");
 }
 
+#[test]
+fn test_span_open_time() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone()).with_span_open_time(true);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        let span = tracing::info_span!("job", id = 3);
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        let _g = span.enter();
+        tracing::info!("started");
+    });
+    let v = parse_line(w.output().trim());
+    let opened_at = v["span"]["opened_at"]
+        .as_str()
+        .expect("opened_at should be a string");
+    assert!(opened_at.ends_with('Z'), "got: {opened_at}");
+    assert_eq!(opened_at.len(), 27, "got: {opened_at}");
+    assert_eq!(&opened_at[10..11], "T");
+    // Same fixed-width format, so the order is lexicographic
+    assert!(opened_at < v["timestamp"].as_str().unwrap());
+    assert_eq!(v["span"]["id"], 3);
+    assert_eq!(v["spans"][0]["opened_at"], opened_at);
+}
+
+#[test]
+fn test_span_open_time_without_time() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone())
+        .with_span_open_time(true)
+        .without_time();
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        let span = tracing::info_span!("job");
+        let _g = span.enter();
+        tracing::info!("started");
+    });
+    assert!(w.output().contains(r#""span":{"name":"job"}"#));
+}
+
 #[test]
 fn test_without_time_valid_json_flat() {
     let w = TestWriter::new();
@@ -125,3 +164,105 @@ fn test_without_time_valid_json_flat() {
     assert_eq!(v["message"], "flat no time");
     assert_eq!(v["key"], "val");
 }
+
+#[test]
+fn test_timestamp_last() {
+    use tracing_microjson::FormatTime;
+
+    struct FixedTime;
+
+    impl FormatTime for FixedTime {
+        fn format_time(
+            &self,
+            w: &mut tracing_subscriber::fmt::format::Writer<'_>,
+        ) -> std::fmt::Result {
+            w.write_str("FIXED")
+        }
+    }
+
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone())
+        .with_timer(FixedTime)
+        .with_timestamp_last(true);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        let span = tracing::info_span!("my_span");
+        let _g = span.enter();
+        tracing::info!("last");
+    });
+    let out = w.output();
+    let line = out.trim();
+    assert!(line.starts_with(r#"{"level":"INFO","#), "got: {line}");
+    assert!(
+        line.ends_with(r#","timestamp":"FIXED"}"#),
+        "timestamp should be the last key, got: {line}"
+    );
+    let v = parse_line(line);
+    assert_eq!(v["timestamp"], "FIXED");
+    assert_eq!(v["span"]["name"], "my_span");
+}
+
+#[test]
+fn test_timestamp_last_flattened() {
+    use tracing_microjson::FormatTime;
+
+    struct FixedTime;
+
+    impl FormatTime for FixedTime {
+        fn format_time(
+            &self,
+            w: &mut tracing_subscriber::fmt::format::Writer<'_>,
+        ) -> std::fmt::Result {
+            w.write_str("FIXED")
+        }
+    }
+
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone())
+        .with_timer(FixedTime)
+        .with_timestamp_last(true)
+        .flatten_event(true)
+        .with_target(false);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!(n = 1, "flat");
+    });
+    assert_eq!(
+        w.output(),
+        "{\"level\":\"INFO\",\"message\":\"flat\",\"n\":1,\"timestamp\":\"FIXED\"}\n"
+    );
+}
+
+#[test]
+fn test_timestamp_last_without_time() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone())
+        .with_timestamp_last(true)
+        .without_time();
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!("no time");
+    });
+    let v = parse_line(w.output().trim());
+    assert!(v.get("timestamp").is_none());
+    assert_eq!(v["fields"]["message"], "no time");
+}
+
+#[test]
+fn test_with_clock() {
+    use std::time::{Duration, SystemTime};
+
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone())
+        .with_clock(|| SystemTime::UNIX_EPOCH + Duration::from_micros(1_771_588_800_123_456));
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!("first");
+        tracing::info!("second");
+    });
+    let out = w.output();
+    for line in out.lines() {
+        assert_eq!(parse_line(line)["timestamp"], "2026-02-20T12:00:00.123456Z");
+    }
+    assert_eq!(out.lines().count(), 2);
+}
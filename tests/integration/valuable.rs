@@ -0,0 +1,183 @@
+use super::common::{TestWriter, parse_line};
+use tracing_microjson::JsonLayer;
+use tracing_subscriber::prelude::*;
+use valuable::{
+    EnumDef, Enumerable, Fields, NamedField, NamedValues, StructDef, Structable, Valuable, Value,
+    Variant, VariantDef, Visit,
+};
+
+// Implemented by hand, as `#[derive(Valuable)]` would, to avoid depending on
+// valuable-derive
+
+struct Address {
+    city: String,
+    zip: u32,
+}
+
+static ADDRESS_FIELDS: &[NamedField<'static>] = &[NamedField::new("city"), NamedField::new("zip")];
+
+impl Valuable for Address {
+    fn as_value(&self) -> Value<'_> {
+        Value::Structable(self)
+    }
+
+    fn visit(&self, visit: &mut dyn Visit) {
+        visit.visit_named_fields(&NamedValues::new(
+            ADDRESS_FIELDS,
+            &[self.city.as_value(), self.zip.as_value()],
+        ));
+    }
+}
+
+impl Structable for Address {
+    fn definition(&self) -> StructDef<'_> {
+        StructDef::new_static("Address", Fields::Named(ADDRESS_FIELDS))
+    }
+}
+
+enum Role {
+    Admin,
+    Guest { until: u64 },
+}
+
+static ROLE_VARIANTS: &[VariantDef<'static>] = &[
+    VariantDef::new("Admin", Fields::Unnamed(0)),
+    VariantDef::new("Guest", Fields::Named(GUEST_FIELDS)),
+];
+
+static GUEST_FIELDS: &[NamedField<'static>] = &[NamedField::new("until")];
+
+impl Valuable for Role {
+    fn as_value(&self) -> Value<'_> {
+        Value::Enumerable(self)
+    }
+
+    fn visit(&self, visit: &mut dyn Visit) {
+        match self {
+            Role::Admin => visit.visit_unnamed_fields(&[]),
+            Role::Guest { until } => {
+                visit.visit_named_fields(&NamedValues::new(GUEST_FIELDS, &[until.as_value()]))
+            }
+        }
+    }
+}
+
+impl Enumerable for Role {
+    fn definition(&self) -> EnumDef<'_> {
+        EnumDef::new_static("Role", ROLE_VARIANTS)
+    }
+
+    fn variant(&self) -> Variant<'_> {
+        match self {
+            Role::Admin => Variant::Static(&ROLE_VARIANTS[0]),
+            Role::Guest { .. } => Variant::Static(&ROLE_VARIANTS[1]),
+        }
+    }
+}
+
+struct User {
+    name: &'static str,
+    address: Address,
+    roles: Vec<Role>,
+}
+
+static USER_FIELDS: &[NamedField<'static>] = &[
+    NamedField::new("name"),
+    NamedField::new("address"),
+    NamedField::new("roles"),
+];
+
+impl Valuable for User {
+    fn as_value(&self) -> Value<'_> {
+        Value::Structable(self)
+    }
+
+    fn visit(&self, visit: &mut dyn Visit) {
+        visit.visit_named_fields(&NamedValues::new(
+            USER_FIELDS,
+            &[
+                self.name.as_value(),
+                self.address.as_value(),
+                self.roles.as_value(),
+            ],
+        ));
+    }
+}
+
+impl Structable for User {
+    fn definition(&self) -> StructDef<'_> {
+        StructDef::new_static("User", Fields::Named(USER_FIELDS))
+    }
+}
+
+#[test]
+fn test_valuable_nested_struct() {
+    let user = User {
+        name: "alice",
+        address: Address {
+            city: "Zürich \"ZH\"".to_owned(),
+            zip: 8001,
+        },
+        roles: vec![Role::Admin, Role::Guest { until: 1700000000 }],
+    };
+
+    let w = TestWriter::new();
+    let subscriber = tracing_subscriber::registry().with(JsonLayer::new(w.clone()));
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!(user = tracing::field::valuable(&user), "login");
+    });
+
+    let v = parse_line(w.output().trim());
+    assert_eq!(
+        v["fields"]["user"],
+        serde_json::json!({
+            "name": "alice",
+            "address": {"city": "Zürich \"ZH\"", "zip": 8001},
+            "roles": ["Admin", {"Guest": {"until": 1700000000u64}}],
+        })
+    );
+    assert_eq!(v["fields"]["message"], "login");
+}
+
+#[test]
+fn test_valuable_collections() {
+    let mut scores = std::collections::BTreeMap::new();
+    scores.insert("a", vec![1.5, 2.0]);
+    scores.insert("b", vec![]);
+
+    let w = TestWriter::new();
+    let subscriber = tracing_subscriber::registry().with(JsonLayer::new(w.clone()));
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!(
+            scores = tracing::field::valuable(&scores),
+            pair = tracing::field::valuable(&(1u8, "x")),
+            unit = tracing::field::valuable(&()),
+        );
+    });
+
+    let v = parse_line(w.output().trim());
+    assert_eq!(
+        v["fields"]["scores"],
+        serde_json::json!({"a": [1.5, 2.0], "b": []})
+    );
+    assert_eq!(v["fields"]["pair"], serde_json::json!([1, "x"]));
+    assert!(v["fields"]["unit"].is_null());
+}
+
+#[test]
+fn test_valuable_redacted() {
+    let address = Address {
+        city: "Bern".to_owned(),
+        zip: 3000,
+    };
+
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone()).with_redacted_fields(["address"]);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!(address = tracing::field::valuable(&address));
+    });
+
+    let v = parse_line(w.output().trim());
+    assert_eq!(v["fields"]["address"], "[REDACTED]");
+}
@@ -0,0 +1,561 @@
+use super::common::{TestWriter, parse_line};
+use std::sync::{Arc, Mutex};
+use tracing_microjson::{BufferedMakeWriter, JsonLayer, LayerMetrics, LineEnding};
+use tracing_subscriber::prelude::*;
+
+#[test]
+fn test_from_shared_writer() {
+    let buf = Arc::new(Mutex::new(Vec::<u8>::new()));
+    let layer = JsonLayer::from_writer(Arc::clone(&buf));
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!(n = 1u64, "first");
+        tracing::info!(n = 2u64, "second");
+    });
+    let out = String::from_utf8(buf.lock().unwrap().clone()).unwrap();
+    let lines: Vec<_> = out.lines().map(parse_line).collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0]["fields"]["n"], 1);
+    assert_eq!(lines[1]["fields"]["message"], "second");
+}
+
+#[test]
+fn test_from_owned_writer() {
+    // Any `Write + Send + 'static` is accepted and wrapped in a mutex
+    let w = TestWriter::new();
+    let layer = JsonLayer::from_writer(w.clone());
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!("owned");
+    });
+    let v = parse_line(w.output().trim());
+    assert_eq!(v["fields"]["message"], "owned");
+}
+
+#[test]
+fn test_with_writer() {
+    let layer = JsonLayer::new(std::io::stderr)
+        .without_time()
+        .flatten_event(true)
+        .with_target(false);
+
+    let w = TestWriter::new();
+    let subscriber = tracing_subscriber::registry().with(layer.with_writer(w.clone()));
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!(n = 1, "swapped");
+    });
+    assert_eq!(
+        w.output(),
+        "{\"level\":\"INFO\",\"message\":\"swapped\",\"n\":1}\n"
+    );
+}
+
+#[test]
+fn test_make_writer_for_routes_by_level() {
+    use tracing::Level;
+    use tracing_subscriber::fmt::MakeWriter;
+
+    /// Sends WARN and ERROR to `errors`, everything else to `others`.
+    #[derive(Clone, Default)]
+    struct LevelSplit {
+        errors: TestWriter,
+        others: TestWriter,
+    }
+
+    impl<'a> MakeWriter<'a> for LevelSplit {
+        type Writer = TestWriter;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.others.clone()
+        }
+
+        fn make_writer_for(&'a self, meta: &tracing::Metadata<'_>) -> Self::Writer {
+            if *meta.level() <= Level::WARN {
+                self.errors.clone()
+            } else {
+                self.others.clone()
+            }
+        }
+    }
+
+    let split = LevelSplit::default();
+    let subscriber = tracing_subscriber::registry().with(JsonLayer::new(split.clone()));
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!("info");
+        tracing::warn!("warn");
+        tracing::error!("error");
+        tracing::debug!("debug");
+    });
+
+    let levels = |w: &TestWriter| -> Vec<String> {
+        w.output()
+            .lines()
+            .map(|l| parse_line(l)["level"].as_str().unwrap().to_owned())
+            .collect()
+    };
+    assert_eq!(levels(&split.errors), ["WARN", "ERROR"]);
+    assert_eq!(levels(&split.others), ["INFO", "DEBUG"]);
+}
+
+#[test]
+fn test_single_threaded_writer() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::single_threaded(w.clone());
+    let dispatch = tracing::Dispatch::new(tracing_subscriber::registry().with(layer));
+    tracing::dispatcher::with_default(&dispatch, || {
+        tracing::info!("owner thread");
+    });
+
+    // Events from another thread are discarded rather than racing the writer
+    let other = dispatch.clone();
+    std::thread::spawn(move || {
+        tracing::dispatcher::with_default(&other, || {
+            tracing::info!("other thread");
+        });
+    })
+    .join()
+    .unwrap();
+
+    tracing::dispatcher::with_default(&dispatch, || {
+        tracing::info!("owner again");
+    });
+
+    let out = w.output();
+    let messages: Vec<_> = out
+        .lines()
+        .map(|l| {
+            parse_line(l)["fields"]["message"]
+                .as_str()
+                .unwrap()
+                .to_owned()
+        })
+        .collect();
+    assert_eq!(messages, ["owner thread", "owner again"]);
+
+    // ...but they are counted as dropped
+    let layer = dispatch
+        .downcast_ref::<JsonLayer<tracing_microjson::SingleThreadWriter<TestWriter>>>()
+        .unwrap();
+    assert_eq!(layer.dropped_lines(), 1);
+}
+
+#[test]
+fn test_array_mode() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone()).without_time().array_mode(true);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!("one");
+        tracing::warn!("two");
+    });
+    let out = w.output();
+    assert!(out.starts_with("[{"), "got: {out}");
+    assert_eq!(out.lines().count(), 3);
+    let v: serde_json::Value = serde_json::from_str(&out).expect("a single JSON array");
+    let events = v.as_array().unwrap();
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0]["fields"]["message"], "one");
+    assert_eq!(events[1]["level"], "WARN");
+}
+
+#[test]
+fn test_array_mode_after_failed_open() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    /// A writer whose first write fails.
+    #[derive(Clone)]
+    struct FailOnce {
+        inner: TestWriter,
+        failed: Arc<AtomicBool>,
+    }
+
+    impl std::io::Write for FailOnce {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if !self.failed.swap(true, Ordering::Relaxed) {
+                return Err(std::io::Error::other("disk full"));
+            }
+            self.inner.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> tracing_subscriber::fmt::MakeWriter<'a> for FailOnce {
+        type Writer = FailOnce;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    let w = TestWriter::new();
+    let writer = FailOnce {
+        inner: w.clone(),
+        failed: Arc::new(AtomicBool::new(false)),
+    };
+    let layer = JsonLayer::new(writer).without_time().array_mode(true);
+    let counter = layer.clone();
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!("lost");
+        tracing::info!("kept");
+    });
+    assert_eq!(counter.dropped_lines(), 1);
+    drop(counter);
+
+    // The `[` is written again with the next event
+    let out = w.output();
+    assert!(out.starts_with("[{"), "got: {out}");
+    let v: serde_json::Value = serde_json::from_str(&out).expect("a single JSON array");
+    let events = v.as_array().unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0]["fields"]["message"], "kept");
+}
+
+#[test]
+fn test_array_mode_after_failed_line() {
+    /// A writer that fails to write anything mentioning "lost".
+    #[derive(Clone)]
+    struct FailLost(TestWriter);
+
+    impl std::io::Write for FailLost {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if buf.windows(4).any(|w| w == b"lost") {
+                return Err(std::io::Error::other("disk full"));
+            }
+            self.0.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> tracing_subscriber::fmt::MakeWriter<'a> for FailLost {
+        type Writer = FailLost;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(FailLost(w.clone()))
+        .without_time()
+        .array_mode(true);
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!("one");
+        tracing::info!("lost");
+        tracing::info!("two");
+    });
+
+    // No separator is left behind by the failed line
+    let out = w.output();
+    let v: serde_json::Value = serde_json::from_str(&out).expect("a single JSON array");
+    let events = v.as_array().unwrap();
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[1]["fields"]["message"], "two");
+}
+
+#[test]
+fn test_array_mode_then_with_writer() {
+    let old = TestWriter::new();
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(old.clone())
+        .without_time()
+        .array_mode(true)
+        .with_writer(w.clone());
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!("one");
+        tracing::info!("two");
+    });
+    assert_eq!(old.output(), "");
+    let v: serde_json::Value = serde_json::from_str(&w.output()).expect("a single JSON array");
+    assert_eq!(v.as_array().unwrap().len(), 2);
+}
+
+#[test]
+fn test_array_mode_without_events() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone()).array_mode(true);
+    drop(tracing_subscriber::registry().with(layer));
+    assert_eq!(w.output(), "[]\n");
+}
+
+#[test]
+fn test_flush() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Counts calls to `flush`.
+    #[derive(Clone, Default)]
+    struct FlushCounter(Arc<AtomicUsize>);
+
+    impl std::io::Write for FlushCounter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        }
+    }
+
+    impl<'a> tracing_subscriber::fmt::MakeWriter<'a> for FlushCounter {
+        type Writer = FlushCounter;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    for (flush, expected) in [(true, 2), (false, 0)] {
+        let w = FlushCounter::default();
+        let layer = JsonLayer::new(w.clone()).with_flush(flush);
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!("first");
+            tracing::info!("second");
+        });
+        assert_eq!(w.0.load(Ordering::Relaxed), expected, "with_flush({flush})");
+    }
+}
+
+#[test]
+fn test_write_error_handler() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// A writer whose every write fails.
+    #[derive(Clone)]
+    struct Failing;
+
+    impl std::io::Write for Failing {
+        fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::other("disk full"))
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> tracing_subscriber::fmt::MakeWriter<'a> for Failing {
+        type Writer = Failing;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            Failing
+        }
+    }
+
+    let calls = Arc::new(AtomicUsize::new(0));
+    let errors = Arc::new(Mutex::new(Vec::new()));
+    let layer = JsonLayer::new(Failing).with_write_error_handler({
+        let calls = Arc::clone(&calls);
+        let errors = Arc::clone(&errors);
+        move |err| {
+            calls.fetch_add(1, Ordering::Relaxed);
+            errors.lock().unwrap().push(err.to_string());
+        }
+    });
+    let counter = layer.clone();
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!("first");
+        tracing::warn!("second");
+    });
+    assert_eq!(calls.load(Ordering::Relaxed), 2);
+    assert_eq!(*errors.lock().unwrap(), ["disk full", "disk full"]);
+    assert_eq!(counter.dropped_lines(), 2);
+}
+
+#[test]
+fn test_dropped_lines_without_errors() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone());
+    let counter = layer.clone();
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!("written");
+    });
+    assert_eq!(w.output().lines().count(), 1);
+    assert_eq!(counter.dropped_lines(), 0);
+}
+
+#[test]
+fn test_metrics() {
+    let metrics = Arc::new(LayerMetrics::new());
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(w.clone()).with_metrics(metrics.clone());
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::error!("one");
+        tracing::warn!("two");
+        tracing::warn!("three");
+        tracing::info!("four");
+        tracing::debug!("five");
+    });
+
+    let snapshot = metrics.snapshot();
+    assert_eq!((snapshot.trace, snapshot.debug, snapshot.info), (0, 1, 1));
+    assert_eq!((snapshot.warn, snapshot.error), (2, 1));
+    assert_eq!(snapshot.events(), 5);
+    assert_eq!(snapshot.bytes, w.output().len() as u64);
+}
+
+#[test]
+fn test_line_ending() {
+    for (ending, expected) in [
+        (LineEnding::Lf, &b"}\n{"[..]),
+        (LineEnding::CrLf, b"}\r\n{"),
+        (LineEnding::None, b"}{"),
+    ] {
+        let w = TestWriter::new();
+        let layer = JsonLayer::new(w.clone())
+            .without_time()
+            .with_line_ending(ending)
+            .with_newline_safety(true);
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!("first");
+            tracing::info!("second");
+        });
+        let out = w.bytes();
+        assert!(
+            out.windows(expected.len()).any(|chunk| chunk == expected),
+            "{ending:?}: {}",
+            String::from_utf8_lossy(&out)
+        );
+        let last = &expected[..expected.len() - 1];
+        assert!(
+            out.ends_with(last),
+            "{ending:?}: {}",
+            String::from_utf8_lossy(&out)
+        );
+    }
+}
+
+#[test]
+fn test_buffered_make_writer() {
+    // Each write the inner writer receives, as one element
+    #[derive(Clone, Default)]
+    struct Writes(Arc<Mutex<Vec<Vec<u8>>>>);
+
+    impl std::io::Write for Writes {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().push(buf.to_vec());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let writes = Writes::default();
+    let layer = JsonLayer::new(BufferedMakeWriter::new(writes.clone(), 8192)).without_time();
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!("first");
+        tracing::info!("second");
+        // Both lines fit in the buffer, so nothing has been written yet
+        assert!(writes.0.lock().unwrap().is_empty());
+    });
+
+    // The subscriber, and with it the writer, is dropped on return
+    let written = writes.0.lock().unwrap().clone();
+    assert_eq!(written.len(), 1, "flushed once on drop");
+    let out = String::from_utf8(written.concat()).unwrap();
+    let lines: Vec<_> = out.lines().map(parse_line).collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[1]["fields"]["message"], "second");
+}
+
+#[test]
+fn test_buffered_make_writer_writes_when_full() {
+    let w = TestWriter::new();
+    let layer = JsonLayer::new(BufferedMakeWriter::new(w.clone(), 100)).without_time();
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!("one");
+        tracing::info!("two");
+        tracing::info!("three");
+        // The second line takes the buffer past 100 bytes
+        assert_eq!(w.output().lines().count(), 2);
+    });
+    assert_eq!(w.output().lines().count(), 3);
+}
+
+#[test]
+fn test_buffered_make_writer_max_lines() {
+    let w = TestWriter::new();
+    let writer = BufferedMakeWriter::new(w.clone(), 8192).with_max_lines(2);
+    let subscriber = tracing_subscriber::registry().with(JsonLayer::new(writer));
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!("one");
+        assert_eq!(w.output(), "");
+        tracing::info!("two");
+        assert_eq!(w.output().lines().count(), 2);
+        tracing::info!("three");
+        assert_eq!(w.output().lines().count(), 2);
+    });
+    assert_eq!(w.output().lines().count(), 3);
+}
+
+#[test]
+fn test_buffered_make_writer_keeps_lines_on_error() {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    /// Fails every write while `failing` is set.
+    #[derive(Clone)]
+    struct Flaky {
+        inner: TestWriter,
+        failing: Arc<AtomicBool>,
+    }
+
+    impl std::io::Write for Flaky {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if self.failing.load(Ordering::Relaxed) {
+                return Err(std::io::Error::other("disk full"));
+            }
+            self.inner.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let w = TestWriter::new();
+    let failing = Arc::new(AtomicBool::new(true));
+    let writer = Flaky {
+        inner: w.clone(),
+        failing: failing.clone(),
+    };
+    let errors = Arc::new(AtomicUsize::new(0));
+    let counter = errors.clone();
+    let layer = JsonLayer::new(BufferedMakeWriter::new(writer, 1))
+        .without_time()
+        .with_write_error_handler(move |_| {
+            counter.fetch_add(1, Ordering::Relaxed);
+        });
+    let subscriber = tracing_subscriber::registry().with(layer);
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info!("one");
+        tracing::info!("two");
+        failing.store(false, Ordering::Relaxed);
+        tracing::info!("three");
+    });
+    assert_eq!(errors.load(Ordering::Relaxed), 2);
+
+    // The lines that failed are written with the next batch
+    let messages: Vec<_> = w
+        .output()
+        .lines()
+        .map(|line| parse_line(line)["fields"]["message"].clone())
+        .collect();
+    assert_eq!(messages, ["one", "two", "three"]);
+}